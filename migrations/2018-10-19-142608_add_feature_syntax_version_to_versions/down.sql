@@ -0,0 +1,2 @@
+ALTER TABLE versions
+DROP COLUMN feature_syntax_version;
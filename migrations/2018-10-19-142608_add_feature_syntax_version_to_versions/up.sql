@@ -0,0 +1,2 @@
+ALTER TABLE versions
+ADD COLUMN feature_syntax_version INTEGER NOT NULL DEFAULT 1;
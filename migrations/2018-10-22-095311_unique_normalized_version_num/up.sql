@@ -0,0 +1,28 @@
+-- Build metadata is ignored when comparing versions, so `1.0.0+foo` and
+-- `1.0.0` must not both be published for the same crate.
+--
+-- Versions that were published before this was enforced can't just be
+-- deleted, so list them and stop if there are any. They have to be resolved
+-- by hand before this migration can run.
+DO $$
+DECLARE
+    duplicates TEXT;
+BEGIN
+    SELECT string_agg(crates.name || ' ' || dupes.nums, ', ' ORDER BY crates.name)
+    INTO duplicates
+    FROM (
+        SELECT crate_id, string_agg(num, ' and ' ORDER BY num) AS nums
+        FROM versions
+        GROUP BY crate_id, split_part(num, '+', 1)
+        HAVING COUNT(*) > 1
+    ) dupes
+    INNER JOIN crates ON crates.id = dupes.crate_id;
+
+    IF duplicates IS NOT NULL THEN
+        RAISE EXCEPTION 'versions differing only in build metadata must be resolved first: %',
+            duplicates;
+    END IF;
+END
+$$;
+
+CREATE UNIQUE INDEX versions_crate_id_normalized_num ON versions (crate_id, split_part(num, '+', 1));
@@ -0,0 +1,2 @@
+ALTER TABLE versions
+ADD COLUMN checksum VARCHAR;
@@ -0,0 +1 @@
+ALTER TABLE crates ADD COLUMN custom_metadata JSONB NOT NULL DEFAULT '{}';
@@ -0,0 +1,6 @@
+CREATE TABLE recent_publishes (
+  id SERIAL PRIMARY KEY,
+  user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+  created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX recent_publishes_user_id_created_at ON recent_publishes (user_id, created_at);
@@ -0,0 +1,9 @@
+CREATE TABLE recent_crate_changes (
+  id SERIAL PRIMARY KEY,
+  user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+  crate_id INTEGER NOT NULL REFERENCES crates (id) ON DELETE CASCADE,
+  operation VARCHAR NOT NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX recent_crate_changes_user_crate_operation
+  ON recent_crate_changes (user_id, crate_id, operation, created_at);
@@ -0,0 +1,6 @@
+CREATE TABLE version_changelogs (
+  version_id INTEGER NOT NULL PRIMARY KEY REFERENCES versions (id) ON DELETE CASCADE,
+  changelog TEXT NOT NULL,
+  excerpt TEXT NOT NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
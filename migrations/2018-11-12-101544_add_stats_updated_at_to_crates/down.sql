@@ -0,0 +1,3 @@
+DROP TRIGGER trigger_crates_update_stats_updated_at ON crates;
+DROP FUNCTION set_stats_updated_at();
+ALTER TABLE crates DROP COLUMN stats_updated_at;
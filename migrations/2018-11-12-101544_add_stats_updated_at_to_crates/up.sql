@@ -0,0 +1,17 @@
+ALTER TABLE crates ADD COLUMN stats_updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP;
+CREATE INDEX crates_stats_updated_at_idx ON crates (stats_updated_at);
+
+CREATE FUNCTION set_stats_updated_at() RETURNS trigger AS $$
+BEGIN
+    IF NEW.downloads IS DISTINCT FROM OLD.downloads THEN
+        NEW.stats_updated_at = CURRENT_TIMESTAMP;
+    END IF;
+    RETURN NEW;
+END
+$$ LANGUAGE plpgsql;
+
+-- Triggers run in order of name, so this one runs after
+-- trigger_crates_set_updated_at and doesn't make it bump updated_at.
+CREATE TRIGGER trigger_crates_update_stats_updated_at BEFORE UPDATE
+ON crates
+FOR EACH ROW EXECUTE PROCEDURE set_stats_updated_at();
@@ -0,0 +1 @@
+ALTER TABLE follows ADD COLUMN prereleases BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1 @@
+ALTER TABLE versions ADD COLUMN published_by INTEGER REFERENCES users (id);
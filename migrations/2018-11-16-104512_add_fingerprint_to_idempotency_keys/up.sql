@@ -0,0 +1,4 @@
+-- Responses recorded before fingerprints existed can't be matched to a
+-- request, so they aren't replayed
+DELETE FROM idempotency_keys;
+ALTER TABLE idempotency_keys ADD COLUMN fingerprint VARCHAR NOT NULL;
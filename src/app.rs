@@ -8,9 +8,11 @@ use std::time::Duration;
 use diesel::r2d2;
 use git2;
 use oauth2;
+use regex::Regex;
 use reqwest;
 use scheduled_thread_pool::ScheduledThreadPool;
 
+use models::{PendingDownloads, VersionIdCache};
 use util::CargoResult;
 use {db, Config, Env};
 
@@ -36,6 +38,16 @@ pub struct App {
 
     /// The server configuration
     pub config: Config,
+
+    /// The compiled `Config::crate_name_pattern`, anchored so that it must
+    /// match the whole name
+    pub crate_name_pattern: Option<Regex>,
+
+    /// Downloads waiting to be written when `Config::buffer_downloads` is set
+    pub pending_downloads: PendingDownloads,
+
+    /// The ids of recently downloaded versions
+    pub version_ids: VersionIdCache,
 }
 
 impl App {
@@ -90,6 +102,10 @@ impl App {
 
         let repo = git2::Repository::open(&config.git_repo_checkout).unwrap();
 
+        let crate_name_pattern = config.crate_name_pattern.as_ref().map(|pattern| {
+            Regex::new(&format!("^(?:{})$", pattern)).expect("couldn't parse CRATE_NAME_PATTERN")
+        });
+
         App {
             diesel_database: db::diesel_pool(&config.db_url, diesel_db_config),
             github,
@@ -97,6 +113,9 @@ impl App {
             git_repo: Mutex::new(repo),
             git_repo_checkout: config.git_repo_checkout.clone(),
             config: config.clone(),
+            crate_name_pattern,
+            pending_downloads: PendingDownloads::default(),
+            version_ids: VersionIdCache::new(config.download_cache_size),
         }
     }
 
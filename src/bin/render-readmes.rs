@@ -150,6 +150,8 @@ fn main() {
                         &readme_path,
                         readme.into_bytes(),
                         "text/html",
+                        config.s3_upload_attempts,
+                        config.s3_upload_backoff_ms,
                     ).unwrap_or_else(|_| {
                         panic!(
                             "[{}-{}] Couldn't upload file to S3",
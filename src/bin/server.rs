@@ -11,6 +11,8 @@ use std::env;
 use std::fs::{self, File};
 use std::sync::mpsc::channel;
 use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
 
 fn main() {
     // Initialize logging
@@ -44,8 +46,42 @@ fn main() {
     cfg.set_str("user.name", "bors").unwrap();
     cfg.set_str("user.email", "bors@rust-lang.org").unwrap();
 
-    let app = cargo_registry::App::new(&config);
-    let app = cargo_registry::build_handler(Arc::new(app));
+    let app = Arc::new(cargo_registry::App::new(&config));
+
+    // Index entries that were queued while the index was unavailable are
+    // retried every minute
+    if config.queue_index_writes {
+        let app = Arc::clone(&app);
+        thread::spawn(move || loop {
+            thread::sleep(Duration::from_secs(60));
+            if let Err(e) = cargo_registry::git::write_pending_entries(&app) {
+                println!("failed to write pending index entries: {}", e);
+            }
+        });
+    }
+
+    // Buffered downloads are written every few seconds, so that a burst of
+    // downloads of one version only updates its row once
+    if config.buffer_downloads {
+        let app = Arc::clone(&app);
+        thread::spawn(move || loop {
+            thread::sleep(Duration::from_secs(10));
+            let flushed = app
+                .diesel_database
+                .get()
+                .map_err(|e| e.to_string())
+                .and_then(|conn| {
+                    app.pending_downloads
+                        .flush(&conn, &app.version_ids)
+                        .map_err(|e| e.to_string())
+                });
+            if let Err(e) = flushed {
+                println!("failed to write buffered downloads: {}", e);
+            }
+        });
+    }
+
+    let app = cargo_registry::build_handler(app);
 
     // On every server restart, ensure the categories available in the database match
     // the information in *src/categories.toml*.
@@ -145,6 +145,7 @@ mod test {
             None,
             None,
             0,
+            None,
         ).unwrap();
         let version = version.save(&conn, &[]).unwrap();
         (krate, version)
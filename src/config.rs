@@ -18,6 +18,24 @@ pub struct Config {
     pub max_unpack_size: u64,
     pub mirror: Replica,
     pub api_protocol: String,
+    pub feature_syntax_version: i32,
+    pub new_crate_window_days: i64,
+    pub admin_gh_ids: Vec<i32>,
+    pub count_downloads: bool,
+    pub require_auth_for_reads: bool,
+    pub publish_rate_limit: Option<i64>,
+    pub publish_rate_window_minutes: i64,
+    pub queue_index_writes: bool,
+    pub crate_change_rate_limit: Option<i64>,
+    pub crate_change_rate_window_minutes: i64,
+    pub reject_dependency_cycles: bool,
+    pub signed_download_expiry_secs: Option<i64>,
+    pub s3_upload_attempts: u32,
+    pub s3_upload_backoff_ms: u64,
+    pub crate_name_pattern: Option<String>,
+    pub publish_size_warning_threshold: Option<u64>,
+    pub buffer_downloads: bool,
+    pub download_cache_size: usize,
 }
 
 impl Default for Config {
@@ -27,6 +45,19 @@ impl Default for Config {
     ///
     /// - `Config::max_upload_size`: 10MiB
     /// - `Config::api_protocol`: `https`
+    /// - `Config::feature_syntax_version`: 2 (namespaced and weak dependency features)
+    /// - `Config::new_crate_window_days`: 14, crates created within this many days are
+    /// flagged as new in crate listings
+    /// - `Config::count_downloads`: true
+    /// - `Config::require_auth_for_reads`: false
+    /// - `Config::publish_rate_window_minutes`: 60
+    /// - `Config::queue_index_writes`: false
+    /// - `Config::buffer_downloads`: false
+    /// - `Config::download_cache_size`: 10000, set to 0 to always look up downloaded versions
+    /// - `Config::crate_change_rate_window_minutes`: 60
+    /// - `Config::reject_dependency_cycles`: false
+    /// - `Config::s3_upload_attempts`: 3
+    /// - `Config::s3_upload_backoff_ms`: 500, doubled after each failed attempt
     ///
     /// Pulls values from the following environment variables:
     ///
@@ -42,6 +73,39 @@ impl Default for Config {
     /// - `GH_CLIENT_ID`: The client ID of the associated GitHub application.
     /// - `GH_CLIENT_SECRET`: The client secret of the associated GitHub application.
     /// - `DATABASE_URL`: The URL of the postgres database to use.
+    /// - `FEATURE_SYNTAX_VERSION`: The newest feature syntax accepted at publish time. Set this
+    /// to 1 if the registry has to be usable by versions of Cargo that predate `features2`.
+    /// - `ADMIN_GH_IDS`: A comma separated list of the GitHub IDs of the users allowed to use the
+    /// `/admin` routes.
+    /// - `DISABLE_DOWNLOAD_COUNTING`: If present, downloads are redirected without being recorded
+    /// in the database.
+    /// - `REQUIRE_AUTH_FOR_READS`: If present, listing, showing and downloading crates requires
+    /// being logged in, for registries that aren't meant to be public.
+    /// - `PUBLISH_RATE_LIMIT`: The most versions a user can publish within
+    /// `Config::publish_rate_window_minutes`. Publishing isn't rate limited if this isn't set.
+    /// - `PUBLISH_RATE_WINDOW_MINUTES`: Overrides `Config::publish_rate_window_minutes`.
+    /// - `QUEUE_INDEX_WRITES`: If present, a publish that can't update the index because it is
+    /// unavailable is accepted anyway, and its index entry is written in the background later.
+    /// - `CRATE_CHANGE_RATE_LIMIT`: The most times a user can update the metadata of a crate,
+    /// yank or unyank its versions, or change its owners within
+    /// `Config::crate_change_rate_window_minutes`. Each kind of change is counted separately.
+    /// These changes aren't rate limited if this isn't set.
+    /// - `CRATE_CHANGE_RATE_WINDOW_MINUTES`: Overrides `Config::crate_change_rate_window_minutes`.
+    /// - `REJECT_DEPENDENCY_CYCLES`: If present, publishing a version whose dependencies depend
+    /// back on it is an error rather than a warning.
+    /// - `SIGNED_DOWNLOAD_EXPIRY_SECS`: If present, downloads are redirected to presigned S3 URLs
+    /// that expire after this many seconds instead of to the public location of the crate, so
+    /// that crates can be served from a private bucket.
+    /// - `S3_UPLOAD_ATTEMPTS`: Overrides `Config::s3_upload_attempts`.
+    /// - `S3_UPLOAD_BACKOFF_MS`: Overrides `Config::s3_upload_backoff_ms`.
+    /// - `CRATE_NAME_PATTERN`: If present, the names of new crates must match this regular
+    /// expression in full. Crates that already exist can still get new versions.
+    /// - `PUBLISH_SIZE_WARNING_THRESHOLD`: If present, publishing a crate larger than this many
+    /// bytes succeeds with a warning, as large crates often include files by accident.
+    /// - `BUFFER_DOWNLOADS`: If present, downloads are counted in memory and written to the
+    /// database every few seconds rather than on each request. Downloads counted when the server
+    /// stops are lost.
+    /// - `DOWNLOAD_CACHE_SIZE`: Overrides `Config::download_cache_size`.
     fn default() -> Config {
         let checkout = PathBuf::from(env("GIT_REPO_CHECKOUT"));
         let api_protocol = String::from("https");
@@ -51,6 +115,49 @@ impl Default for Config {
             Replica::Primary
         };
         let heroku = env::var("HEROKU").is_ok();
+        let feature_syntax_version = match env::var("FEATURE_SYNTAX_VERSION") {
+            Ok(v) => v.parse().expect("couldn't parse FEATURE_SYNTAX_VERSION"),
+            Err(_) => 2,
+        };
+        let admin_gh_ids = env::var("ADMIN_GH_IDS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|id| id.trim())
+            .filter(|id| !id.is_empty())
+            .map(|id| id.parse().expect("couldn't parse ADMIN_GH_IDS"))
+            .collect();
+        let publish_rate_limit = env::var("PUBLISH_RATE_LIMIT")
+            .ok()
+            .map(|v| v.parse().expect("couldn't parse PUBLISH_RATE_LIMIT"));
+        let publish_rate_window_minutes = match env::var("PUBLISH_RATE_WINDOW_MINUTES") {
+            Ok(v) => v.parse().expect("couldn't parse PUBLISH_RATE_WINDOW_MINUTES"),
+            Err(_) => 60,
+        };
+        let crate_change_rate_limit = env::var("CRATE_CHANGE_RATE_LIMIT")
+            .ok()
+            .map(|v| v.parse().expect("couldn't parse CRATE_CHANGE_RATE_LIMIT"));
+        let crate_change_rate_window_minutes = match env::var("CRATE_CHANGE_RATE_WINDOW_MINUTES") {
+            Ok(v) => v.parse().expect("couldn't parse CRATE_CHANGE_RATE_WINDOW_MINUTES"),
+            Err(_) => 60,
+        };
+        let signed_download_expiry_secs = env::var("SIGNED_DOWNLOAD_EXPIRY_SECS")
+            .ok()
+            .map(|v| v.parse().expect("couldn't parse SIGNED_DOWNLOAD_EXPIRY_SECS"));
+        let publish_size_warning_threshold = env::var("PUBLISH_SIZE_WARNING_THRESHOLD")
+            .ok()
+            .map(|v| v.parse().expect("couldn't parse PUBLISH_SIZE_WARNING_THRESHOLD"));
+        let s3_upload_attempts = match env::var("S3_UPLOAD_ATTEMPTS") {
+            Ok(v) => v.parse().expect("couldn't parse S3_UPLOAD_ATTEMPTS"),
+            Err(_) => 3,
+        };
+        let s3_upload_backoff_ms = match env::var("S3_UPLOAD_BACKOFF_MS") {
+            Ok(v) => v.parse().expect("couldn't parse S3_UPLOAD_BACKOFF_MS"),
+            Err(_) => 500,
+        };
+        let download_cache_size = match env::var("DOWNLOAD_CACHE_SIZE") {
+            Ok(v) => v.parse().expect("couldn't parse DOWNLOAD_CACHE_SIZE"),
+            Err(_) => 10_000,
+        };
         let cargo_env = if heroku {
             Env::Production
         } else {
@@ -136,6 +243,24 @@ impl Default for Config {
             max_unpack_size: 512 * 1024 * 1024, // 512 MB max when decompressed
             mirror,
             api_protocol,
+            feature_syntax_version,
+            new_crate_window_days: 14,
+            admin_gh_ids,
+            count_downloads: env::var("DISABLE_DOWNLOAD_COUNTING").is_err(),
+            require_auth_for_reads: env::var("REQUIRE_AUTH_FOR_READS").is_ok(),
+            publish_rate_limit,
+            publish_rate_window_minutes,
+            queue_index_writes: env::var("QUEUE_INDEX_WRITES").is_ok(),
+            crate_change_rate_limit,
+            crate_change_rate_window_minutes,
+            reject_dependency_cycles: env::var("REJECT_DEPENDENCY_CYCLES").is_ok(),
+            signed_download_expiry_secs,
+            s3_upload_attempts,
+            s3_upload_backoff_ms,
+            crate_name_pattern: env::var("CRATE_NAME_PATTERN").ok(),
+            publish_size_warning_threshold,
+            buffer_downloads: env::var("BUFFER_DOWNLOADS").is_ok(),
+            download_cache_size,
         }
     }
 }
@@ -0,0 +1,338 @@
+//! Diagnostic endpoints for registry operators
+//!
+//! All of these routes require the current user to be listed in
+//! `Config::admin_gh_ids`.
+
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Read};
+use std::sync::Arc;
+
+use chrono::{Duration, Utc};
+use hex;
+use serde_json;
+
+use app::App;
+use controllers::prelude::*;
+use git;
+use models::DependencyKind;
+use schema::*;
+use uploaders::{self, Uploader};
+use util::{bad_request, forbidden};
+
+/// The number of crates loaded from the database at once by `index_audit`
+const AUDIT_BATCH_SIZE: i64 = 100;
+
+/// Crate files modified more recently than this are left alone by `gc_s3`,
+/// as they may belong to a publish that is still in progress
+const GC_GRACE_PERIOD_HOURS: i64 = 24;
+
+/// Handles the `GET /admin/index_audit` route.
+///
+/// Compares every version in the database against its line in the local
+/// checkout of the index, reporting any version whose checksum, yank flag or
+/// dependencies differ, as well as versions that only appear on one side.
+/// Nothing is repaired.
+///
+/// Crates are checked in batches while the response is being written, so the
+/// report for a large registry is never held in memory all at once.
+pub fn index_audit(req: &mut dyn Request) -> CargoResult<Response> {
+    if !req.user()?.is_admin(req.app()) {
+        return Err(forbidden("must be an admin to perform that action"));
+    }
+
+    let mut headers = HashMap::new();
+    headers.insert(
+        "Content-Type".to_string(),
+        vec!["application/json; charset=utf-8".to_string()],
+    );
+    Ok(Response {
+        status: (200, "OK"),
+        headers,
+        body: Box::new(IndexAudit::new(Arc::clone(req.app()))),
+    })
+}
+
+#[derive(Serialize)]
+struct Discrepancy {
+    #[serde(rename = "crate")]
+    krate: String,
+    version: String,
+    problem: String,
+}
+
+/// Writes out `{"discrepancies":[...]}`, auditing the next batch of crates
+/// whenever the previous one has been read.
+struct IndexAudit {
+    app: Arc<App>,
+    last_crate_id: i32,
+    reported: usize,
+    buf: Vec<u8>,
+    pos: usize,
+    finished: bool,
+}
+
+impl IndexAudit {
+    fn new(app: Arc<App>) -> Self {
+        IndexAudit {
+            app,
+            last_crate_id: 0,
+            reported: 0,
+            buf: b"{\"discrepancies\":[".to_vec(),
+            pos: 0,
+            finished: false,
+        }
+    }
+
+    fn fill(&mut self) -> CargoResult<()> {
+        self.buf.clear();
+        self.pos = 0;
+
+        let discrepancies = match self.audit_next_batch()? {
+            Some(discrepancies) => discrepancies,
+            None => {
+                self.buf.extend_from_slice(b"]}");
+                self.finished = true;
+                return Ok(());
+            }
+        };
+        for discrepancy in discrepancies {
+            if self.reported > 0 {
+                self.buf.push(b',');
+            }
+            serde_json::to_writer(&mut self.buf, &discrepancy)?;
+            self.reported += 1;
+        }
+        Ok(())
+    }
+
+    /// Returns `None` once every crate has been audited
+    fn audit_next_batch(&mut self) -> CargoResult<Option<Vec<Discrepancy>>> {
+        let conn = self.app.diesel_database.get()?;
+
+        let krates = crates::table
+            .select((crates::id, crates::name))
+            .filter(crates::id.gt(self.last_crate_id))
+            .order(crates::id)
+            .limit(AUDIT_BATCH_SIZE)
+            .load::<(i32, String)>(&*conn)?;
+        match krates.last() {
+            Some(&(id, _)) => self.last_crate_id = id,
+            None => return Ok(None),
+        }
+
+        let crate_ids = krates.iter().map(|&(id, _)| id).collect::<Vec<_>>();
+        let versions = versions::table
+            .select((
+                versions::id,
+                versions::crate_id,
+                versions::num,
+                versions::checksum,
+                versions::yanked,
+            )).filter(versions::crate_id.eq_any(crate_ids))
+            .load::<(i32, i32, String, Option<String>, bool)>(&*conn)?;
+
+        let version_ids = versions.iter().map(|v| v.0).collect::<Vec<_>>();
+        let mut deps = HashMap::new();
+        for (version_id, name, req, kind, optional, target) in dependencies::table
+            .inner_join(crates::table)
+            .select((
+                dependencies::version_id,
+                crates::name,
+                dependencies::req,
+                dependencies::kind,
+                dependencies::optional,
+                dependencies::target,
+            )).filter(dependencies::version_id.eq_any(version_ids))
+            .load::<(i32, String, String, i32, bool, Option<String>)>(&*conn)?
+        {
+            deps.entry(version_id)
+                .or_insert_with(Vec::new)
+                .push(dependency_key(&name, &req, kind, optional, &target));
+        }
+
+        let mut discrepancies = Vec::new();
+        for (crate_id, name) in krates {
+            let mut entries = git::crate_entries(&self.app, &name)?
+                .into_iter()
+                .map(|entry| (entry.vers.clone(), entry))
+                .collect::<HashMap<_, _>>();
+
+            let mut report = |version: &str, problem: String| {
+                discrepancies.push(Discrepancy {
+                    krate: name.clone(),
+                    version: version.to_string(),
+                    problem,
+                })
+            };
+
+            for &(version_id, _, ref num, ref checksum, yanked) in
+                versions.iter().filter(|v| v.1 == crate_id)
+            {
+                let entry = match entries.remove(num) {
+                    Some(entry) => entry,
+                    None => {
+                        report(num, "missing from the index".to_string());
+                        continue;
+                    }
+                };
+
+                if let Some(ref checksum) = *checksum {
+                    if *checksum != entry.cksum {
+                        report(
+                            num,
+                            format!(
+                                "checksum is `{}` in the database but `{}` in the index",
+                                checksum, entry.cksum
+                            ),
+                        );
+                    }
+                }
+
+                let index_yanked = entry.yanked.unwrap_or(false);
+                if yanked != index_yanked {
+                    report(
+                        num,
+                        format!(
+                            "yanked is {} in the database but {} in the index",
+                            yanked, index_yanked
+                        ),
+                    );
+                }
+
+                let mut db_deps = deps.remove(&version_id).unwrap_or_default();
+                let mut index_deps = entry
+                    .deps
+                    .iter()
+                    .map(|dep| {
+                        let kind = dep.kind.unwrap_or(DependencyKind::Normal);
+                        let name = dep.package.as_ref().unwrap_or(&dep.name);
+                        dependency_key(name, &dep.req, kind as i32, dep.optional, &dep.target)
+                    }).collect::<Vec<_>>();
+                db_deps.sort();
+                index_deps.sort();
+                if db_deps != index_deps {
+                    report(
+                        num,
+                        "dependencies in the database don't match the index".to_string(),
+                    );
+                }
+            }
+
+            let mut extra = entries.into_iter().map(|(vers, _)| vers).collect::<Vec<_>>();
+            extra.sort();
+            for vers in extra {
+                report(&vers, "missing from the database".to_string());
+            }
+        }
+
+        Ok(Some(discrepancies))
+    }
+}
+
+impl Read for IndexAudit {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.buf.len() {
+            if self.finished {
+                return Ok(0);
+            }
+            self.fill()
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+        }
+        let n = (&self.buf[self.pos..]).read(out)?;
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// Handles the `POST /admin/gc_s3` route.
+///
+/// Lists the crate files on S3 that don't belong to any version in the
+/// database, such as those left behind by publishes that failed after the
+/// upload. Files whose path doesn't follow the layout used for uploads are
+/// never considered orphaned.
+///
+/// Without a body this is a dry run, reporting the orphaned files along with
+/// a `confirm` token. Sending `{"confirm":"<token>"}` back deletes them, which
+/// is refused if the orphaned files have changed since the dry run.
+pub fn gc_s3(req: &mut dyn Request) -> CargoResult<Response> {
+    if !req.user()?.is_admin(req.app()) {
+        return Err(forbidden("must be an admin to perform that action"));
+    }
+
+    #[derive(Deserialize, Default)]
+    struct GcRequest {
+        confirm: Option<String>,
+    }
+
+    let mut body = String::new();
+    req.body().read_to_string(&mut body)?;
+    let gc_request = if body.trim().is_empty() {
+        GcRequest::default()
+    } else {
+        serde_json::from_str::<GcRequest>(&body).map_err(|_| human("invalid json request"))?
+    };
+
+    let app = Arc::clone(req.app());
+    let conn = req.db_conn()?;
+    let live_versions = versions::table
+        .inner_join(crates::table)
+        .select((crates::name, versions::num))
+        .load::<(String, String)>(&*conn)?
+        .into_iter()
+        .collect::<HashSet<_>>();
+
+    let cutoff = Utc::now() - Duration::hours(GC_GRACE_PERIOD_HOURS);
+    let mut orphans = app
+        .config
+        .uploader
+        .crate_files(&app)?
+        .into_iter()
+        .filter(|&(_, last_modified)| last_modified < cutoff)
+        .map(|(path, _)| path)
+        .filter(|path| match Uploader::parse_crate_path(path) {
+            Some((name, num)) => !live_versions.contains(&(name.to_string(), num.to_string())),
+            None => false,
+        }).collect::<Vec<_>>();
+    orphans.sort();
+    let confirm = hex::encode(uploaders::hash(orphans.join("\n").as_bytes()));
+
+    let deleted = match gc_request.confirm {
+        None => false,
+        Some(ref token) if *token == confirm => {
+            for path in &orphans {
+                app.config.uploader.delete(&app, path)?;
+            }
+            true
+        }
+        Some(_) => {
+            return Err(bad_request(
+                "the orphaned files have changed since the dry run, \
+                 run it again to get a new `confirm` token",
+            ))
+        }
+    };
+
+    #[derive(Serialize)]
+    struct R {
+        orphans: Vec<String>,
+        confirm: String,
+        deleted: bool,
+    }
+    Ok(req.json(&R {
+        orphans,
+        confirm,
+        deleted,
+    }))
+}
+
+/// The parts of a dependency that are recorded both in the database and in
+/// the index, in a form that can be compared
+fn dependency_key(
+    crate_name: &str,
+    req: &str,
+    kind: i32,
+    optional: bool,
+    target: &Option<String>,
+) -> String {
+    format!("{} {} {} {} {:?}", crate_name, req, kind, optional, target)
+}
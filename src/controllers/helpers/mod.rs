@@ -1,5 +1,13 @@
-use conduit::Response;
-use util::{json_response, CargoResult};
+use chrono::{DateTime, Duration, Utc};
+use conduit::{Request, Response};
+use diesel::PgConnection;
+use serde::Serialize;
+use serde_json::{self, Value};
+
+use controllers::prelude::RequestUtils;
+use middleware::app::RequestApp;
+use models::{CrateChange, RecentCrateChange};
+use util::{json_response, too_many_requests, CargoResult};
 
 pub mod pagination;
 
@@ -13,3 +21,73 @@ pub fn ok_true() -> CargoResult<Response> {
 
     Ok(json_response(&R { ok: true }))
 }
+
+/// Enforces `Config::crate_change_rate_limit` for the user `user_id` making a
+/// change to the crate `crate_id`, recording the change if it's allowed.
+///
+/// This must be called in the transaction that makes the change, once the
+/// change is known to succeed, so that a change that's rolled back isn't
+/// counted and concurrent changes can't both get in under the limit.
+pub fn limit_crate_changes(
+    req: &dyn Request,
+    conn: &PgConnection,
+    user_id: i32,
+    crate_id: i32,
+    change: CrateChange,
+) -> CargoResult<()> {
+    let config = &req.app().config;
+    let limit = match config.crate_change_rate_limit {
+        Some(limit) => limit,
+        None => return Ok(()),
+    };
+    let window_start =
+        Utc::now().naive_utc() - Duration::minutes(config.crate_change_rate_window_minutes);
+    RecentCrateChange::lock(conn, user_id, crate_id)?;
+    if RecentCrateChange::count_since(conn, user_id, crate_id, change, window_start)? >= limit {
+        return Err(too_many_requests(&format_args!(
+            "rate limit exceeded, retry later: at most {} changes of this kind can be made \
+             to a crate every {} minutes",
+            limit, config.crate_change_rate_window_minutes
+        )));
+    }
+    RecentCrateChange::record(conn, user_id, crate_id, change, window_start)?;
+    Ok(())
+}
+
+/// Serializes `t` as the response to `req`. The `created_at` and `updated_at`
+/// fields are given as Unix timestamps instead of RFC 3339 strings when
+/// `time_format=unix` is in the query string.
+pub fn json_with_time_format<T: Serialize>(req: &dyn Request, t: &T) -> CargoResult<Response> {
+    if req.query().get("time_format").map(|s| &s[..]) != Some("unix") {
+        return Ok(json_response(t));
+    }
+    let mut value = serde_json::to_value(t)?;
+    use_unix_timestamps(&mut value);
+    Ok(json_response(&value))
+}
+
+fn use_unix_timestamps(value: &mut Value) {
+    match *value {
+        Value::Object(ref mut fields) => {
+            for (key, value) in fields.iter_mut() {
+                if key == "created_at" || key == "updated_at" {
+                    let timestamp = value
+                        .as_str()
+                        .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+                        .map(|time| time.timestamp());
+                    if let Some(timestamp) = timestamp {
+                        *value = Value::from(timestamp);
+                        continue;
+                    }
+                }
+                use_unix_timestamps(value);
+            }
+        }
+        Value::Array(ref mut values) => {
+            for value in values {
+                use_unix_timestamps(value);
+            }
+        }
+        _ => {}
+    }
+}
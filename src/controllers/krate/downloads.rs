@@ -5,20 +5,44 @@
 
 use std::cmp;
 
+use chrono::{DateTime, NaiveDateTime, Utc};
+use url;
+
 use controllers::prelude::*;
+use util::{bad_request, rfc3339};
 
 use models::{Crate, CrateVersions, Version, VersionDownload};
-use schema::version_downloads;
+use schema::{crates, version_downloads};
 use views::EncodableVersionDownload;
 
 use models::krate::to_char;
 
+/// The most days of download counts `downloads` returns, which is also the
+/// default
+const MAX_DOWNLOAD_DAYS: i32 = 90;
+
 /// Handles the `GET /crates/:crate_id/downloads` route.
+///
+/// Returns daily download counts for the last `days` days, for the five
+/// highest versions individually and for the rest of the versions combined.
 pub fn downloads(req: &mut dyn Request) -> CargoResult<Response> {
     use diesel::dsl::*;
     use diesel::sql_types::BigInt;
 
     let crate_name = &req.params()["crate_id"];
+    let days = match req.query().get("days") {
+        Some(days) => days
+            .parse::<i32>()
+            .ok()
+            .filter(|&days| days > 0 && days <= MAX_DOWNLOAD_DAYS)
+            .ok_or_else(|| {
+                bad_request(&format_args!(
+                    "`days` must be between 1 and {}",
+                    MAX_DOWNLOAD_DAYS
+                ))
+            })?,
+        None => MAX_DOWNLOAD_DAYS,
+    };
     let conn = req.db_conn()?;
     let krate = Crate::by_name(crate_name).first::<Crate>(&*conn)?;
 
@@ -27,7 +51,7 @@ pub fn downloads(req: &mut dyn Request) -> CargoResult<Response> {
     let (latest_five, rest) = versions.split_at(cmp::min(5, versions.len()));
 
     let downloads = VersionDownload::belonging_to(latest_five)
-        .filter(version_downloads::date.gt(date(now - 90.days())))
+        .filter(version_downloads::date.gt(date(now - days.days())))
         .order(version_downloads::date.asc())
         .load(&*conn)?
         .into_iter()
@@ -39,7 +63,7 @@ pub fn downloads(req: &mut dyn Request) -> CargoResult<Response> {
         .select((
             to_char(version_downloads::date, "YYYY-MM-DD"),
             sum_downloads,
-        )).filter(version_downloads::date.gt(date(now - 90.days())))
+        )).filter(version_downloads::date.gt(date(now - days.days())))
         .group_by(version_downloads::date)
         .order(version_downloads::date.asc())
         .load::<ExtraDownload>(&*conn)?;
@@ -66,3 +90,93 @@ pub fn downloads(req: &mut dyn Request) -> CargoResult<Response> {
         meta,
     }))
 }
+
+/// Handles the `GET /changed_crates` route.
+///
+/// Lists the crates whose download count has changed since `ts`, given in
+/// RFC 3339 format, along with their current download count, so that a
+/// dashboard can refresh only what has changed since it last polled. The
+/// crates are ordered by when their count last changed, oldest first. Each
+/// page links to the next one by the change time and id of the last crate on
+/// it, given as `ts` and `after_id`.
+///
+/// A count's change time is when the transaction that changed it started, so
+/// a change can show up after changes with later times have been listed.
+/// Clients should poll again from a few minutes before the last change time
+/// they saw, and expect to see some crates twice.
+pub fn changed_since(req: &mut dyn Request) -> CargoResult<Response> {
+    let params = req.query();
+    let since = params
+        .get("ts")
+        .and_then(|ts| DateTime::parse_from_rfc3339(ts).ok())
+        .map(|ts| ts.naive_utc())
+        .ok_or_else(|| bad_request("`ts` must be an RFC 3339 timestamp"))?;
+    let after_id = match params.get("after_id") {
+        Some(id) => Some(
+            id.parse::<i32>()
+                .map_err(|_| bad_request("`after_id` must be a crate id"))?,
+        ),
+        None => None,
+    };
+    let limit = req.per_page(100, 1000)?;
+
+    let conn = req.db_conn()?;
+    let mut query = crates::table
+        .select((
+            crates::id,
+            crates::name,
+            crates::downloads,
+            crates::stats_updated_at,
+        )).order((crates::stats_updated_at, crates::id))
+        .limit(limit)
+        .into_boxed();
+    query = match after_id {
+        Some(id) => query.filter(
+            crates::stats_updated_at
+                .gt(since)
+                .or(crates::stats_updated_at.eq(since).and(crates::id.gt(id))),
+        ),
+        None => query.filter(crates::stats_updated_at.gt(since)),
+    };
+    let data = query.load::<(i32, String, i32, NaiveDateTime)>(&*conn)?;
+
+    let next_page = match data.last() {
+        Some(&(id, _, _, stats_updated_at)) if data.len() as i64 == limit => {
+            let ts = DateTime::<Utc>::from_utc(stats_updated_at, Utc).to_rfc3339();
+            let mut link = url::form_urlencoded::Serializer::new(String::new());
+            link.append_pair("ts", &ts);
+            link.append_pair("after_id", &id.to_string());
+            link.append_pair("per_page", &limit.to_string());
+            Some(format!("?{}", link.finish()))
+        }
+        _ => None,
+    };
+    let crates = data
+        .into_iter()
+        .map(|(_, name, downloads, stats_updated_at)| ChangedCrate {
+            name,
+            downloads,
+            stats_updated_at,
+        }).collect::<Vec<_>>();
+
+    #[derive(Serialize)]
+    struct ChangedCrate {
+        name: String,
+        downloads: i32,
+        #[serde(with = "rfc3339")]
+        stats_updated_at: NaiveDateTime,
+    }
+    #[derive(Serialize)]
+    struct R {
+        crates: Vec<ChangedCrate>,
+        meta: Meta,
+    }
+    #[derive(Serialize)]
+    struct Meta {
+        next_page: Option<String>,
+    }
+    Ok(req.json(&R {
+        crates,
+        meta: Meta { next_page },
+    }))
+}
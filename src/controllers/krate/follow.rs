@@ -1,7 +1,10 @@
 //! Endpoints for managing a per user list of followed crates
 
+use std::io::Read;
+
 use diesel;
 use diesel::associations::Identifiable;
+use serde_json;
 
 use controllers::prelude::*;
 use models::{Crate, Follow};
@@ -17,16 +20,41 @@ fn follow_target(req: &mut dyn Request) -> CargoResult<Follow> {
     Ok(Follow {
         user_id: user.id,
         crate_id,
+        prereleases: false,
     })
 }
 
 /// Handles the `PUT /crates/:crate_id/follow` route.
+///
+/// Followers only see stable versions of the crate in their updates unless
+/// the body is `{"prereleases": true}`. Following a crate again replaces the
+/// previous choice.
 pub fn follow(req: &mut dyn Request) -> CargoResult<Response> {
-    let follow = follow_target(req)?;
+    #[derive(Deserialize, Default)]
+    struct FollowRequest {
+        #[serde(default)]
+        prereleases: bool,
+    }
+
+    let mut body = String::new();
+    req.body().read_to_string(&mut body)?;
+    let request = if body.trim().is_empty() {
+        FollowRequest::default()
+    } else {
+        serde_json::from_str::<FollowRequest>(&body)
+            .map_err(|_| human("invalid json request"))?
+    };
+
+    let follow = Follow {
+        prereleases: request.prereleases,
+        ..follow_target(req)?
+    };
     let conn = req.db_conn()?;
     diesel::insert_into(follows::table)
         .values(&follow)
-        .on_conflict_do_nothing()
+        .on_conflict((follows::user_id, follows::crate_id))
+        .do_update()
+        .set(follows::prereleases.eq(follow.prereleases))
         .execute(&*conn)?;
 
     ok_true()
@@ -43,15 +71,21 @@ pub fn unfollow(req: &mut dyn Request) -> CargoResult<Response> {
 
 /// Handles the `GET /crates/:crate_id/following` route.
 pub fn following(req: &mut dyn Request) -> CargoResult<Response> {
-    use diesel::dsl::exists;
-
     let follow = follow_target(req)?;
     let conn = req.db_conn()?;
-    let following = diesel::select(exists(follows::table.find(follow.id()))).get_result(&*conn)?;
+    let prereleases = follows::table
+        .find(follow.id())
+        .select(follows::prereleases)
+        .first::<bool>(&*conn)
+        .optional()?;
 
     #[derive(Serialize)]
     struct R {
         following: bool,
+        prereleases: bool,
     }
-    Ok(req.json(&R { following }))
+    Ok(req.json(&R {
+        following: prereleases.is_some(),
+        prereleases: prereleases.unwrap_or(false),
+    }))
 }
@@ -2,18 +2,30 @@
 //!
 //! These endpoints provide data that could be obtained direclty from the
 //! index or cached metadata which was extracted (client side) from the
-//! `Cargo.toml` file.
+//! `Cargo.toml` file. Owners can also update some of that metadata between
+//! publishes.
 
+use std::collections::HashMap;
+use std::slice;
+
+use semver;
+use serde_json;
+
+use controllers::helpers::{json_with_time_format, limit_crate_changes};
 use controllers::prelude::*;
 use models::{
-    Category, Crate, CrateCategory, CrateDownload, CrateKeyword, CrateVersions, Keyword, Version,
+    Category, Crate, CrateCategory, CrateChange, CrateDownload, CrateKeyword, CrateUpdate,
+    CrateVersions, DependencyKind, Keyword, Rights, User, Version,
 };
 use schema::*;
+use uploaders::Uploader;
 use views::{
     EncodableCategory, EncodableCrate, EncodableDependency, EncodableKeyword, EncodableVersion,
 };
 
 use models::krate::ALL_COLUMNS;
+use util::bad_request;
+use util::errors::{CargoError, NotFound};
 
 /// Handles the `GET /summary` route.
 pub fn summary(req: &mut dyn Request) -> CargoResult<Response> {
@@ -85,7 +97,7 @@ pub fn summary(req: &mut dyn Request) -> CargoResult<Response> {
         popular_keywords: Vec<EncodableKeyword>,
         popular_categories: Vec<EncodableCategory>,
     }
-    Ok(req.json(&R {
+    json_with_time_format(req, &R {
         num_downloads,
         num_crates,
         new_crates: encode_crates(new_crates)?,
@@ -94,10 +106,13 @@ pub fn summary(req: &mut dyn Request) -> CargoResult<Response> {
         just_updated: encode_crates(just_updated)?,
         popular_keywords,
         popular_categories,
-    }))
+    })
 }
 
 /// Handles the `GET /crates/:crate_id` route.
+///
+/// The logins of the crate's owners are included when `include_owners=true`
+/// is given.
 pub fn show(req: &mut dyn Request) -> CargoResult<Response> {
     use diesel::dsl::*;
 
@@ -107,7 +122,18 @@ pub fn show(req: &mut dyn Request) -> CargoResult<Response> {
 
     let mut versions = krate.all_versions().load::<Version>(&*conn)?;
     versions.sort_by(|a, b| b.num.cmp(&a.num));
-    let ids = versions.iter().map(|v| v.id).collect();
+    let ids = versions.iter().map(|v| v.id).collect::<Vec<_>>();
+    let mut changelog_excerpts = Version::changelog_excerpts(&conn, &ids)?;
+    let publisher_ids = versions
+        .iter()
+        .filter_map(|v| v.published_by)
+        .collect::<Vec<_>>();
+    let publishers = users::table
+        .filter(users::id.eq_any(publisher_ids))
+        .load::<User>(&*conn)?
+        .into_iter()
+        .map(|user| (user.id, user))
+        .collect::<HashMap<_, _>>();
 
     let kws = CrateKeyword::belonging_to(&krate)
         .inner_join(keywords::table)
@@ -127,6 +153,74 @@ pub fn show(req: &mut dyn Request) -> CargoResult<Response> {
         .load(&*conn)?;
     let max_version = krate.max_version(&conn)?;
 
+    // Dev-dependencies aren't built by dependents, so they don't count here
+    let has_no_dependencies = match versions.iter().find(|v| !v.yanked && v.num == max_version) {
+        Some(latest) => {
+            let has_dependencies = select(exists(
+                dependencies::table
+                    .filter(dependencies::version_id.eq(latest.id))
+                    .filter(dependencies::kind.ne(DependencyKind::Dev as i32)),
+            )).get_result::<bool>(&*conn)?;
+            Some(!has_dependencies)
+        }
+        None => None,
+    };
+
+    // Custom metadata is only meant for the people managing the crate
+    let can_see_custom_metadata = match req.user() {
+        Ok(user) => {
+            user.is_admin(req.app())
+                || user.rights(req.app(), &krate.owners(&conn)?)? >= Rights::Publish
+        }
+        Err(_) => false,
+    };
+    let custom_metadata = if can_see_custom_metadata {
+        Some(krate.custom_metadata(&conn)?)
+    } else {
+        None
+    };
+
+    // Lets clients download a version without going through the `download`
+    // endpoint first, at the cost of that download not being counted. Tests
+    // that don't use the recording proxy have no crate files to link to.
+    let config = &req.app().config;
+    let links_to_files = match config.uploader {
+        Uploader::Panic => false,
+        _ => true,
+    };
+
+    let owners = if req.query().get("include_owners").map(|s| &s[..]) == Some("true") {
+        let owners = krate.owners(&conn)?;
+        Some(owners.iter().map(|o| o.login().to_string()).collect())
+    } else {
+        None
+    };
+
+    let mut versions = versions
+        .into_iter()
+        .map(|v| {
+            let num = v.num.to_string();
+            let download_url = match config.signed_download_expiry_secs {
+                _ if !links_to_files => None,
+                Some(expires_in) => config
+                    .uploader
+                    .signed_crate_location(&krate.name, &num, expires_in),
+                None => config.uploader.crate_location(&krate.name, &num),
+            };
+            let changelog_excerpt = changelog_excerpts.remove(&v.id);
+            let published_by = v
+                .published_by
+                .and_then(|id| publishers.get(&id))
+                .map(|user| user.clone().encodable_public());
+            EncodableVersion {
+                download_url,
+                changelog_excerpt,
+                published_by,
+                ..v.encodable(&krate.name)
+            }
+        }).collect::<Vec<_>>();
+    Version::add_provenance_links(&conn, &mut versions)?;
+
     #[derive(Serialize)]
     struct R {
         #[serde(rename = "crate")]
@@ -134,24 +228,84 @@ pub fn show(req: &mut dyn Request) -> CargoResult<Response> {
         versions: Vec<EncodableVersion>,
         keywords: Vec<EncodableKeyword>,
         categories: Vec<EncodableCategory>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        owners: Option<Vec<String>>,
     }
-    Ok(req.json(&R {
-        krate: krate.clone().encodable(
-            &max_version,
-            Some(ids),
-            Some(&kws),
-            Some(&cats),
-            Some(badges),
-            false,
-            recent_downloads,
-        ),
-        versions: versions
-            .into_iter()
-            .map(|v| v.encodable(&krate.name))
-            .collect(),
+    json_with_time_format(req, &R {
+        krate: EncodableCrate {
+            has_no_dependencies,
+            custom_metadata,
+            ..krate.clone().encodable(
+                &max_version,
+                Some(ids),
+                Some(&kws),
+                Some(&cats),
+                Some(badges),
+                false,
+                recent_downloads,
+            )
+        },
+        versions,
         keywords: kws.into_iter().map(|k| k.encodable()).collect(),
         categories: cats.into_iter().map(|k| k.encodable()).collect(),
-    }))
+        owners,
+    })
+}
+
+/// Handles the `PUT /crates/:crate_id` route.
+///
+/// Lets the owners of a crate change the metadata that is otherwise only
+/// updated when a new version is published, as well as the crate's
+/// `custom_metadata`. Admins can update any crate. Fields that are left out of
+/// the request keep their current value. The crate is returned in the same
+/// form as `GET /crates/:crate_id`.
+pub fn update(req: &mut dyn Request) -> CargoResult<Response> {
+    #[derive(Deserialize)]
+    struct UpdateRequest {
+        #[serde(rename = "crate")]
+        krate: CrateChanges,
+    }
+
+    #[derive(Deserialize)]
+    struct CrateChanges {
+        description: Option<String>,
+        homepage: Option<String>,
+        documentation: Option<String>,
+        repository: Option<String>,
+        custom_metadata: Option<serde_json::Value>,
+    }
+
+    let mut body = String::new();
+    req.body().read_to_string(&mut body)?;
+    let request: UpdateRequest =
+        serde_json::from_str(&body).map_err(|_| human("invalid json request"))?;
+
+    {
+        let user = req.user()?;
+        let crate_name = &req.params()["crate_id"];
+        let conn = req.db_conn()?;
+        let krate = Crate::by_name(crate_name).first::<Crate>(&*conn)?;
+
+        let owners = krate.owners(&conn)?;
+        if !user.is_admin(req.app()) && user.rights(req.app(), &owners)? < Rights::Publish {
+            return Err(human("only owners have permission to modify crate metadata"));
+        }
+
+        let changes = request.krate;
+        let changes = CrateUpdate {
+            description: changes.description.as_ref().map(|s| &**s),
+            homepage: changes.homepage.as_ref().map(|s| &**s),
+            documentation: changes.documentation.as_ref().map(|s| &**s),
+            repository: changes.repository.as_ref().map(|s| &**s),
+            custom_metadata: changes.custom_metadata,
+        };
+        conn.transaction::<_, Box<dyn CargoError>, _>(|| {
+            krate.update_metadata(&conn, &changes)?;
+            limit_crate_changes(req, &conn, user.id, krate.id, CrateChange::Update)
+        })?;
+    }
+
+    show(req)
 }
 
 /// Handles the `GET /crates/:crate_id/:version/readme` route.
@@ -186,10 +340,11 @@ pub fn versions(req: &mut dyn Request) -> CargoResult<Response> {
     let krate = Crate::by_name(crate_name).first::<Crate>(&*conn)?;
     let mut versions = krate.all_versions().load::<Version>(&*conn)?;
     versions.sort_by(|a, b| b.num.cmp(&a.num));
-    let versions = versions
+    let mut versions = versions
         .into_iter()
         .map(|v| v.encodable(crate_name))
-        .collect();
+        .collect::<Vec<_>>();
+    Version::add_provenance_links(&conn, &mut versions)?;
 
     #[derive(Serialize)]
     struct R {
@@ -198,6 +353,60 @@ pub fn versions(req: &mut dyn Request) -> CargoResult<Response> {
     Ok(req.json(&R { versions }))
 }
 
+/// Handles the `GET /crates/:crate_id/resolve` route.
+///
+/// Returns the version picked by `resolve_version`, or a 404 if there isn't
+/// one.
+pub fn resolve(req: &mut dyn Request) -> CargoResult<Response> {
+    let (version, krate) = resolve_version(req)?;
+    let conn = req.db_conn()?;
+    let mut version = version.encodable(&krate.name);
+    Version::add_provenance_links(&conn, slice::from_mut(&mut version))?;
+
+    #[derive(Serialize)]
+    struct R {
+        version: EncodableVersion,
+    }
+    Ok(req.json(&R { version }))
+}
+
+/// Returns the highest version of the crate that isn't yanked and satisfies
+/// the `req` query parameter. As in Cargo, pre-release versions only match
+/// requirements that mention a pre-release, unless `include_prerelease=true`
+/// is given, in which case they're also matched as though they were the
+/// release they precede.
+pub fn resolve_version(req: &mut dyn Request) -> CargoResult<(Version, Crate)> {
+    let crate_name = &req.params()["crate_id"];
+    let query = req.query();
+    let version_req = query
+        .get("req")
+        .ok_or_else(|| bad_request("missing query parameter `req`"))?;
+    let version_req = semver::VersionReq::parse(version_req).map_err(|_| {
+        bad_request(&format_args!(
+            "`{}` is not a valid version requirement",
+            version_req
+        ))
+    })?;
+    let include_prerelease = query.get("include_prerelease").map(|s| &s[..]) == Some("true");
+
+    let conn = req.db_conn()?;
+    let krate = Crate::by_name(crate_name).first::<Crate>(&*conn)?;
+    let version = krate
+        .versions()
+        .load::<Version>(&*conn)?
+        .into_iter()
+        .filter(|v| {
+            if version_req.matches(&v.num) {
+                return true;
+            }
+            let mut release = v.num.clone();
+            release.pre.clear();
+            include_prerelease && v.num.is_prerelease() && version_req.matches(&release)
+        }).max_by(|a, b| a.num.cmp(&b.num))
+        .ok_or(NotFound)?;
+    Ok((version, krate))
+}
+
 /// Handles the `GET /crates/:crate_id/reverse_dependencies` route.
 pub fn reverse_dependencies(req: &mut dyn Request) -> CargoResult<Response> {
     use diesel::dsl::any;
@@ -214,14 +423,15 @@ pub fn reverse_dependencies(req: &mut dyn Request) -> CargoResult<Response> {
 
     let version_ids: Vec<i32> = rev_deps.iter().map(|dep| dep.version_id).collect();
 
-    let versions = versions::table
+    let mut versions = versions::table
         .filter(versions::id.eq(any(version_ids)))
         .inner_join(crates::table)
         .select((versions::all_columns, crates::name))
         .load::<(Version, String)>(&*conn)?
         .into_iter()
         .map(|(version, krate_name)| version.encodable(&krate_name))
-        .collect();
+        .collect::<Vec<_>>();
+    Version::add_provenance_links(&conn, &mut versions)?;
 
     #[derive(Serialize)]
     struct R {
@@ -239,3 +449,53 @@ pub fn reverse_dependencies(req: &mut dyn Request) -> CargoResult<Response> {
         meta: Meta { total },
     }))
 }
+
+/// Handles the `GET /crates/:crate_id/feature_usage` route.
+///
+/// Lists every feature of the crate's newest version along with the number of
+/// crates whose newest version enables it when depending on this crate,
+/// including features that no one enables. `default` is counted for
+/// dependents that leave default features turned on.
+pub fn feature_usage(req: &mut dyn Request) -> CargoResult<Response> {
+    let name = &req.params()["crate_id"];
+    let conn = req.db_conn()?;
+    let krate = Crate::by_name(name).first::<Crate>(&*conn)?;
+
+    let newest = krate
+        .versions()
+        .load::<Version>(&*conn)?
+        .into_iter()
+        .max_by(|a, b| a.num.cmp(&b.num));
+    let mut usage = newest
+        .as_ref()
+        .and_then(|v| v.features.as_object())
+        .map(|features| features.keys().map(|f| (f.clone(), 0)).collect())
+        .unwrap_or_else(HashMap::new);
+
+    for enabled in krate.feature_usage(&conn)? {
+        if let Some(count) = usage.get_mut(&enabled.feature) {
+            *count = enabled.dependents;
+        }
+    }
+
+    #[derive(Serialize)]
+    struct FeatureUsage {
+        name: String,
+        dependents: i64,
+    }
+    let mut features = usage
+        .into_iter()
+        .map(|(name, dependents)| FeatureUsage { name, dependents })
+        .collect::<Vec<_>>();
+    features.sort_by(|a, b| {
+        b.dependents
+            .cmp(&a.dependents)
+            .then_with(|| a.name.cmp(&b.name))
+    });
+
+    #[derive(Serialize)]
+    struct R {
+        features: Vec<FeatureUsage>,
+    }
+    Ok(req.json(&R { features }))
+}
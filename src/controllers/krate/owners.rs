@@ -2,8 +2,10 @@
 
 use serde_json;
 
+use controllers::helpers::limit_crate_changes;
 use controllers::prelude::*;
-use models::{Crate, Owner, Rights, Team, User};
+use models::{Crate, CrateChange, Owner, Rights, Team, User};
+use util::errors::CargoError;
 use views::EncodableOwner;
 
 /// Handles the `GET /crates/:crate_id/owners` route.
@@ -103,25 +105,30 @@ fn modify_owners(req: &mut dyn Request, add: bool) -> CargoResult<Response> {
         .or(request.users)
         .ok_or_else(|| human("invalid json request"))?;
 
-    let mut msgs = Vec::new();
-
-    for login in &logins {
-        if add {
-            let login_test = |owner: &Owner| owner.login().to_lowercase() == *login.to_lowercase();
-            if owners.iter().any(login_test) {
-                return Err(human(&format_args!("`{}` is already an owner", login)));
-            }
-            let msg = krate.owner_add(req.app(), &conn, user, login)?;
-            msgs.push(msg);
-        } else {
-            // Removing the team that gives you rights is prevented because
-            // team members only have Rights::Publish
-            if owners.len() == 1 {
-                return Err(human("cannot remove the sole owner of a crate"));
+    // Only changes that go through count towards the rate limit
+    let msgs = conn.transaction::<_, Box<dyn CargoError>, _>(|| {
+        let mut msgs = Vec::new();
+        for login in &logins {
+            if add {
+                let login_test =
+                    |owner: &Owner| owner.login().to_lowercase() == *login.to_lowercase();
+                if owners.iter().any(login_test) {
+                    return Err(human(&format_args!("`{}` is already an owner", login)));
+                }
+                let msg = krate.owner_add(req.app(), &conn, user, login)?;
+                msgs.push(msg);
+            } else {
+                // Removing the team that gives you rights is prevented because
+                // team members only have Rights::Publish
+                if owners.len() == 1 {
+                    return Err(human("cannot remove the sole owner of a crate"));
+                }
+                krate.owner_remove(req.app(), &conn, user, login)?;
             }
-            krate.owner_remove(req.app(), &conn, user, login)?;
         }
-    }
+        limit_crate_changes(req, &conn, user.id, krate.id, CrateChange::Owners)?;
+        Ok(msgs)
+    })?;
 
     let comma_sep_msg = msgs.join(",");
 
@@ -1,21 +1,41 @@
 //! Functionality related to publishing a new crate or version of a crate.
 
 use std::collections::HashMap;
+use std::io::Read;
 use std::sync::Arc;
 
+use chrono::{Duration, NaiveDateTime, Utc};
 use hex::ToHex;
+use semver;
 use serde_json;
 
+use app::App;
 use git;
 use render;
-use util::{internal, ChainError, Maximums};
-use util::{read_fill, read_le_u32};
+use uploaders;
+use util::{internal, too_many_requests, ChainError, Maximums};
+use util::{read_fill, read_le_u32, LimitErrorReader};
 
 use controllers::prelude::*;
 use models::dependency;
-use models::{Badge, Category, Keyword, NewCrate, NewVersion, Rights, User};
+use models::{
+    Badge, Category, Crate, IdempotencyKey, Keyword, NewCrate, NewVersion, PendingIndexWrite,
+    RecentPublish, Rights, User, Version, MAX_CHANGELOG_LENGTH,
+};
 use views::{EncodableCrate, EncodableCrateUpload};
 
+/// The longest `Idempotency-Key` header accepted by `publish`
+const MAX_IDEMPOTENCY_KEY_LENGTH: usize = 255;
+
+/// The most features a version can declare
+const MAX_FEATURES: usize = 300;
+
+/// The most entries a version's features can enable, across all of them
+const MAX_FEATURE_ENTRIES: usize = 3000;
+
+/// The longest version requirement a dependency can have, once normalized
+const MAX_DEPENDENCY_REQ_LENGTH: usize = 255;
+
 /// Handles the `PUT /crates/new` route.
 /// Used by `cargo publish` to publish a new crate or to publish a new version of an
 /// existing crate.
@@ -41,6 +61,57 @@ pub fn publish(req: &mut dyn Request) -> CargoResult<Response> {
 
     let (new_crate, user) = parse_new_headers(req)?;
 
+    // A retried publish with the same key gets the original response back
+    // instead of being published again
+    let idempotency_key = req
+        .headers()
+        .find("Idempotency-Key")
+        .and_then(|values| values.first().map(|s| s.to_string()))
+        .filter(|s| !s.is_empty());
+    if let Some(ref key) = idempotency_key {
+        if key.len() > MAX_IDEMPOTENCY_KEY_LENGTH {
+            return Err(human(&format_args!(
+                "the Idempotency-Key header must not be longer than {} characters",
+                MAX_IDEMPOTENCY_KEY_LENGTH
+            )));
+        }
+        let (recorded, max_upload_size) = {
+            let conn = req.db_conn()?;
+            let krate = Crate::by_name(&*new_crate.name)
+                .first::<Crate>(&*conn)
+                .optional()?;
+            let maximums = Maximums::new(
+                krate.and_then(|k| k.max_upload_size),
+                app.config.max_upload_size,
+                app.config.max_unpack_size,
+            );
+            (
+                IdempotencyKey::find(&conn, user.id, key)?,
+                maximums.max_upload_size,
+            )
+        };
+        if let Some(recorded) = recorded {
+            // The key only counts as a retry if it publishes the same thing
+            let cksum = tarball_checksum(req, max_upload_size)?;
+            let fingerprint =
+                IdempotencyKey::fingerprint(&*new_crate.name, &new_crate.vers.to_string(), &cksum);
+            if recorded.fingerprint != fingerprint {
+                return Err(IdempotencyKey::reused_error());
+            }
+            return Ok(publish_response(req, &recorded.response));
+        }
+    }
+
+    // This is checked before anything is uploaded, so that a client stuck
+    // publishing in a loop doesn't cost us any bandwidth. It's checked again
+    // under a lock before the publish is recorded.
+    let rate_limit_window_start =
+        Utc::now().naive_utc() - Duration::minutes(app.config.publish_rate_window_minutes);
+    {
+        let conn = req.db_conn()?;
+        limit_publishes(&app, &conn, user.id, rate_limit_window_start)?;
+    }
+
     let name = &*new_crate.name;
     let vers = &*new_crate.vers;
     let links = new_crate.links.clone();
@@ -63,6 +134,21 @@ pub fn publish(req: &mut dyn Request) -> CargoResult<Response> {
     let categories = new_crate.categories.as_ref().map(|s| &s[..]).unwrap_or(&[]);
     let categories: Vec<_> = categories.iter().map(|k| &***k).collect();
 
+    // The VCS ref the crate was packaged from, if the client told us
+    let git_ref = req
+        .headers()
+        .find("X-Cargo-Pkg-Git-Ref")
+        .and_then(|values| values.first().map(|s| s.to_string()))
+        .filter(|s| !s.is_empty());
+    if let Some(ref git_ref) = git_ref {
+        if !Version::valid_git_ref(git_ref) {
+            return Err(human(&format_args!(
+                "invalid `X-Cargo-Pkg-Git-Ref` header: `{}` isn't a branch, tag or commit",
+                git_ref
+            )));
+        }
+    }
+
     let conn = req.db_conn()?;
     // Create a transaction on the database, if there are no errors,
     // commit the transactions to record a new or updated crate.
@@ -101,28 +187,41 @@ pub fn publish(req: &mut dyn Request) -> CargoResult<Response> {
         }
 
         // Length of the .crate tarball, which appears after the metadata in the request body.
-        // TODO: Not sure why we're using the total content length (metadata + .crate file length)
-        // to compare against the max upload size... investigate that and perhaps change to use
-        // this file length.
         let file_length = read_le_u32(req.body())?;
 
-        let content_length = req
-            .content_length()
-            .chain_error(|| human("missing header: Content-Length"))?;
-
         let maximums = Maximums::new(
             krate.max_upload_size,
             app.config.max_upload_size,
             app.config.max_unpack_size,
         );
 
-        if content_length > maximums.max_upload_size {
+        // A body streamed without a Content-Length can only be checked against
+        // the length the client declared for the tarball here. Either way,
+        // `upload_crate` stops reading the tarball once it goes over the max.
+        // TODO: Not sure why we're using the total content length (metadata + .crate file length)
+        // to compare against the max upload size... investigate that and perhaps change to use
+        // this file length.
+        let upload_length = req.content_length().unwrap_or_else(|| u64::from(file_length));
+        if upload_length > maximums.max_upload_size {
             return Err(human(&format_args!(
                 "max upload size is: {}",
                 maximums.max_upload_size
             )));
         }
 
+        // Large crates are allowed, but often include files by accident
+        let mut other_warnings = Vec::new();
+        if let Some(threshold) = app.config.publish_size_warning_threshold {
+            if u64::from(file_length) > threshold {
+                other_warnings.push(format!(
+                    "the uploaded crate is {} bytes, more than the {} bytes this registry \
+                     considers large; consider excluding large files that aren't needed \
+                     to build it",
+                    file_length, threshold
+                ));
+            }
+        }
+
         // This is only redundant for now. Eventually the duplication will be removed.
         let license = new_crate.license.clone();
 
@@ -136,11 +235,56 @@ pub fn publish(req: &mut dyn Request) -> CargoResult<Response> {
             // Downcast is okay because the file length must be less than the max upload size
             // to get here, and max upload sizes are way less than i32 max
             file_length as i32,
-        )?.save(&conn, &new_crate.authors)?;
+            Some(user.id),
+        )?;
+
+        if version.feature_syntax_version() > app.config.feature_syntax_version {
+            return Err(human(&format_args!(
+                "this registry only accepts feature syntax up to version {}, \
+                 namespaced (`dep:`) and weak (`?/`) dependency features are not supported",
+                app.config.feature_syntax_version
+            )));
+        }
+
+        let version = version.save(&conn, &new_crate.authors)?;
+
+        if app.config.publish_rate_limit.is_some() {
+            // Concurrent publishes by the same user could otherwise all get
+            // past the check above
+            RecentPublish::lock(&conn, user.id)?;
+            limit_publishes(&app, &conn, user.id, rate_limit_window_start)?;
+            RecentPublish::record(&conn, user.id, rate_limit_window_start)?;
+        }
+
+        if let Some(ref provenance) = new_crate.provenance {
+            version.record_provenance(&conn, provenance)?;
+        }
+
+        if let Some(git_ref) = git_ref {
+            version.record_git_ref(&conn, &git_ref)?;
+        }
+
+        if let Some(ref changelog) = new_crate.changelog {
+            version.record_changelog(&conn, changelog)?;
+        }
 
         // Link this new version to all dependencies
         let git_deps = dependency::add_dependencies(&conn, &new_crate.deps, version.id)?;
 
+        // The check is only best-effort, so cycles are just a warning unless
+        // the registry is configured to reject them
+        let dependency_cycles = dependency::find_cycles(&conn, &krate, vers, &new_crate.deps)?;
+        if app.config.reject_dependency_cycles && !dependency_cycles.is_empty() {
+            return Err(human(&format_args!(
+                "this version would create a dependency cycle: {}",
+                dependency_cycles.join(", ")
+            )));
+        }
+        // Cargo only prints the warnings it knows about, and `other`
+        for cycle in &dependency_cycles {
+            other_warnings.push(format!("this version creates a dependency cycle: {}", cycle));
+        }
+
         // Update all keywords for this crate
         Keyword::update_crate(&conn, &krate, &keywords)?;
 
@@ -166,7 +310,7 @@ pub fn publish(req: &mut dyn Request) -> CargoResult<Response> {
         // Upload the crate, return way to delete the crate from the server
         // If the git commands fail below, we shouldn't keep the crate on the
         // server.
-        let (cksum, mut crate_bomb, mut readme_bomb) = app
+        let (cksum, files, uncompressed_size, mut crate_bomb, mut readme_bomb) = app
             .config
             .uploader
             .upload_crate(req, &krate, readme, maximums, vers)?;
@@ -174,23 +318,54 @@ pub fn publish(req: &mut dyn Request) -> CargoResult<Response> {
 
         let mut hex_cksum = String::new();
         cksum.write_hex(&mut hex_cksum)?;
+        version.record_checksum(&conn, &hex_cksum)?;
+        version.record_integrity_manifest(&conn, &serde_json::to_value(&files)?)?;
+        version.record_uncompressed_size(&conn, uncompressed_size)?;
+
+        // Features using the newer syntax are listed separately in the index
+        // so that older versions of Cargo can still read the entry.
+        let (features, features2): (HashMap<_, _>, HashMap<_, _>) =
+            features.into_iter().partition(|&(_, ref enabled)| {
+                enabled
+                    .iter()
+                    .all(|feature| Crate::feature_syntax_version(feature) == 1)
+            });
+        let (features2, v) = if features2.is_empty() {
+            (None, None)
+        } else {
+            (Some(features2), Some(2))
+        };
 
-        // Register this crate in our local git repo.
+        // Register this crate in our local git repo. If the index is unavailable
+        // the entry can be queued for `git::write_pending_entries` instead, so
+        // that the uploaded files don't have to be thrown away.
         let git_crate = git::Crate {
             name: name.to_string(),
             vers: vers.to_string(),
-            cksum: hex_cksum,
+            cksum: hex_cksum.clone(),
             features,
+            features2,
             deps: git_deps,
             yanked: Some(false),
             links,
+            v,
+        };
+        let index_pending = match git::add_crate(&**req.app(), &git_crate) {
+            Ok(()) => false,
+            Err(ref e) if app.config.queue_index_writes && git::is_unavailable(&**e) => {
+                let entry = serde_json::to_value(&git_crate)?;
+                PendingIndexWrite::record(&conn, version.id, &entry)?;
+                true
+            }
+            Err(e) => {
+                return Err(e).chain_error(|| {
+                    internal(&format_args!(
+                        "could not add crate `{}` to the git repo",
+                        name
+                    ))
+                })
+            }
         };
-        git::add_crate(&**req.app(), &git_crate).chain_error(|| {
-            internal(&format_args!(
-                "could not add crate `{}` to the git repo",
-                name
-            ))
-        })?;
 
         // Now that we've come this far, we're committed!
         crate_bomb.path = None;
@@ -200,10 +375,14 @@ pub fn publish(req: &mut dyn Request) -> CargoResult<Response> {
         struct Warnings<'a> {
             invalid_categories: Vec<&'a str>,
             invalid_badges: Vec<&'a str>,
+            dependency_cycles: Vec<String>,
+            other: Vec<String>,
         }
         let warnings = Warnings {
             invalid_categories: ignored_invalid_categories,
             invalid_badges: ignored_invalid_badges,
+            dependency_cycles,
+            other: other_warnings,
         };
 
         #[derive(Serialize)]
@@ -211,14 +390,73 @@ pub fn publish(req: &mut dyn Request) -> CargoResult<Response> {
             #[serde(rename = "crate")]
             krate: EncodableCrate,
             warnings: Warnings<'a>,
+            /// Whether the index entry was queued rather than written
+            index_pending: bool,
         }
-        Ok(req.json(&R {
+        let response = serde_json::to_value(&R {
             krate: krate.minimal_encodable(&max_version, None, false, None),
             warnings,
-        }))
+            index_pending,
+        })?;
+        if let Some(ref key) = idempotency_key {
+            let fingerprint = IdempotencyKey::fingerprint(name, &vers.to_string(), &hex_cksum);
+            IdempotencyKey::record(&conn, user.id, key, &fingerprint, &response)?;
+        }
+        Ok(publish_response(req, &response))
     })
 }
 
+/// Responds to a publish with `body`, which is a 202 rather than a 200
+/// when the index entry was queued. A replayed response is answered the same
+/// way as the original.
+fn publish_response(req: &dyn Request, body: &serde_json::Value) -> Response {
+    let mut response = req.json(body);
+    if body["index_pending"].as_bool() == Some(true) {
+        response.status = (202, "Accepted");
+    }
+    response
+}
+
+/// Enforces `Config::publish_rate_limit` for the user `user_id`
+fn limit_publishes(
+    app: &App,
+    conn: &PgConnection,
+    user_id: i32,
+    window_start: NaiveDateTime,
+) -> CargoResult<()> {
+    let limit = match app.config.publish_rate_limit {
+        Some(limit) => limit,
+        None => return Ok(()),
+    };
+    if RecentPublish::count_since(conn, user_id, window_start)? >= limit {
+        return Err(too_many_requests(&format_args!(
+            "rate limit exceeded, retry later: at most {} versions can be published \
+             every {} minutes",
+            limit, app.config.publish_rate_window_minutes
+        )));
+    }
+    Ok(())
+}
+
+/// Reads the tarball of a publish request whose metadata has already been
+/// read, returning its hex checksum
+fn tarball_checksum(req: &mut dyn Request, max_upload_size: u64) -> CargoResult<String> {
+    read_le_u32(req.body())?;
+    let mut body = Vec::new();
+    if let Err(e) = LimitErrorReader::new(req.body(), max_upload_size).read_to_end(&mut body) {
+        if body.len() as u64 >= max_upload_size {
+            return Err(human(&format_args!(
+                "max upload size is: {}",
+                max_upload_size
+            )));
+        }
+        return Err(e.into());
+    }
+    let mut cksum = String::new();
+    uploaders::hash(&body).write_hex(&mut cksum)?;
+    Ok(cksum)
+}
+
 /// Used by the `krate::new` function.
 ///
 /// This function parses the JSON headers to interpret the data and validates
@@ -234,8 +472,10 @@ fn parse_new_headers(req: &mut dyn Request) -> CargoResult<(EncodableCrateUpload
     let mut json = vec![0; metadata_length as usize];
     read_fill(req.body(), &mut json)?;
     let json = String::from_utf8(json).map_err(|_| human("json body was not valid utf-8"))?;
-    let new: EncodableCrateUpload = serde_json::from_str(&json)
-        .map_err(|e| human(&format_args!("invalid upload request: {}", e)))?;
+    let new: EncodableCrateUpload = serde_json::from_str(&json).map_err(|e| {
+        let detail = invalid_dependency_req(&json).unwrap_or_else(|| e.to_string());
+        human(&format_args!("invalid upload request: {}", detail))
+    })?;
 
     // Make sure required fields are provided
     fn empty(s: Option<&String>) -> bool {
@@ -261,6 +501,115 @@ fn parse_new_headers(req: &mut dyn Request) -> CargoResult<(EncodableCrateUpload
         )));
     }
 
+    // Crates that were published before the pattern was configured can
+    // still get new versions
+    if let Some(ref pattern) = req.app().crate_name_pattern {
+        if !pattern.is_match(&new.name) {
+            let conn = req.db_conn()?;
+            let existing = Crate::by_name(&*new.name)
+                .first::<Crate>(&*conn)
+                .optional()?;
+            if existing.is_none() {
+                return Err(human(&format_args!(
+                    "crate names on this registry must match the pattern `{}`",
+                    req.app().config.crate_name_pattern.as_ref().unwrap()
+                )));
+            }
+        }
+    }
+
+    if new.changelog.as_ref().map_or(0, |s| s.len()) > MAX_CHANGELOG_LENGTH {
+        return Err(human(&format_args!(
+            "the changelog must not be longer than {} bytes",
+            MAX_CHANGELOG_LENGTH
+        )));
+    }
+
+    validate_features(&new)?;
+    validate_dependency_reqs(&new)?;
+
     let user = req.user()?;
     Ok((new, user.clone()))
 }
+
+/// Finds the first dependency in the upload request `json` whose version
+/// requirement isn't valid semver, as the error from deserializing it only
+/// names the requirement and not the dependency it belongs to.
+fn invalid_dependency_req(json: &str) -> Option<String> {
+    let value = serde_json::from_str::<serde_json::Value>(json).ok()?;
+    value
+        .get("deps")?
+        .as_array()?
+        .iter()
+        .filter_map(|dep| {
+            let req = dep.get("version_req")?.as_str()?;
+            if semver::VersionReq::parse(req).is_ok() {
+                return None;
+            }
+            let name = dep.get("name").and_then(|n| n.as_str()).unwrap_or("");
+            Some(format!(
+                "dependency `{}` has an invalid version requirement `{}`",
+                name, req
+            ))
+        }).next()
+}
+
+/// Checks that the version requirements of `new`'s dependencies aren't too
+/// long. They are stored and written to the index in their normalized form,
+/// so that's what is measured.
+fn validate_dependency_reqs(new: &EncodableCrateUpload) -> CargoResult<()> {
+    for dep in &new.deps {
+        if dep.version_req.0.to_string().len() > MAX_DEPENDENCY_REQ_LENGTH {
+            return Err(human(&format_args!(
+                "invalid upload request: the version requirement of dependency `{}` \
+                 must not be longer than {} bytes",
+                &*dep.name, MAX_DEPENDENCY_REQ_LENGTH
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Checks that the feature map of `new` isn't too large, and that the
+/// features that enable something in a dependency name one of its
+/// dependencies. The names themselves are checked while deserializing.
+fn validate_features(new: &EncodableCrateUpload) -> CargoResult<()> {
+    if new.features.len() > MAX_FEATURES {
+        return Err(human(&format_args!(
+            "invalid upload request: a version can have at most {} features",
+            MAX_FEATURES
+        )));
+    }
+    let entries = new.features.values().map(|v| v.len()).sum::<usize>();
+    if entries > MAX_FEATURE_ENTRIES {
+        return Err(human(&format_args!(
+            "invalid upload request: features can enable at most {} entries in total",
+            MAX_FEATURE_ENTRIES
+        )));
+    }
+
+    let dep_names = new
+        .deps
+        .iter()
+        .map(|dep| &***dep.explicit_name_in_toml.as_ref().unwrap_or(&dep.name))
+        .collect::<Vec<&str>>();
+    for (name, enabled) in &new.features {
+        for feature in enabled {
+            let dep = if feature.starts_with("dep:") {
+                &feature["dep:".len()..]
+            } else if let Some(slash) = feature.find('/') {
+                feature[..slash].trim_right_matches('?')
+            } else {
+                continue;
+            };
+            if !dep_names.contains(&dep) {
+                return Err(human(&format_args!(
+                    "invalid upload request: feature `{}` enables `{}`, \
+                     but `{}` is not a dependency",
+                    &**name, &**feature, dep
+                )));
+            }
+        }
+    }
+    Ok(())
+}
@@ -1,21 +1,50 @@
 //! Endpoint for searching and discovery functionality
 
+use std::collections::{HashMap, HashSet};
+
+use chrono::{Duration, Utc};
 use diesel_full_text_search::*;
+use serde_json;
+use url;
 
-use controllers::helpers::Paginate;
+use controllers::helpers::{json_with_time_format, Paginate};
 use controllers::prelude::*;
 use models::{Crate, CrateBadge, CrateVersions, OwnerKind, Version};
 use schema::*;
+use util::bad_request;
 use views::EncodableCrate;
 
 use models::krate::{canon_crate_name, ALL_COLUMNS};
 
+/// The values accepted for the `sort` parameter of `search`. `alpha` and
+/// `name` both sort by name, and `relevance` only has an effect alongside `q`.
+const SORT_OPTIONS: &[&str] = &[
+    "alpha",
+    "name",
+    "downloads",
+    "recent-downloads",
+    "recent-updates",
+    "created_at",
+    "updated_at",
+    "relevance",
+];
+
 /// Handles the `GET /crates` route.
 /// Returns a list of crates. Called in a variety of scenarios in the
 /// front end, including:
 /// - Alphabetical listing of crates
 /// - List of crates under a specific owner
 /// - Listing a user's followed crates
+/// - Browsing crates whose names start with a given `prefix`
+/// - Listing the crates with a version that depends on the crate named by
+///   `depends_on`
+/// - Searching with `q`, which matches the name anywhere as well as the other
+///   indexed fields. `letter` is ignored when `q` is given.
+///
+/// Results are ordered by `sort`, which must be one of `SORT_OPTIONS`. Crate
+/// names sort in ascending order and everything else has the largest or newest
+/// value first. Without a `sort`, crates are ordered by name, after the most
+/// relevant ones when searching with `q`.
 ///
 /// Notes:
 /// The different use cases this function covers is handled through passing
@@ -32,15 +61,283 @@ use models::krate::{canon_crate_name, ALL_COLUMNS};
 /// function out to cover the different use cases, and create unit tests
 /// for them.
 pub fn search(req: &mut dyn Request) -> CargoResult<Response> {
-    use diesel::sql_types::Bool;
-
     let conn = req.db_conn()?;
-    let (offset, limit) = req.pagination(10, 100)?;
     let params = req.query();
-    let sort = params
-        .get("sort")
-        .map(|s| &**s)
-        .unwrap_or("recent-downloads");
+    // Unlike most paginated endpoints, pages before the first are clamped
+    // to the first page instead of being rejected
+    let page = params
+        .get("page")
+        .and_then(|s| s.parse::<i64>().ok())
+        .unwrap_or(1)
+        .max(1);
+    let limit = req.per_page(10, 100)?;
+    let offset = (page - 1).saturating_mul(limit);
+
+    let data = load_crates(req, &conn, &params, offset, limit)?;
+    // The total is counted alongside the results, so a page past the end needs
+    // another query to find out how many results there are
+    let total = match data.first() {
+        Some(&(_, total)) => total,
+        None if offset > 0 => load_crates(req, &conn, &params, 0, 1)?
+            .first()
+            .map(|&(_, total)| total)
+            .unwrap_or(0),
+        None => 0,
+    };
+
+    let perfect_matches = data.iter().map(|&((_, b, _), _)| b).collect::<Vec<_>>();
+    let recent_downloads = data
+        .iter()
+        .map(|&((_, _, s), _)| s.unwrap_or(0))
+        .collect::<Vec<_>>();
+    let crates = data.into_iter().map(|((c, _, _), _)| c).collect::<Vec<_>>();
+    let crates = encode_crates(req, &conn, crates, recent_downloads, perfect_matches)?;
+
+    #[derive(Serialize)]
+    struct R {
+        crates: Vec<EncodableCrate>,
+        meta: Meta,
+    }
+    #[derive(Serialize)]
+    struct Meta {
+        total: i64,
+        page: i64,
+        per_page: i64,
+        next_page: Option<String>,
+        prev_page: Option<String>,
+    }
+
+    let next_page = if offset.saturating_add(limit) < total {
+        Some(page_link(&params, page + 1))
+    } else {
+        None
+    };
+    let prev_page = if page > 1 {
+        Some(page_link(&params, page - 1))
+    } else {
+        None
+    };
+    json_with_time_format(req, &R {
+        crates,
+        meta: Meta {
+            total,
+            page,
+            per_page: limit,
+            next_page,
+            prev_page,
+        },
+    })
+}
+
+/// Encodes crates the way `search` and `lookup` list them, along with their
+/// recent downloads and whether each is an exact match for the search.
+fn encode_crates(
+    req: &dyn Request,
+    conn: &PgConnection,
+    crates: Vec<Crate>,
+    recent_downloads: Vec<i64>,
+    perfect_matches: Vec<bool>,
+) -> CargoResult<Vec<EncodableCrate>> {
+    let versions = crates
+        .versions()
+        .load::<Version>(conn)?
+        .grouped_by(&crates)
+        .into_iter()
+        .map(|versions| Version::max(versions.into_iter().map(|v| v.num)));
+
+    let badges = CrateBadge::belonging_to(&crates)
+        .select((badges::crate_id, badges::all_columns))
+        .load::<CrateBadge>(conn)?
+        .grouped_by(&crates)
+        .into_iter()
+        .map(|badges| badges.into_iter().map(|cb| cb.badge).collect());
+
+    let new_cutoff =
+        Utc::now().naive_utc() - Duration::days(req.app().config.new_crate_window_days);
+
+    Ok(versions
+        .zip(crates)
+        .zip(perfect_matches)
+        .zip(recent_downloads)
+        .zip(badges)
+        .map(
+            |((((max_version, krate), perfect_match), recent_downloads), badges)| {
+                let is_new = krate.created_at > new_cutoff;
+                EncodableCrate {
+                    is_new: Some(is_new),
+                    ..krate.minimal_encodable(
+                        &max_version,
+                        Some(badges),
+                        perfect_match,
+                        Some(recent_downloads),
+                    )
+                }
+            },
+        ).collect())
+}
+
+/// The most crates that can be looked up by a single call to `lookup`, the
+/// same as the largest page `search` returns
+const MAX_LOOKUP_SIZE: usize = 100;
+
+/// Handles the `POST /crates/lookup` route.
+///
+/// Returns the same summary of each crate named in `ids` as `search` does,
+/// so that the frontend can show a known set of crates (such as the
+/// dependencies in a `Cargo.toml`) without fetching them one at a time.
+/// Crates are returned in the order they were asked for, and names that
+/// don't match a crate are listed under `missing` instead.
+pub fn lookup(req: &mut dyn Request) -> CargoResult<Response> {
+    use diesel::dsl::any;
+
+    #[derive(Deserialize)]
+    struct LookupRequest {
+        ids: Vec<String>,
+    }
+
+    let mut body = String::new();
+    req.body().read_to_string(&mut body)?;
+    let request: LookupRequest =
+        serde_json::from_str(&body).map_err(|_| bad_request("invalid json request"))?;
+    if request.ids.len() > MAX_LOOKUP_SIZE {
+        return Err(bad_request(&format_args!(
+            "cannot look up more than {} crates at once",
+            MAX_LOOKUP_SIZE
+        )));
+    }
+
+    let names = request
+        .ids
+        .iter()
+        .map(|name| Crate::canonical_name(name))
+        .collect::<Vec<_>>();
+    let conn = req.db_conn()?;
+    let data = crates::table
+        .left_join(recent_crate_downloads::table)
+        .select((ALL_COLUMNS, recent_crate_downloads::downloads.nullable()))
+        .filter(canon_crate_name(crates::name).eq(any(names)))
+        .load::<(Crate, Option<i64>)>(&*conn)?;
+
+    let recent_downloads = data
+        .iter()
+        .map(|&(_, s)| s.unwrap_or(0))
+        .collect::<Vec<_>>();
+    let crates = data.into_iter().map(|(c, _)| c).collect::<Vec<_>>();
+    let perfect_matches = vec![false; crates.len()];
+    let mut found = encode_crates(req, &conn, crates, recent_downloads, perfect_matches)?
+        .into_iter()
+        .map(|krate| (Crate::canonical_name(&krate.name), krate))
+        .collect::<HashMap<_, _>>();
+
+    let mut crates = Vec::new();
+    let mut missing = Vec::new();
+    let mut seen = HashSet::new();
+    for name in request.ids {
+        let canonical_name = Crate::canonical_name(&name);
+        if !seen.insert(canonical_name.clone()) {
+            continue;
+        }
+        match found.remove(&canonical_name) {
+            Some(krate) => crates.push(krate),
+            None => missing.push(name),
+        }
+    }
+
+    #[derive(Serialize)]
+    struct R {
+        crates: Vec<EncodableCrate>,
+        missing: Vec<String>,
+    }
+    json_with_time_format(req, &R { crates, missing })
+}
+
+/// The number of names `names` returns at once by default, and the most it
+/// can be asked for
+const NAMES_PER_PAGE: usize = 1000;
+const MAX_NAMES_PER_PAGE: usize = 10_000;
+
+/// How long clients and proxies may cache a page of `names`, in seconds
+const NAMES_MAX_AGE: u32 = 300;
+
+/// Handles the `GET /crate_names` route.
+///
+/// Lists the name of every crate and nothing else, in order, for tools that
+/// only need to enumerate crates. Each page links to the next one by the
+/// last name on it, given as `after`, so that a client can resume from
+/// wherever it stopped even if crates are published in the meantime.
+pub fn names(req: &mut dyn Request) -> CargoResult<Response> {
+    let limit = req.per_page(NAMES_PER_PAGE, MAX_NAMES_PER_PAGE)?;
+    let after = req.query().remove("after");
+
+    let conn = req.db_conn()?;
+    let mut query = crates::table
+        .select(crates::name)
+        .order(crates::name)
+        .limit(limit)
+        .into_boxed();
+    if let Some(ref after) = after {
+        query = query.filter(crates::name.gt(after));
+    }
+    let names = query.load::<String>(&*conn)?;
+
+    let next_page = match names.last() {
+        Some(last) if names.len() as i64 == limit => {
+            let mut link = url::form_urlencoded::Serializer::new(String::new());
+            link.append_pair("after", last);
+            link.append_pair("per_page", &limit.to_string());
+            Some(format!("?{}", link.finish()))
+        }
+        _ => None,
+    };
+
+    #[derive(Serialize)]
+    struct R {
+        names: Vec<String>,
+        meta: Meta,
+    }
+    #[derive(Serialize)]
+    struct Meta {
+        next_page: Option<String>,
+    }
+    let mut response = req.json(&R {
+        names,
+        meta: Meta { next_page },
+    });
+    // Shared caches mustn't hand out a registry that needs a login for reads
+    let visibility = if req.app().config.require_auth_for_reads {
+        "private"
+    } else {
+        "public"
+    };
+    response.headers.insert(
+        "Cache-Control".to_string(),
+        vec![format!("{}, max-age={}", visibility, NAMES_MAX_AGE)],
+    );
+    Ok(response)
+}
+
+/// Builds and runs the query for `search`. Each crate is returned along with
+/// whether it's an exact match for `q`, its recent downloads, and the total
+/// number of crates matching the query.
+fn load_crates(
+    req: &dyn Request,
+    conn: &PgConnection,
+    params: &HashMap<String, String>,
+    offset: i64,
+    limit: i64,
+) -> CargoResult<Vec<((Crate, bool, Option<i64>), i64)>> {
+    use diesel::sql_types::Bool;
+
+    // An empty `sort` is treated the same as no `sort` at all
+    let requested_sort = params.get("sort").map(|s| &**s).filter(|s| !s.is_empty());
+    let sort = requested_sort.unwrap_or("name");
+    if !SORT_OPTIONS.contains(&sort) {
+        return Err(human(&format_args!(
+            "invalid sort `{}`, expected one of: {}",
+            sort,
+            SORT_OPTIONS.join(", ")
+        )));
+    }
 
     let mut query = crates::table
         .left_join(recent_crate_downloads::table)
@@ -50,26 +347,27 @@ pub fn search(req: &mut dyn Request) -> CargoResult<Response> {
             recent_crate_downloads::downloads.nullable(),
         )).into_boxed();
 
-    if let Some(q_string) = params.get("q") {
-        if !q_string.is_empty() {
-            let sort = params.get("sort").map(|s| &**s).unwrap_or("relevance");
-            let q = plainto_tsquery(q_string);
-            query = query.filter(
-                q.matches(crates::textsearchable_index_col)
-                    .or(Crate::with_name(q_string)),
-            );
-
-            query = query.select((
-                ALL_COLUMNS,
-                Crate::with_name(q_string),
-                recent_crate_downloads::downloads.nullable(),
-            ));
-            query = query.order(Crate::with_name(q_string).desc());
-
-            if sort == "relevance" {
-                let rank = ts_rank_cd(crates::textsearchable_index_col, q);
-                query = query.then_order_by(rank.desc())
-            }
+    // An empty `q` is treated the same as no `q` at all
+    let q_string = params.get("q").filter(|q| !q.is_empty());
+    if let Some(q_string) = q_string {
+        let q = plainto_tsquery(q_string);
+        let name_pattern = format!("%{}%", escape_like(&Crate::canonical_name(q_string)));
+        query = query.filter(
+            q.matches(crates::textsearchable_index_col)
+                .or(Crate::with_name(q_string))
+                .or(canon_crate_name(crates::name).like(name_pattern)),
+        );
+
+        query = query.select((
+            ALL_COLUMNS,
+            Crate::with_name(q_string),
+            recent_crate_downloads::downloads.nullable(),
+        ));
+        query = query.order(Crate::with_name(q_string).desc());
+
+        if requested_sort.unwrap_or("relevance") == "relevance" {
+            let rank = ts_rank_cd(crates::textsearchable_index_col, q);
+            query = query.then_order_by(rank.desc())
         }
     }
 
@@ -88,6 +386,30 @@ pub fn search(req: &mut dyn Request) -> CargoResult<Response> {
         );
     }
 
+    if let Some(prefix) = params.get("prefix") {
+        if !prefix.is_empty() {
+            let pattern = format!("{}%", escape_like(&Crate::canonical_name(prefix)));
+            query = query.filter(canon_crate_name(crates::name).like(pattern));
+        }
+    }
+
+    if let Some(dep) = params.get("depends_on") {
+        query = query.filter(
+            crates::id.eq_any(
+                dependencies::table
+                    .inner_join(versions::table)
+                    .select(versions::crate_id)
+                    .filter(
+                        dependencies::crate_id.eq_any(
+                            crates::table
+                                .select(crates::id)
+                                .filter(Crate::with_name(dep)),
+                        ),
+                    ),
+            ),
+        );
+    }
+
     if let Some(kw) = params.get("keyword") {
         query = query.filter(
             crates::id.eq_any(
@@ -97,16 +419,15 @@ pub fn search(req: &mut dyn Request) -> CargoResult<Response> {
                     .filter(::lower(keywords::keyword).eq(::lower(kw))),
             ),
         );
-    } else if let Some(letter) = params.get("letter") {
-        let pattern = format!(
-            "{}%",
-            letter
-                .chars()
-                .next()
-                .unwrap()
-                .to_lowercase()
-                .collect::<String>()
-        );
+    } else if let Some(letter) = params
+        .get("letter")
+        .filter(|_| q_string.is_none())
+        .and_then(|l| l.chars().next())
+    {
+        // Match against the canonical name so that `letter=a` also lists
+        // crates whose display name starts with `A`.
+        let letter = Crate::canonical_name(&letter.to_string());
+        let pattern = format!("{}%", escape_like(&letter));
         query = query.filter(canon_crate_name(crates::name).like(pattern));
     } else if let Some(user_id) = params.get("user_id").and_then(|s| s.parse::<i32>().ok()) {
         query = query.filter(
@@ -144,65 +465,46 @@ pub fn search(req: &mut dyn Request) -> CargoResult<Response> {
         query = query.then_order_by(recent_crate_downloads::downloads.desc().nulls_last())
     } else if sort == "recent-updates" {
         query = query.order(crates::updated_at.desc());
+    } else if sort == "created_at" {
+        query = query.then_order_by(crates::created_at.desc())
+    } else if sort == "updated_at" {
+        query = query.then_order_by(crates::updated_at.desc())
     } else {
-        query = query.then_order_by(crates::name.asc())
+        query = query.then_order_by(canon_crate_name(crates::name).asc())
     }
 
     // The database query returns a tuple within a tuple, with the root
     // tuple containing 3 items.
     let data = query
         .paginate(limit, offset)
-        .load::<((Crate, bool, Option<i64>), i64)>(&*conn)?;
-    let total = data.first().map(|&(_, t)| t).unwrap_or(0);
-    let perfect_matches = data.iter().map(|&((_, b, _), _)| b).collect::<Vec<_>>();
-    let recent_downloads = data
+        .load::<((Crate, bool, Option<i64>), i64)>(conn)?;
+    Ok(data)
+}
+
+/// Returns the query string for another page of the same search
+fn page_link(params: &HashMap<String, String>, page: i64) -> String {
+    let mut params = params
         .iter()
-        .map(|&((_, _, s), _)| s.unwrap_or(0))
+        .filter(|&(name, _)| name != "page")
         .collect::<Vec<_>>();
-    let crates = data.into_iter().map(|((c, _, _), _)| c).collect::<Vec<_>>();
-
-    let versions = crates
-        .versions()
-        .load::<Version>(&*conn)?
-        .grouped_by(&crates)
-        .into_iter()
-        .map(|versions| Version::max(versions.into_iter().map(|v| v.num)));
-
-    let badges = CrateBadge::belonging_to(&crates)
-        .select((badges::crate_id, badges::all_columns))
-        .load::<CrateBadge>(&conn)?
-        .grouped_by(&crates)
-        .into_iter()
-        .map(|badges| badges.into_iter().map(|cb| cb.badge).collect());
-
-    let crates = versions
-        .zip(crates)
-        .zip(perfect_matches)
-        .zip(recent_downloads)
-        .zip(badges)
-        .map(
-            |((((max_version, krate), perfect_match), recent_downloads), badges)| {
-                krate.minimal_encodable(
-                    &max_version,
-                    Some(badges),
-                    perfect_match,
-                    Some(recent_downloads),
-                )
-            },
-        ).collect();
-
-    #[derive(Serialize)]
-    struct R {
-        crates: Vec<EncodableCrate>,
-        meta: Meta,
-    }
-    #[derive(Serialize)]
-    struct Meta {
-        total: i64,
+    params.sort();
+    let mut link = url::form_urlencoded::Serializer::new(String::new());
+    for (name, value) in params {
+        link.append_pair(name, value);
     }
+    link.append_pair("page", &page.to_string());
+    format!("?{}", link.finish())
+}
 
-    Ok(req.json(&R {
-        crates,
-        meta: Meta { total },
-    }))
+/// Escapes the characters that have a special meaning in a `LIKE` pattern so
+/// that user input only ever matches literally.
+fn escape_like(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if c == '\\' || c == '%' || c == '_' {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
 }
@@ -24,6 +24,7 @@ mod prelude {
         fn query(&self) -> HashMap<String, String>;
         fn wants_json(&self) -> bool;
         fn pagination(&self, default: usize, max: usize) -> CargoResult<(i64, i64)>;
+        fn per_page(&self, default: usize, max: usize) -> CargoResult<i64>;
     }
 
     impl<'a> RequestUtils for dyn Request + 'a {
@@ -55,12 +56,21 @@ mod prelude {
         }
 
         fn pagination(&self, default: usize, max: usize) -> CargoResult<(i64, i64)> {
-            let query = self.query();
-            let page = query
+            let page = self
+                .query()
                 .get("page")
                 .and_then(|s| s.parse::<usize>().ok())
                 .unwrap_or(1);
-            let limit = query
+            let limit = self.per_page(default, max)?;
+            if page == 0 {
+                return Err(human("page indexing starts from 1, page 0 is invalid"));
+            }
+            Ok(((page - 1) as i64 * limit, limit))
+        }
+
+        fn per_page(&self, default: usize, max: usize) -> CargoResult<i64> {
+            let limit = self
+                .query()
                 .get("per_page")
                 .and_then(|s| s.parse::<usize>().ok())
                 .unwrap_or(default);
@@ -70,16 +80,14 @@ mod prelude {
                     max
                 )));
             }
-            if page == 0 {
-                return Err(human("page indexing starts from 1, page 0 is invalid"));
-            }
-            Ok((((page - 1) * limit) as i64, limit as i64))
+            Ok(limit as i64)
         }
     }
 }
 
 pub mod helpers;
 
+pub mod admin;
 pub mod category;
 pub mod crate_owner_invitation;
 pub mod keyword;
@@ -46,17 +46,26 @@ pub fn me(req: &mut dyn Request) -> CargoResult<Response> {
 }
 
 /// Handles the `GET /me/updates` route.
+///
+/// Pre-release versions are only included for the crates the user asked to
+/// hear about them for when following.
 pub fn updates(req: &mut dyn Request) -> CargoResult<Response> {
-    use diesel::dsl::any;
+    use diesel::dsl::{any, sql};
+    use diesel::sql_types::Bool;
 
     let user = req.user()?;
     let (offset, limit) = req.pagination(10, 100)?;
     let conn = req.db_conn()?;
 
     let followed_crates = Follow::belonging_to(user).select(follows::crate_id);
+    let prerelease_crates = Follow::belonging_to(user)
+        .filter(follows::prereleases)
+        .select(follows::crate_id);
+    let is_stable = sql::<Bool>("split_part(versions.num, '+', 1) NOT LIKE '%-%'");
     let data = versions::table
         .inner_join(crates::table)
         .filter(crates::id.eq(any(followed_crates)))
+        .filter(crates::id.eq(any(prerelease_crates)).or(is_stable))
         .order(versions::created_at.desc())
         .select((versions::all_columns, crates::name))
         .paginate(limit, offset)
@@ -67,10 +76,11 @@ pub fn updates(req: &mut dyn Request) -> CargoResult<Response> {
         .map(|&(_, count)| count > offset + limit)
         .unwrap_or(false);
 
-    let versions = data
+    let mut versions = data
         .into_iter()
         .map(|((version, crate_name), _)| version.encodable(&crate_name))
-        .collect();
+        .collect::<Vec<_>>();
+    Version::add_provenance_links(&conn, &mut versions)?;
 
     #[derive(Serialize)]
     struct R {
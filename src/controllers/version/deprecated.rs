@@ -5,6 +5,8 @@
 //! period of time to ensure there are no external users of an endpoint before
 //! it is removed.
 
+use std::slice;
+
 use controllers::prelude::*;
 
 use url;
@@ -26,14 +28,15 @@ pub fn index(req: &mut dyn Request) -> CargoResult<Response> {
         .filter_map(|(ref a, ref b)| if *a == "ids[]" { b.parse().ok() } else { None })
         .collect::<Vec<i32>>();
 
-    let versions = versions::table
+    let mut versions = versions::table
         .inner_join(crates::table)
         .select((versions::all_columns, crates::name))
         .filter(versions::id.eq(any(ids)))
         .load::<(Version, String)>(&*conn)?
         .into_iter()
         .map(|(version, crate_name)| version.encodable(&crate_name))
-        .collect();
+        .collect::<Vec<_>>();
+    Version::add_provenance_links(&conn, &mut versions)?;
 
     #[derive(Serialize)]
     struct R {
@@ -65,11 +68,18 @@ pub fn show(req: &mut dyn Request) -> CargoResult<Response> {
         }
     };
 
+    let conn = req.db_conn()?;
+    let changelog_excerpt = Version::changelog_excerpts(&conn, &[version.id])?.remove(&version.id);
+
+    let mut version = EncodableVersion {
+        changelog_excerpt,
+        ..version.encodable(&krate.name)
+    };
+    Version::add_provenance_links(&conn, slice::from_mut(&mut version))?;
+
     #[derive(Serialize)]
     struct R {
         version: EncodableVersion,
     }
-    Ok(req.json(&R {
-        version: version.encodable(&krate.name),
-    }))
+    Ok(req.json(&R { version }))
 }
@@ -2,10 +2,19 @@
 //!
 //! Crate level functionality is located in `krate::downloads`.
 
+use std::cmp;
+
+use controllers::krate::metadata::resolve_version;
 use controllers::prelude::*;
 
 use chrono::{Duration, NaiveDate, Utc};
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use semver;
+use url::percent_encoding::{utf8_percent_encode, DEFAULT_ENCODE_SET};
+use url::Url;
 
+use app::App;
+use util::errors::NotFound;
 use Replica;
 
 use models::{Crate, VersionDownload};
@@ -14,37 +23,105 @@ use views::EncodableVersionDownload;
 
 use super::version_and_crate;
 
+/// How many seconds a redirect to a crate file may be cached for. Downloads
+/// that are served from a cached redirect aren't counted.
+const DOWNLOAD_REDIRECT_MAX_AGE: i64 = 60 * 60;
+
 /// Handles the `GET /crates/:crate_id/:version/download` route.
 /// This returns a URL to the location where the crate is stored.
 pub fn download(req: &mut dyn Request) -> CargoResult<Response> {
     let crate_name = &req.params()["crate_id"];
     let version = &req.params()["version"];
+    // Both end up in the redirect URL, which is built without consulting the
+    // database when downloads aren't counted
+    if !Crate::valid_name(crate_name)
+        || version.contains("..")
+        || semver::Version::parse(version).is_err()
+    {
+        return Err(NotFound.into());
+    }
+    download_version(req, crate_name, version)
+}
 
+/// Handles the `GET /crates/:crate_id/download` route.
+///
+/// Downloads the highest version that isn't yanked and satisfies the `req`
+/// query parameter, as picked by the `resolve` route, or returns a 404 if
+/// there isn't one.
+pub fn download_matching(req: &mut dyn Request) -> CargoResult<Response> {
+    let (version, krate) = resolve_version(req)?;
+    download_version(req, &krate.name, &version.num.to_string())
+}
+
+fn download_version(
+    req: &dyn Request,
+    crate_name: &str,
+    version: &str,
+) -> CargoResult<Response> {
     // If we are a mirror, ignore failure to update download counts.
     // API-only mirrors won't have any crates in their database, and
     // incrementing the download count will look up the crate in the
     // database. Mirrors just want to pass along a redirect URL.
-    if req.app().config.mirror == Replica::ReadOnlyMirror {
+    if !req.app().config.count_downloads {
+        // Counting has been disabled, so the database isn't touched at all.
+    } else if req.app().config.mirror == Replica::ReadOnlyMirror {
         let _ = increment_download_counts(req, crate_name, version);
     } else {
         increment_download_counts(req, crate_name, version)?;
     }
 
-    let redirect_url = req
-        .app()
-        .config
-        .uploader
-        .crate_location(crate_name, version)
-        .ok_or_else(|| human("crate files not found"))?;
+    let config = &req.app().config;
+    let redirect_url = match config.signed_download_expiry_secs {
+        Some(expires_in) => config
+            .uploader
+            .signed_crate_location(crate_name, version, expires_in),
+        None => config.uploader.crate_location(crate_name, version),
+    }.ok_or_else(|| human("crate files not found"))?;
+    // A presigned URL is only meant for whoever asked for it, and stops
+    // working once it expires
+    let cache_control = match config.signed_download_expiry_secs {
+        Some(expires_in) => format!(
+            "private, max-age={}",
+            cmp::min(expires_in / 2, DOWNLOAD_REDIRECT_MAX_AGE)
+        ),
+        None => format!("public, max-age={}", DOWNLOAD_REDIRECT_MAX_AGE),
+    };
 
     if req.wants_json() {
+        // Mirrors may not know about the crate, in which case nothing but
+        // the URL is returned
+        let (git_ref, source_url, yanked) = match download_details(req, crate_name, version) {
+            Ok(details) => (details.git_ref, details.source_url, Some(details.yanked)),
+            Err(_) if req.app().config.mirror == Replica::ReadOnlyMirror => (None, None, None),
+            Err(e) => return Err(e),
+        };
+
         #[derive(Serialize)]
-        struct R {
+        struct R<'a> {
             url: String,
+            name: &'a str,
+            version: &'a str,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            git_ref: Option<String>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            source_url: Option<String>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            yanked: Option<bool>,
         }
-        Ok(req.json(&R { url: redirect_url }))
+        Ok(req.json(&R {
+            url: redirect_url,
+            name: crate_name,
+            version,
+            git_ref,
+            source_url,
+            yanked,
+        }))
     } else {
-        Ok(req.redirect(redirect_url))
+        let mut response = req.redirect(redirect_url);
+        response
+            .headers
+            .insert("Cache-Control".to_string(), vec![cache_control]);
+        Ok(response)
     }
 }
 
@@ -55,17 +132,89 @@ fn increment_download_counts(
 ) -> CargoResult<()> {
     use self::versions::dsl::*;
 
+    let app = req.app();
     let conn = req.db_conn()?;
+    if let Some(version_id) = app.version_ids.get(crate_name, version) {
+        // In its own transaction, so that the connection can still be used to
+        // look the version up again if this fails
+        match conn.transaction(|| count_download(app, version_id, &conn)) {
+            Err(DieselError::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, _)) => {
+                // The version has been deleted since it was cached
+                app.version_ids.remove(crate_name, version);
+            }
+            result => return Ok(result?),
+        }
+    }
+
     let version_id = versions
         .select(id)
         .filter(crate_id.eq_any(Crate::by_name(crate_name).select(crates::id)))
         .filter(num.eq(version))
         .first(&*conn)?;
-
-    VersionDownload::create_or_increment(version_id, &conn)?;
+    app.version_ids.insert(crate_name, version, version_id);
+    count_download(app, version_id, &conn)?;
     Ok(())
 }
 
+fn count_download(app: &App, version_id: i32, conn: &PgConnection) -> QueryResult<()> {
+    if app.config.buffer_downloads {
+        app.pending_downloads.increment(version_id);
+        Ok(())
+    } else {
+        VersionDownload::create_or_increment(version_id, conn)
+    }
+}
+
+/// Repository hosts that show the files at a ref at `<repository>/tree/<ref>`
+const TREE_URL_HOSTS: &[&str] = &["github.com", "gitlab.com"];
+
+struct DownloadDetails {
+    /// The VCS ref the version was published from, if the client sent it
+    git_ref: Option<String>,
+    /// A link to the crate's repository at that ref, if the repository is on
+    /// a host that's known to link to refs this way
+    source_url: Option<String>,
+    /// Yanked versions can still be downloaded, so that builds with a lockfile
+    /// that refers to them keep working
+    yanked: bool,
+}
+
+fn download_details(
+    req: &dyn Request,
+    crate_name: &str,
+    version: &str,
+) -> CargoResult<DownloadDetails> {
+    let conn = req.db_conn()?;
+    let (repository, git_ref, yanked) = versions::table
+        .inner_join(crates::table)
+        .select((crates::repository, versions::git_ref, versions::yanked))
+        .filter(Crate::with_name(crate_name))
+        .filter(versions::num.eq(version))
+        .first::<(Option<String>, Option<String>, bool)>(&*conn)?;
+
+    let source_url = match (repository, git_ref.as_ref()) {
+        (Some(repository), Some(git_ref)) => source_url(&repository, git_ref),
+        _ => None,
+    };
+    Ok(DownloadDetails {
+        git_ref,
+        source_url,
+        yanked,
+    })
+}
+
+fn source_url(repository: &str, git_ref: &str) -> Option<String> {
+    let repository = repository.trim_right_matches('/');
+    let repository = repository.trim_right_matches(".git");
+    let url = Url::parse(repository).ok()?;
+    match url.host_str() {
+        Some(host) if TREE_URL_HOSTS.contains(&host) => {}
+        _ => return None,
+    }
+    let git_ref = utf8_percent_encode(git_ref, DEFAULT_ENCODE_SET);
+    Some(format!("{}/tree/{}", repository, git_ref))
+}
+
 /// Handles the `GET /crates/:crate_id/:version/downloads` route.
 pub fn downloads(req: &mut dyn Request) -> CargoResult<Response> {
     let (version, _) = version_and_crate(req)?;
@@ -4,8 +4,15 @@
 //! index or cached metadata which was extracted (client side) from the
 //! `Cargo.toml` file.
 
+use std::collections::HashMap;
+
+use serde_json;
+
 use controllers::prelude::*;
+use util::bad_request;
 
+use models::krate::canon_crate_name;
+use models::Crate;
 use schema::*;
 use views::{EncodableDependency, EncodablePublicUser};
 
@@ -17,7 +24,7 @@ use super::version_and_crate;
 ///
 /// In addition to returning cached data from the index, this returns
 /// fields for `id`, `version_id`, and `downloads` (which appears to always
-/// be 0)
+/// be 0). Returns a 404 if the crate doesn't have the version.
 pub fn dependencies(req: &mut dyn Request) -> CargoResult<Response> {
     let (version, _) = version_and_crate(req)?;
     let conn = req.db_conn()?;
@@ -61,3 +68,125 @@ pub fn authors(req: &mut dyn Request) -> CargoResult<Response> {
         meta: Meta { names },
     }))
 }
+
+/// Handles the `GET /crates/:crate_id/:version/provenance` route.
+///
+/// Returns the provenance statement supplied when this version was published,
+/// exactly as it was uploaded. It is not verified by crates.io.
+pub fn provenance(req: &mut dyn Request) -> CargoResult<Response> {
+    let (version, _) = version_and_crate(req)?;
+    let conn = req.db_conn()?;
+    let provenance = version.provenance(&conn)?;
+
+    #[derive(Serialize)]
+    struct R {
+        provenance: serde_json::Value,
+    }
+    Ok(req.json(&R { provenance }))
+}
+
+/// Handles the `GET /crates/:crate_id/:version/changelog` route.
+///
+/// Returns the changelog published with this version in the `changelog` field,
+/// exactly as it was uploaded. Versions published without one return a 404.
+pub fn changelog(req: &mut dyn Request) -> CargoResult<Response> {
+    let (version, _) = version_and_crate(req)?;
+    let conn = req.db_conn()?;
+    let changelog = version.changelog(&conn)?;
+
+    #[derive(Serialize)]
+    struct R {
+        changelog: String,
+    }
+    Ok(req.json(&R { changelog }))
+}
+
+/// Handles the `GET /crates/:crate_id/:version/integrity` route.
+///
+/// Lists the files in the `.crate` file along with their sizes and SHA256
+/// checksums, as found when the version was published. Versions published
+/// before these were recorded return a 404.
+pub fn integrity(req: &mut dyn Request) -> CargoResult<Response> {
+    let (version, _) = version_and_crate(req)?;
+    let conn = req.db_conn()?;
+    let files = version.integrity_manifest(&conn)?;
+
+    #[derive(Serialize)]
+    struct R {
+        files: serde_json::Value,
+    }
+    Ok(req.json(&R { files }))
+}
+
+/// Handles the `POST /checksums/verify` route.
+///
+/// Returns the stored checksums for a batch of crate versions in a single
+/// request, so that verifiers don't have to look them up one at a time.
+/// Versions that don't exist, or that were published before checksums were
+/// recorded, are returned with a `cksum` of `null`.
+pub fn verify_checksums(req: &mut dyn Request) -> CargoResult<Response> {
+    use diesel::dsl::any;
+
+    const MAX_BATCH_SIZE: usize = 100;
+
+    #[derive(Deserialize)]
+    struct CrateVersion {
+        name: String,
+        version: String,
+    }
+    #[derive(Deserialize)]
+    struct VerifyRequest {
+        crates: Vec<CrateVersion>,
+    }
+
+    let mut body = String::new();
+    req.body().read_to_string(&mut body)?;
+    let request: VerifyRequest =
+        serde_json::from_str(&body).map_err(|_| bad_request("invalid json request"))?;
+    if request.crates.len() > MAX_BATCH_SIZE {
+        return Err(bad_request(&format_args!(
+            "cannot verify more than {} crates at once",
+            MAX_BATCH_SIZE
+        )));
+    }
+
+    let names = request
+        .crates
+        .iter()
+        .map(|c| Crate::canonical_name(&c.name))
+        .collect::<Vec<_>>();
+    let conn = req.db_conn()?;
+    let stored = versions::table
+        .inner_join(crates::table)
+        .filter(canon_crate_name(crates::name).eq(any(names)))
+        .select((crates::name, versions::num, versions::checksum))
+        .load::<(String, String, Option<String>)>(&*conn)?
+        .into_iter()
+        .map(|(name, num, cksum)| ((Crate::canonical_name(&name), num), cksum))
+        .collect::<HashMap<_, _>>();
+
+    #[derive(Serialize)]
+    struct Checksum {
+        name: String,
+        version: String,
+        cksum: Option<String>,
+    }
+    let checksums = request
+        .crates
+        .into_iter()
+        .map(|c| {
+            let key = (Crate::canonical_name(&c.name), c.version);
+            let cksum = stored.get(&key).and_then(|cksum| cksum.clone());
+            Checksum {
+                name: c.name,
+                version: key.1,
+                cksum,
+            }
+        }).collect();
+
+    #[derive(Serialize)]
+    struct R {
+        checksums: Vec<Checksum>,
+    }
+    Ok(req.json(&R { checksums }))
+}
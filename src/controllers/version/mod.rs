@@ -5,27 +5,24 @@ pub mod yank;
 
 use super::prelude::*;
 use semver;
+use util::errors::NotFound;
 
 use models::{Crate, CrateVersions, Version};
 use schema::versions;
 
+/// Loads the version of a crate named in the route, returning a 404 if either
+/// doesn't exist
 fn version_and_crate(req: &mut dyn Request) -> CargoResult<(Version, Crate)> {
     let crate_name = &req.params()["crate_id"];
     let semver = &req.params()["version"];
     if semver::Version::parse(semver).is_err() {
-        return Err(human(&format_args!("invalid semver: {}", semver)));
+        return Err(NotFound.into());
     };
     let conn = req.db_conn()?;
     let krate = Crate::by_name(crate_name).first::<Crate>(&*conn)?;
     let version = krate
         .all_versions()
         .filter(versions::num.eq(semver))
-        .first(&*conn)
-        .map_err(|_| {
-            human(&format_args!(
-                "crate `{}` does not have a version `{}`",
-                crate_name, semver
-            ))
-        })?;
+        .first(&*conn)?;
     Ok((version, krate))
 }
@@ -1,14 +1,12 @@
 //! Endpoints for yanking and unyanking specific versions of crates
 
+use controllers::helpers::limit_crate_changes;
 use controllers::prelude::*;
 
-use diesel;
-
 use git;
 use util::errors::CargoError;
 
-use models::Rights;
-use schema::*;
+use models::{CrateChange, PendingIndexWrite, Rights};
 
 use super::version_and_crate;
 
@@ -41,13 +39,21 @@ fn modify_yank(req: &mut dyn Request, yanked: bool) -> CargoResult<Response> {
     }
 
     if version.yanked != yanked {
+        // Yanks that don't change anything don't count towards the rate limit
         conn.transaction::<_, Box<dyn CargoError>, _>(|| {
-            diesel::update(&version)
-                .set(versions::yanked.eq(yanked))
-                .execute(&*conn)?;
-            git::yank(&**req.app(), &krate.name, &version.num, yanked)?;
+            limit_crate_changes(req, &conn, user.id, krate.id, CrateChange::Yank)?;
+            // A version whose index entry is still queued isn't in the index
+            // yet. The entry picks up the new `yanked` flag when it's written.
+            let pending = PendingIndexWrite::lock(&conn, version.id)?;
+            version.set_yanked(&conn, yanked)?;
+            if pending.is_none() {
+                git::yank(&**req.app(), &krate.name, &version.num, yanked)?;
+            }
             Ok(())
         })?;
+        req.app()
+            .version_ids
+            .remove(&krate.name, &version.num.to_string());
     }
 
     #[derive(Serialize)]
@@ -1,17 +1,20 @@
 use std::collections::HashMap;
 use std::env;
 use std::fs::{self, File};
+use std::io;
 use std::io::prelude::*;
 use std::path::{Path, PathBuf};
 
+use diesel::prelude::*;
 use git2;
 use semver;
 use serde_json;
 
 use app::App;
-use util::{internal, CargoResult};
+use util::{internal, CargoError, CargoResult};
 
-use models::DependencyKind;
+use models::{DependencyKind, PendingIndexWrite};
+use schema::versions;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Crate {
@@ -20,9 +23,16 @@ pub struct Crate {
     pub deps: Vec<Dependency>,
     pub cksum: String,
     pub features: HashMap<String, Vec<String>>,
+    /// Features using the namespaced (`dep:`) or weak (`?/`) dependency syntax. These are kept
+    /// apart from `features` so that older versions of Cargo can still parse the entry.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub features2: Option<HashMap<String, Vec<String>>>,
     pub yanked: Option<bool>,
     #[serde(default)]
     pub links: Option<String>,
+    /// The index format version, only present when `features2` is used.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub v: Option<u32>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -64,8 +74,18 @@ pub fn add_crate(app: &App, krate: &Crate) -> CargoResult<()> {
         if fs::metadata(&dst).is_ok() {
             File::open(&dst).and_then(|mut f| f.read_to_string(&mut prev))?;
         }
-        let s = serde_json::to_string(krate).unwrap();
-        let new = prev + &s;
+
+        // An earlier attempt to add this version may have been committed
+        // without being pushed, in which case its line is replaced
+        let mut new = String::new();
+        for line in prev.lines().filter(|line| !line.is_empty()) {
+            let existing = serde_json::from_str::<Crate>(line).ok();
+            if existing.map_or(true, |existing| existing.vers != krate.vers) {
+                new.push_str(line);
+                new.push('\n');
+            }
+        }
+        new.push_str(&serde_json::to_string(krate).unwrap());
         let mut f = File::create(&dst)?;
         f.write_all(new.as_bytes())?;
         f.write_all(b"\n")?;
@@ -77,6 +97,91 @@ pub fn add_crate(app: &App, krate: &Crate) -> CargoResult<()> {
     })
 }
 
+/// Returns whether `err` means that the index couldn't be read or written,
+/// for example because the checkout is unusable or the remote can't be reached.
+pub fn is_unavailable(err: &dyn CargoError) -> bool {
+    err.is::<git2::Error>() || err.is::<io::Error>()
+}
+
+/// Writes the index entries that were queued while the index was unavailable,
+/// oldest first, returning how many were written. Entries that still can't be
+/// written are logged and left queued for the next attempt.
+///
+/// Every server runs this, so each entry is claimed with a row lock that the
+/// others skip. An entry that's already in the index, for example because its
+/// row couldn't be deleted after it was written, is only removed from the
+/// queue. Versions yanked in the meantime are added to the index as yanked.
+pub fn write_pending_entries(app: &App) -> CargoResult<usize> {
+    let conn = app.diesel_database.get()?;
+    let mut written = 0;
+    let mut failed = Vec::new();
+    loop {
+        let attempt = conn.transaction::<_, Box<dyn CargoError>, _>(|| {
+            let write = match PendingIndexWrite::claim_next(&conn, &failed)? {
+                Some(write) => write,
+                None => return Ok(None),
+            };
+            let result = write_pending_entry(app, &conn, &write);
+            if result.is_ok() {
+                write.delete(&conn)?;
+            }
+            Ok(Some((write.version_id, result)))
+        })?;
+        match attempt {
+            None => return Ok(written),
+            Some((_, Ok(()))) => written += 1,
+            Some((version_id, Err(e))) => {
+                println!(
+                    "failed to write the pending index entry of version {}: {}",
+                    version_id, e
+                );
+                failed.push(version_id);
+            }
+        }
+    }
+}
+
+fn write_pending_entry(
+    app: &App,
+    conn: &PgConnection,
+    write: &PendingIndexWrite,
+) -> CargoResult<()> {
+    let mut krate = serde_json::from_value::<Crate>(write.entry.clone())?;
+    let yanked = versions::table
+        .find(write.version_id)
+        .select(versions::yanked)
+        .first::<bool>(conn)?;
+    krate.yanked = Some(yanked);
+    let already_written = crate_entries(app, &krate.name)?
+        .iter()
+        .any(|entry| entry.vers == krate.vers && entry.yanked == krate.yanked);
+    if !already_written {
+        add_crate(app, &krate)?;
+    }
+    Ok(())
+}
+
+/// Reads all of the entries for a crate from the local checkout of the index.
+/// A crate without an index file has no entries.
+pub fn crate_entries(app: &App, krate: &str) -> CargoResult<Vec<Crate>> {
+    let repo = app.git_repo.lock().unwrap();
+    let dst = index_file(repo.workdir().unwrap(), krate);
+
+    let mut contents = String::new();
+    match File::open(&dst) {
+        Ok(mut f) => f.read_to_string(&mut contents)?,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    contents
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            serde_json::from_str::<Crate>(line)
+                .map_err(|_| internal(&format_args!("couldn't decode: `{}`", line)))
+        }).collect()
+}
+
 /// Yanks or unyanks a crate version. This requires finding the index
 /// file, deserlialise the crate from JSON, change the yank boolean to
 /// `true` or `false`, write all the lines back out, and commit and
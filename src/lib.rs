@@ -31,6 +31,7 @@ extern crate log;
 extern crate oauth2;
 extern crate openssl;
 extern crate rand;
+extern crate regex;
 extern crate reqwest;
 extern crate s3;
 extern crate scheduled_thread_pool;
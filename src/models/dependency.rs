@@ -1,3 +1,5 @@
+use std::collections::HashSet;
+
 use diesel::prelude::*;
 use semver;
 
@@ -8,6 +10,10 @@ use models::{Crate, Version};
 use schema::*;
 use views::{EncodableCrateDependency, EncodableDependency};
 
+/// How many crates `find_cycles` follows the dependencies of before giving
+/// up on finding a way back to the crate being published
+const MAX_CYCLE_DEPTH: usize = 2;
+
 #[derive(Identifiable, Associations, Debug, Queryable, QueryableByName)]
 #[belongs_to(Version)]
 #[belongs_to(Crate)]
@@ -138,6 +144,87 @@ pub fn add_dependencies(
     Ok(git_deps)
 }
 
+/// Looks for dependency cycles that would be created by publishing version
+/// `vers` of `krate` with `deps`, returning a description of each one.
+///
+/// This is only a shallow check of the versions already in the database: a
+/// cycle is only found if it passes through at most `MAX_CYCLE_DEPTH` other
+/// crates, each of which has a version that isn't yanked that matches the
+/// requirement on it. Dev-dependencies are ignored, as Cargo allows those to
+/// form cycles.
+pub fn find_cycles(
+    conn: &PgConnection,
+    krate: &Crate,
+    vers: &semver::Version,
+    deps: &[EncodableCrateDependency],
+) -> CargoResult<Vec<String>> {
+    // Each path is the names of the crates depended on so far, along with
+    // the id of the last one and the requirement on it
+    let mut paths = Vec::new();
+    for dep in deps {
+        if let Some(DependencyKind::Dev) = dep.kind {
+            continue;
+        }
+        let (id, name) = crates::table
+            .select((crates::id, crates::name))
+            .filter(crates::name.eq(&*dep.name))
+            .first::<(i32, String)>(conn)?;
+        paths.push((vec![krate.name.clone(), name], id, dep.version_req.0.clone()));
+    }
+
+    let mut cycles = Vec::new();
+    let mut visited = HashSet::new();
+    for _ in 0..MAX_CYCLE_DEPTH {
+        let mut next_paths = Vec::new();
+        for (path, crate_id, version_req) in paths {
+            if !visited.insert((crate_id, version_req.to_string())) {
+                continue;
+            }
+
+            let version_ids = versions::table
+                .select((versions::id, versions::num))
+                .filter(versions::crate_id.eq(crate_id))
+                .filter(versions::yanked.eq(false))
+                .load::<(i32, String)>(conn)?
+                .into_iter()
+                .filter(|&(_, ref num)| {
+                    semver::Version::parse(num)
+                        .map(|num| version_req.matches(&num))
+                        .unwrap_or(false)
+                }).map(|(id, _)| id)
+                .collect::<Vec<_>>();
+
+            let next_deps = dependencies::table
+                .inner_join(crates::table)
+                .select((crates::id, crates::name, dependencies::req))
+                .filter(dependencies::version_id.eq_any(version_ids))
+                .filter(dependencies::kind.ne(DependencyKind::Dev as i32))
+                .distinct()
+                .load::<(i32, String, String)>(conn)?;
+            for (id, name, req) in next_deps {
+                let req = match semver::VersionReq::parse(&req) {
+                    Ok(req) => req,
+                    Err(_) => continue,
+                };
+                let mut next_path = path.clone();
+                next_path.push(name);
+                if id == krate.id {
+                    if req.matches(vers) {
+                        cycles.push(next_path.join(" -> "));
+                    }
+                } else {
+                    next_paths.push((next_path, id, req));
+                }
+            }
+        }
+        paths = next_paths;
+    }
+
+    cycles.sort();
+    cycles.dedup();
+    Ok(cycles)
+}
+
 use diesel::deserialize::{self, FromSql};
 use diesel::pg::Pg;
 use diesel::sql_types::Integer;
@@ -1,8 +1,12 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
 use chrono::NaiveDate;
 use diesel;
 use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
 
-use models::Version;
+use models::{Crate, Version};
 use schema::version_downloads;
 use views::EncodableVersionDownload;
 
@@ -19,16 +23,21 @@ pub struct VersionDownload {
 
 impl VersionDownload {
     pub fn create_or_increment(version: i32, conn: &PgConnection) -> QueryResult<()> {
+        VersionDownload::create_or_add(version, 1, conn)
+    }
+
+    /// Counts `amount` downloads of `version` towards today's downloads
+    pub fn create_or_add(version: i32, amount: i32, conn: &PgConnection) -> QueryResult<()> {
         use self::version_downloads::dsl::*;
 
         // We only update the counter for *today* (the default date),
         // nothing else. We have lots of other counters, but they're
         // all updated later on via the update-downloads script.
         diesel::insert_into(version_downloads)
-            .values(version_id.eq(version))
+            .values((version_id.eq(version), downloads.eq(amount)))
             .on_conflict((version_id, date))
             .do_update()
-            .set(downloads.eq(downloads + 1))
+            .set(downloads.eq(downloads + amount))
             .execute(conn)?;
         Ok(())
     }
@@ -42,3 +51,136 @@ impl VersionDownload {
         }
     }
 }
+
+/// Downloads that have been served but not yet written to `version_downloads`,
+/// used when `Config::buffer_downloads` is set
+#[derive(Debug, Default)]
+pub struct PendingDownloads {
+    counts: Mutex<HashMap<i32, i32>>,
+}
+
+impl PendingDownloads {
+    /// Counts a download of `version`, to be written by the next `flush`
+    pub fn increment(&self, version: i32) {
+        *self.counts.lock().unwrap().entry(version).or_insert(0) += 1;
+    }
+
+    /// Writes out the downloads counted since the last flush.
+    ///
+    /// Downloads are counted towards the day they are written on, so those
+    /// counted just before midnight may end up in the next day. Downloads of
+    /// versions that have been deleted since are dropped, and the versions
+    /// are removed from `version_ids`, as buffered downloads are served
+    /// without checking the version still exists. If writing fails otherwise,
+    /// the downloads that weren't written are kept for the next flush.
+    pub fn flush(&self, conn: &PgConnection, version_ids: &VersionIdCache) -> QueryResult<()> {
+        let counts = self.counts.lock().unwrap().drain().collect::<Vec<_>>();
+        for (i, &(version, amount)) in counts.iter().enumerate() {
+            // In its own transaction, so that the rest are still written if
+            // this one fails
+            match conn.transaction(|| VersionDownload::create_or_add(version, amount, conn)) {
+                Ok(()) => {}
+                Err(DieselError::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, _)) => {
+                    version_ids.remove_id(version);
+                }
+                Err(e) => {
+                    let mut pending = self.counts.lock().unwrap();
+                    for &(version, amount) in &counts[i..] {
+                        *pending.entry(version).or_insert(0) += amount;
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The ids of recently downloaded versions, keyed by the canonical name of
+/// their crate and their number, so that counting a download doesn't have to
+/// look its version up every time.
+///
+/// Holds at most `Config::download_cache_size` versions, forgetting the least
+/// recently downloaded one first.
+#[derive(Debug)]
+pub struct VersionIdCache {
+    capacity: usize,
+    inner: Mutex<VersionIdCacheInner>,
+}
+
+#[derive(Debug, Default)]
+struct VersionIdCacheInner {
+    /// Each version's id, and when it was last used
+    ids: HashMap<(String, String), (i32, u64)>,
+    /// The key of each version by when it was last used
+    by_last_use: BTreeMap<u64, (String, String)>,
+    clock: u64,
+}
+
+impl VersionIdCache {
+    pub fn new(capacity: usize) -> Self {
+        VersionIdCache {
+            capacity,
+            inner: Mutex::new(VersionIdCacheInner::default()),
+        }
+    }
+
+    pub fn get(&self, crate_name: &str, num: &str) -> Option<i32> {
+        let key = (Crate::canonical_name(crate_name), num.to_string());
+        let mut inner = self.inner.lock().unwrap();
+        let inner = &mut *inner;
+        inner.clock += 1;
+        let entry = inner.ids.get_mut(&key)?;
+        inner.by_last_use.remove(&entry.1);
+        entry.1 = inner.clock;
+        inner.by_last_use.insert(inner.clock, key);
+        Some(entry.0)
+    }
+
+    pub fn insert(&self, crate_name: &str, num: &str, version_id: i32) {
+        if self.capacity == 0 {
+            return;
+        }
+        let key = (Crate::canonical_name(crate_name), num.to_string());
+        let mut inner = self.inner.lock().unwrap();
+        let inner = &mut *inner;
+        inner.clock += 1;
+        let now = inner.clock;
+        if let Some((_, last_use)) = inner.ids.insert(key.clone(), (version_id, now)) {
+            inner.by_last_use.remove(&last_use);
+        }
+        inner.by_last_use.insert(now, key);
+        while inner.ids.len() > self.capacity {
+            let oldest = *inner.by_last_use.keys().next().unwrap();
+            let key = inner.by_last_use.remove(&oldest).unwrap();
+            inner.ids.remove(&key);
+        }
+    }
+
+    /// Forgets a version, such as when it was yanked or deleted
+    pub fn remove(&self, crate_name: &str, num: &str) {
+        let key = (Crate::canonical_name(crate_name), num.to_string());
+        let mut inner = self.inner.lock().unwrap();
+        let inner = &mut *inner;
+        if let Some((_, last_use)) = inner.ids.remove(&key) {
+            inner.by_last_use.remove(&last_use);
+        }
+    }
+
+    /// Forgets the version with the id `version_id`, such as when it turns
+    /// out to have been deleted
+    pub fn remove_id(&self, version_id: i32) {
+        let mut inner = self.inner.lock().unwrap();
+        let inner = &mut *inner;
+        let key = inner
+            .ids
+            .iter()
+            .find(|&(_, &(id, _))| id == version_id)
+            .map(|(key, _)| key.clone());
+        if let Some(key) = key {
+            if let Some((_, last_use)) = inner.ids.remove(&key) {
+                inner.by_last_use.remove(&last_use);
+            }
+        }
+    }
+}
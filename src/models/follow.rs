@@ -8,4 +8,6 @@ use schema::follows;
 pub struct Follow {
     pub user_id: i32,
     pub crate_id: i32,
+    /// Whether the user also wants to hear about pre-release versions
+    pub prereleases: bool,
 }
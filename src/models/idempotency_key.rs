@@ -0,0 +1,95 @@
+use chrono::NaiveDateTime;
+use diesel;
+use diesel::dsl::*;
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use serde_json;
+
+use schema::idempotency_keys;
+use util::{unprocessable_entity, CargoError, CargoResult};
+
+/// The number of hours during which a retried request with the same
+/// `Idempotency-Key` gets the original response back
+pub const IDEMPOTENCY_KEY_TTL_HOURS: i32 = 24;
+
+/// The response to a completed request, stored under the `Idempotency-Key`
+/// header the client sent with it
+#[derive(Clone, Debug, Queryable)]
+pub struct IdempotencyKey {
+    pub user_id: i32,
+    pub key: String,
+    pub response: serde_json::Value,
+    pub created_at: NaiveDateTime,
+    /// Identifies what the request published, so that a key reused for
+    /// something else isn't mistaken for a retry
+    pub fingerprint: String,
+}
+
+impl IdempotencyKey {
+    /// Returns the fingerprint of a publish of version `vers` of crate `name`
+    /// with a tarball whose hex checksum is `cksum`
+    pub fn fingerprint(name: &str, vers: &str, cksum: &str) -> String {
+        format!("{} {} {}", name, vers, cksum)
+    }
+
+    /// Returns the request recorded by `user_id` under `key`, unless it has expired
+    pub fn find(
+        conn: &PgConnection,
+        user_id: i32,
+        key: &str,
+    ) -> QueryResult<Option<IdempotencyKey>> {
+        idempotency_keys::table
+            .find((user_id, key))
+            .filter(idempotency_keys::created_at.gt(now - IDEMPOTENCY_KEY_TTL_HOURS.hours()))
+            .first(conn)
+            .optional()
+    }
+
+    /// Records the response to a request made by `user_id` with `key`, along
+    /// with the request's fingerprint.
+    ///
+    /// Expired keys are deleted at the same time, so that the table doesn't
+    /// grow without bound and an expired key can be reused.
+    ///
+    /// Two requests using a new key at the same time both miss it in `find`.
+    /// Whichever is recorded second fails here with `reused_error`. If both
+    /// publish the same version, the second usually fails earlier instead,
+    /// with the error for a version that's already uploaded.
+    pub fn record(
+        conn: &PgConnection,
+        user_id: i32,
+        key: &str,
+        fingerprint: &str,
+        response: &serde_json::Value,
+    ) -> CargoResult<()> {
+        diesel::delete(
+            idempotency_keys::table
+                .filter(idempotency_keys::created_at.le(now - IDEMPOTENCY_KEY_TTL_HOURS.hours())),
+        ).execute(conn)?;
+
+        conn.transaction(|| {
+            diesel::insert_into(idempotency_keys::table)
+                .values((
+                    idempotency_keys::user_id.eq(user_id),
+                    idempotency_keys::key.eq(key),
+                    idempotency_keys::response.eq(response),
+                    idempotency_keys::fingerprint.eq(fingerprint),
+                )).execute(conn)
+                .map_err(|e| match e {
+                    DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+                        IdempotencyKey::reused_error()
+                    }
+                    e => e.into(),
+                })?;
+            Ok(())
+        })
+    }
+
+    /// The error for a key that was already used for a different request
+    pub fn reused_error() -> Box<dyn CargoError> {
+        unprocessable_entity(
+            "this Idempotency-Key was already used to publish a different crate, \
+             version or tarball",
+        )
+    }
+}
@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use chrono::{NaiveDate, NaiveDateTime};
 use diesel;
 use diesel::associations::Identifiable;
@@ -5,6 +7,7 @@ use diesel::pg::Pg;
 use diesel::prelude::*;
 use license_exprs;
 use semver;
+use serde_json;
 use url::Url;
 
 use app::App;
@@ -138,33 +141,6 @@ impl<'a> NewCrate<'a> {
     }
 
     fn validate(&mut self, license_file: Option<&'a str>) -> CargoResult<()> {
-        fn validate_url(url: Option<&str>, field: &str) -> CargoResult<()> {
-            let url = match url {
-                Some(s) => s,
-                None => return Ok(()),
-            };
-            let url = Url::parse(url)
-                .map_err(|_| human(&format_args!("`{}` is not a valid url: `{}`", field, url)))?;
-            match &url.scheme()[..] {
-                "http" | "https" => {}
-                s => {
-                    return Err(human(&format_args!(
-                        "`{}` has an invalid url \
-                         scheme: `{}`",
-                        field, s
-                    )))
-                }
-            }
-            if url.cannot_be_a_base() {
-                return Err(human(&format_args!(
-                    "`{}` must have relative scheme \
-                     data: {}",
-                    field, url
-                )));
-            }
-            Ok(())
-        }
-
         validate_url(self.homepage, "homepage")?;
         validate_url(self.documentation, "documentation")?;
         validate_url(self.repository, "repository")?;
@@ -236,6 +212,92 @@ impl<'a> NewCrate<'a> {
     }
 }
 
+fn validate_url(url: Option<&str>, field: &str) -> CargoResult<()> {
+    let url = match url {
+        Some(s) => s,
+        None => return Ok(()),
+    };
+    let url = Url::parse(url)
+        .map_err(|_| human(&format_args!("`{}` is not a valid url: `{}`", field, url)))?;
+    match &url.scheme()[..] {
+        "http" | "https" => {}
+        s => {
+            return Err(human(&format_args!(
+                "`{}` has an invalid url \
+                 scheme: `{}`",
+                field, s
+            )))
+        }
+    }
+    if url.cannot_be_a_base() {
+        return Err(human(&format_args!(
+            "`{}` must have relative scheme \
+             data: {}",
+            field, url
+        )));
+    }
+    Ok(())
+}
+
+/// Custom metadata is limited to this many entries
+pub const MAX_CUSTOM_METADATA_ENTRIES: usize = 50;
+/// Keys and values in custom metadata may be at most this many bytes long
+pub const MAX_CUSTOM_METADATA_LENGTH: usize = 256;
+
+/// Custom metadata has to be a flat object of strings, and is size-bounded
+fn validate_custom_metadata(metadata: Option<&serde_json::Value>) -> CargoResult<()> {
+    let entries = match metadata {
+        Some(metadata) => metadata
+            .as_object()
+            .ok_or_else(|| human("`custom_metadata` must be an object"))?,
+        None => return Ok(()),
+    };
+    if entries.len() > MAX_CUSTOM_METADATA_ENTRIES {
+        return Err(human(&format_args!(
+            "`custom_metadata` can have at most {} entries",
+            MAX_CUSTOM_METADATA_ENTRIES
+        )));
+    }
+    for (key, value) in entries {
+        let value = value.as_str().ok_or_else(|| {
+            human(&format_args!(
+                "`custom_metadata` values must be strings, `{}` is not",
+                key
+            ))
+        })?;
+        if key.len() > MAX_CUSTOM_METADATA_LENGTH || value.len() > MAX_CUSTOM_METADATA_LENGTH {
+            return Err(human(&format_args!(
+                "`custom_metadata` keys and values can be at most {} bytes long",
+                MAX_CUSTOM_METADATA_LENGTH
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// The metadata of a crate that its owners can change without publishing a
+/// new version. Fields that are `None` are left as they are.
+#[derive(AsChangeset, Default, Debug)]
+#[table_name = "crates"]
+pub struct CrateUpdate<'a> {
+    pub description: Option<&'a str>,
+    pub homepage: Option<&'a str>,
+    pub documentation: Option<&'a str>,
+    pub repository: Option<&'a str>,
+    /// Replaces all of the crate's custom metadata
+    pub custom_metadata: Option<serde_json::Value>,
+}
+
+impl<'a> CrateUpdate<'a> {
+    fn is_empty(&self) -> bool {
+        self.description.is_none()
+            && self.homepage.is_none()
+            && self.documentation.is_none()
+            && self.repository.is_none()
+            && self.custom_metadata.is_none()
+    }
+}
+
 impl Crate {
     pub fn with_name(name: &str) -> WithName<'_> {
         canon_crate_name(crates::name).eq(canon_crate_name(name))
@@ -253,6 +315,12 @@ impl Crate {
         crates::table.select(ALL_COLUMNS)
     }
 
+    /// Returns the canonical form of a crate name, matching the `canon_crate_name` SQL
+    /// function that crate name uniqueness is enforced on.
+    pub fn canonical_name(name: &str) -> String {
+        name.to_lowercase().replace('-', "_")
+    }
+
     pub fn valid_name(name: &str) -> bool {
         let under_max_length = name.chars().take(MAX_NAME_LENGTH + 1).count() <= MAX_NAME_LENGTH;
         Crate::valid_ident(name) && under_max_length
@@ -274,20 +342,43 @@ impl Crate {
             && name.chars().all(|c| c.is_ascii())
     }
 
+    /// Validates a single entry of a feature's list of enabled features.
+    ///
+    /// Besides plain feature names and `dep/feature`, this accepts the
+    /// namespaced (`dep:name`) and weak dependency (`dep?/feature`) syntax
+    /// introduced by newer versions of Cargo.
     pub fn valid_feature(name: &str) -> bool {
+        if name.starts_with("dep:") {
+            return Crate::valid_feature_name(&name["dep:".len()..]);
+        }
         let mut parts = name.split('/');
-        match parts.next() {
-            Some(part) if !Crate::valid_feature_name(part) => return false,
+        let (first, weak) = match parts.next() {
+            Some(part) if part.ends_with('?') => (&part[..part.len() - 1], true),
+            Some(part) => (part, false),
             None => return false,
-            _ => {}
+        };
+        if !Crate::valid_feature_name(first) {
+            return false;
         }
         match parts.next() {
             Some(part) if !Crate::valid_feature_name(part) => return false,
+            // A weak dependency feature has to name a feature of the dependency
+            None if weak => return false,
             _ => {}
         }
         parts.next().is_none()
     }
 
+    /// Returns the feature syntax version required to understand a feature
+    /// entry: `2` for namespaced or weak dependency features, `1` otherwise.
+    pub fn feature_syntax_version(name: &str) -> i32 {
+        if name.starts_with("dep:") || name.contains("?/") {
+            2
+        } else {
+            1
+        }
+    }
+
     pub fn minimal_encodable(
         self,
         max_version: &semver::Version,
@@ -339,6 +430,7 @@ impl Crate {
 
         EncodableCrate {
             id: name.clone(),
+            slug: Crate::canonical_name(&name),
             name: name.clone(),
             updated_at,
             created_at,
@@ -352,6 +444,9 @@ impl Crate {
             documentation,
             homepage,
             exact_match,
+            is_new: None,
+            has_no_dependencies: None,
+            custom_metadata: None,
             description,
             repository,
             links: EncodableCrateLinks {
@@ -393,6 +488,38 @@ impl Crate {
         }
     }
 
+    /// Updates the metadata of this crate, returning the crate as it is now
+    /// stored
+    pub fn update_metadata(
+        &self,
+        conn: &PgConnection,
+        changes: &CrateUpdate,
+    ) -> CargoResult<Crate> {
+        validate_url(changes.homepage, "homepage")?;
+        validate_url(changes.documentation, "documentation")?;
+        validate_url(changes.repository, "repository")?;
+        validate_custom_metadata(changes.custom_metadata.as_ref())?;
+        if changes.is_empty() {
+            return Ok(self.clone());
+        }
+
+        diesel::update(self)
+            .set(changes)
+            .returning(ALL_COLUMNS)
+            .get_result(conn)
+            .map_err(Into::into)
+    }
+
+    /// The registry specific key-value pairs that the owners have attached to
+    /// this crate. These aren't public.
+    pub fn custom_metadata(&self, conn: &PgConnection) -> CargoResult<HashMap<String, String>> {
+        let metadata = crates::table
+            .find(self.id)
+            .select(crates::custom_metadata)
+            .first::<serde_json::Value>(conn)?;
+        Ok(serde_json::from_value(metadata)?)
+    }
+
     pub fn max_version(&self, conn: &PgConnection) -> CargoResult<semver::Version> {
         use schema::versions::dsl::*;
 
@@ -514,6 +641,25 @@ impl Crate {
 
         Ok(rows.records_and_total())
     }
+
+    /// Returns the features that the newest versions of other crates enable
+    /// when depending on this one, along with how many crates enable each
+    pub fn feature_usage(&self, conn: &PgConnection) -> QueryResult<Vec<FeatureUsage>> {
+        use diesel::sql_query;
+        use diesel::sql_types::Integer;
+
+        sql_query(include_str!("krate_feature_usage.sql"))
+            .bind::<Integer, _>(self.id)
+            .load(conn)
+    }
+}
+
+#[derive(Debug, QueryableByName)]
+pub struct FeatureUsage {
+    #[sql_type = "::diesel::sql_types::Text"]
+    pub feature: String,
+    #[sql_type = "::diesel::sql_types::BigInt"]
+    pub dependents: i64,
 }
 
 use diesel::sql_types::{Date, Text};
@@ -524,6 +670,13 @@ sql_function!(fn to_char(a: Date, b: Text) -> Text);
 mod tests {
     use models::Crate;
 
+    #[test]
+    fn canonical_name_lowercases_and_replaces_hyphens() {
+        assert_eq!(Crate::canonical_name("foo"), "foo");
+        assert_eq!(Crate::canonical_name("Foo-Bar"), "foo_bar");
+        assert_eq!(Crate::canonical_name("foo_bar"), "foo_bar");
+    }
+
     #[test]
     fn documentation_blocked_no_url_provided() {
         assert_eq!(Crate::remove_blocked_documentation_urls(None), None);
@@ -0,0 +1,33 @@
+-- Each feature of $1 along with the number of crates whose newest version
+-- enables it. Leaving default features on counts as enabling `default`.
+SELECT feature, COUNT(DISTINCT crate_id) AS dependents FROM (
+    SELECT
+    newest.crate_id,
+    unnest(
+        CASE WHEN dependencies.default_features
+        THEN array_append(dependencies.features, 'default')
+        ELSE dependencies.features
+        END
+    ) AS feature
+    -- Only the newest version of each crate counts, whether or not it still
+    -- depends on $1
+    FROM (
+        SELECT DISTINCT ON (crate_id) id, crate_id
+        FROM versions
+        WHERE NOT yanked
+        AND crate_id = ANY(
+            SELECT versions.crate_id
+            FROM versions
+            INNER JOIN dependencies
+            ON dependencies.version_id = versions.id
+            WHERE dependencies.crate_id = $1
+        )
+        ORDER BY crate_id, to_semver_no_prerelease(num) DESC NULLS LAST
+    ) newest
+    -- A version can depend on $1 more than once, e.g. as both a normal and a
+    -- dev dependency, which the DISTINCT above merges
+    INNER JOIN dependencies
+      ON dependencies.version_id = newest.id
+    WHERE dependencies.crate_id = $1
+) enabled
+GROUP BY feature
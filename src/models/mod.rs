@@ -2,17 +2,21 @@ pub use self::badge::{Badge, CrateBadge, MaintenanceStatus};
 pub use self::category::{Category, CrateCategory, NewCategory};
 pub use self::crate_owner_invitation::{CrateOwnerInvitation, NewCrateOwnerInvitation};
 pub use self::dependency::{Dependency, DependencyKind, ReverseDependency};
-pub use self::download::VersionDownload;
+pub use self::download::{PendingDownloads, VersionDownload, VersionIdCache};
 pub use self::email::{Email, NewEmail};
 pub use self::follow::Follow;
+pub use self::idempotency_key::IdempotencyKey;
 pub use self::keyword::{CrateKeyword, Keyword};
-pub use self::krate::{Crate, CrateDownload, CrateVersions, NewCrate};
+pub use self::krate::{Crate, CrateDownload, CrateUpdate, CrateVersions, NewCrate};
 pub use self::owner::{CrateOwner, Owner, OwnerKind};
+pub use self::pending_index_write::PendingIndexWrite;
+pub use self::recent_crate_change::{CrateChange, RecentCrateChange};
+pub use self::recent_publish::RecentPublish;
 pub use self::rights::Rights;
 pub use self::team::{NewTeam, Team};
 pub use self::token::ApiToken;
 pub use self::user::{NewUser, User};
-pub use self::version::{NewVersion, Version};
+pub use self::version::{NewVersion, Version, MAX_CHANGELOG_LENGTH};
 
 pub mod helpers;
 
@@ -23,9 +27,13 @@ pub mod dependency;
 mod download;
 mod email;
 mod follow;
+mod idempotency_key;
 mod keyword;
 pub mod krate;
 mod owner;
+mod pending_index_write;
+mod recent_crate_change;
+mod recent_publish;
 mod rights;
 mod team;
 mod token;
@@ -0,0 +1,69 @@
+use chrono::NaiveDateTime;
+use diesel;
+use diesel::prelude::*;
+use diesel::sql_types::{Array, Integer};
+use serde_json;
+
+use schema::pending_index_writes;
+
+/// An index entry for a published version that couldn't be written because
+/// the index was unavailable at the time
+#[derive(Clone, Debug, Queryable, QueryableByName)]
+#[table_name = "pending_index_writes"]
+pub struct PendingIndexWrite {
+    pub version_id: i32,
+    pub entry: serde_json::Value,
+    pub created_at: NaiveDateTime,
+}
+
+impl PendingIndexWrite {
+    /// Queues `entry` to be written to the index for `version_id`
+    pub fn record(
+        conn: &PgConnection,
+        version_id: i32,
+        entry: &serde_json::Value,
+    ) -> QueryResult<usize> {
+        diesel::insert_into(pending_index_writes::table)
+            .values((
+                pending_index_writes::version_id.eq(version_id),
+                pending_index_writes::entry.eq(entry),
+            )).execute(conn)
+    }
+
+    /// Claims the oldest queued write that isn't in `skip` and that no other
+    /// transaction has claimed. The claim is held until the current
+    /// transaction ends, so this must be called inside one.
+    pub fn claim_next(
+        conn: &PgConnection,
+        skip: &[i32],
+    ) -> QueryResult<Option<PendingIndexWrite>> {
+        diesel::sql_query(
+            "SELECT * FROM pending_index_writes \
+             WHERE version_id <> ALL($1) \
+             ORDER BY created_at \
+             LIMIT 1 \
+             FOR UPDATE SKIP LOCKED",
+        ).bind::<Array<Integer>, _>(skip)
+        .get_result(conn)
+        .optional()
+    }
+
+    /// Returns the queued write for `version_id`, if there is one, waiting
+    /// for any transaction that's writing it to finish first. The row stays
+    /// locked until the current transaction ends.
+    pub fn lock(
+        conn: &PgConnection,
+        version_id: i32,
+    ) -> QueryResult<Option<PendingIndexWrite>> {
+        pending_index_writes::table
+            .find(version_id)
+            .for_update()
+            .first(conn)
+            .optional()
+    }
+
+    /// Removes this write from the queue once it has been written
+    pub fn delete(&self, conn: &PgConnection) -> QueryResult<usize> {
+        diesel::delete(pending_index_writes::table.find(self.version_id)).execute(conn)
+    }
+}
@@ -0,0 +1,96 @@
+use chrono::NaiveDateTime;
+use diesel;
+use diesel::prelude::*;
+use diesel::sql_types::Integer;
+
+use schema::recent_crate_changes;
+
+/// The kinds of changes to a crate that are rate limited separately
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CrateChange {
+    /// Updating the crate's metadata
+    Update,
+    /// Yanking or unyanking one of its versions
+    Yank,
+    /// Adding or removing owners
+    Owners,
+}
+
+impl CrateChange {
+    fn operation(self) -> &'static str {
+        match self {
+            CrateChange::Update => "update",
+            CrateChange::Yank => "yank",
+            CrateChange::Owners => "owners",
+        }
+    }
+}
+
+/// A change made to a crate by a user, kept for as long as it counts towards
+/// their rate limit for that crate
+#[derive(Clone, Debug, Queryable)]
+pub struct RecentCrateChange {
+    pub id: i32,
+    pub user_id: i32,
+    pub crate_id: i32,
+    pub operation: String,
+    pub created_at: NaiveDateTime,
+}
+
+impl RecentCrateChange {
+    /// Waits until no other transaction is counting or recording changes by
+    /// `user_id` to `crate_id`, and keeps them from doing so until the
+    /// current transaction ends
+    pub fn lock(conn: &PgConnection, user_id: i32, crate_id: i32) -> QueryResult<()> {
+        diesel::sql_query("SELECT pg_advisory_xact_lock($1, $2)")
+            .bind::<Integer, _>(user_id)
+            .bind::<Integer, _>(crate_id)
+            .execute(conn)?;
+        Ok(())
+    }
+
+    /// Returns the number of changes of this kind `user_id` has made to
+    /// `crate_id` since `since`
+    pub fn count_since(
+        conn: &PgConnection,
+        user_id: i32,
+        crate_id: i32,
+        change: CrateChange,
+        since: NaiveDateTime,
+    ) -> QueryResult<i64> {
+        recent_crate_changes::table
+            .filter(recent_crate_changes::user_id.eq(user_id))
+            .filter(recent_crate_changes::crate_id.eq(crate_id))
+            .filter(recent_crate_changes::operation.eq(change.operation()))
+            .filter(recent_crate_changes::created_at.gt(since))
+            .count()
+            .get_result(conn)
+    }
+
+    /// Records a change by `user_id` to `crate_id`.
+    ///
+    /// Their changes to the crate from before `since` are deleted at the same
+    /// time, as they no longer count towards the rate limit.
+    pub fn record(
+        conn: &PgConnection,
+        user_id: i32,
+        crate_id: i32,
+        change: CrateChange,
+        since: NaiveDateTime,
+    ) -> QueryResult<()> {
+        diesel::delete(
+            recent_crate_changes::table
+                .filter(recent_crate_changes::user_id.eq(user_id))
+                .filter(recent_crate_changes::crate_id.eq(crate_id))
+                .filter(recent_crate_changes::created_at.le(since)),
+        ).execute(conn)?;
+
+        diesel::insert_into(recent_crate_changes::table)
+            .values((
+                recent_crate_changes::user_id.eq(user_id),
+                recent_crate_changes::crate_id.eq(crate_id),
+                recent_crate_changes::operation.eq(change.operation()),
+            )).execute(conn)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,60 @@
+use chrono::NaiveDateTime;
+use diesel;
+use diesel::prelude::*;
+use diesel::sql_types::BigInt;
+
+use schema::recent_publishes;
+
+/// A version published by a user, kept for as long as it counts towards
+/// their publish rate limit
+#[derive(Clone, Debug, Queryable)]
+pub struct RecentPublish {
+    pub id: i32,
+    pub user_id: i32,
+    pub created_at: NaiveDateTime,
+}
+
+impl RecentPublish {
+    /// Waits until no other transaction is counting or recording publishes by
+    /// `user_id`, and keeps them from doing so until the current transaction
+    /// ends.
+    ///
+    /// This lock uses Postgres' single key form, which doesn't overlap with
+    /// the two keys of `RecentCrateChange::lock`.
+    pub fn lock(conn: &PgConnection, user_id: i32) -> QueryResult<()> {
+        diesel::sql_query("SELECT pg_advisory_xact_lock($1)")
+            .bind::<BigInt, _>(i64::from(user_id))
+            .execute(conn)?;
+        Ok(())
+    }
+
+    /// Returns the number of versions `user_id` has published since `since`
+    pub fn count_since(
+        conn: &PgConnection,
+        user_id: i32,
+        since: NaiveDateTime,
+    ) -> QueryResult<i64> {
+        recent_publishes::table
+            .filter(recent_publishes::user_id.eq(user_id))
+            .filter(recent_publishes::created_at.gt(since))
+            .count()
+            .get_result(conn)
+    }
+
+    /// Records a publish by `user_id`.
+    ///
+    /// Their publishes from before `since` are deleted at the same time, as
+    /// they no longer count towards the rate limit.
+    pub fn record(conn: &PgConnection, user_id: i32, since: NaiveDateTime) -> QueryResult<()> {
+        diesel::delete(
+            recent_publishes::table
+                .filter(recent_publishes::user_id.eq(user_id))
+                .filter(recent_publishes::created_at.le(since)),
+        ).execute(conn)?;
+
+        diesel::insert_into(recent_publishes::table)
+            .values(recent_publishes::user_id.eq(user_id))
+            .execute(conn)?;
+        Ok(())
+    }
+}
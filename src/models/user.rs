@@ -154,6 +154,11 @@ impl User {
         Ok(best)
     }
 
+    /// Whether this user is allowed to use the `/admin` routes
+    pub fn is_admin(&self, app: &App) -> bool {
+        app.config.admin_gh_ids.contains(&self.gh_id)
+    }
+
     /// Converts this `User` model into an `EncodablePrivateUser` for JSON serialization.
     pub fn encodable_private(
         self,
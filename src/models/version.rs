@@ -7,12 +7,18 @@ use semver;
 use serde_json;
 
 use license_exprs;
-use util::{human, CargoResult};
+use util::{human, CargoError, CargoResult};
 
 use models::{Crate, Dependency};
 use schema::*;
 use views::{EncodableVersion, EncodableVersionLinks};
 
+/// The longest changelog that can be published with a version, in bytes
+pub const MAX_CHANGELOG_LENGTH: usize = 64 * 1024;
+
+/// The most characters of a changelog included in the version metadata
+const CHANGELOG_EXCERPT_LENGTH: usize = 200;
+
 // Queryable has a custom implementation below
 #[derive(Clone, Identifiable, Associations, Debug, Queryable)]
 #[belongs_to(Crate)]
@@ -27,6 +33,13 @@ pub struct Version {
     pub yanked: bool,
     pub license: Option<String>,
     pub crate_size: Option<i32>,
+    pub feature_syntax_version: i32,
+    pub checksum: Option<String>,
+    pub git_ref: Option<String>,
+    pub uncompressed_size: Option<i64>,
+    /// The user who published this version, not known for versions published
+    /// before it was recorded
+    pub published_by: Option<i32>,
 }
 
 #[derive(Insertable, Debug)]
@@ -37,6 +50,8 @@ pub struct NewVersion {
     features: serde_json::Value,
     license: Option<String>,
     crate_size: Option<i32>,
+    feature_syntax_version: i32,
+    published_by: Option<i32>,
 }
 
 impl Version {
@@ -51,6 +66,8 @@ impl Version {
             yanked,
             license,
             crate_size,
+            checksum,
+            uncompressed_size,
             ..
         } = self;
         let num = num.to_string();
@@ -70,8 +87,16 @@ impl Version {
                 dependencies: format!("/api/v1/crates/{}/{}/dependencies", crate_name, num),
                 version_downloads: format!("/api/v1/crates/{}/{}/downloads", crate_name, num),
                 authors: format!("/api/v1/crates/{}/{}/authors", crate_name, num),
+                provenance: None,
+                integrity: format!("/api/v1/crates/{}/{}/integrity", crate_name, num),
+                changelog: format!("/api/v1/crates/{}/{}/changelog", crate_name, num),
             },
             crate_size,
+            uncompressed_size,
+            cksum: checksum,
+            download_url: None,
+            changelog_excerpt: None,
+            published_by: None,
         }
     }
 
@@ -100,6 +125,144 @@ impl Version {
             })
     }
 
+    /// Marks this version as yanked or unyanked. This only updates the
+    /// database, the index has to be updated separately.
+    pub fn set_yanked(&self, conn: &PgConnection, yanked: bool) -> QueryResult<usize> {
+        diesel::update(self)
+            .set(versions::yanked.eq(yanked))
+            .execute(conn)
+    }
+
+    /// Stores the hex encoded SHA256 checksum of the uploaded `.crate` file
+    pub fn record_checksum(&self, conn: &PgConnection, cksum: &str) -> QueryResult<usize> {
+        diesel::update(self)
+            .set(versions::checksum.eq(cksum))
+            .execute(conn)
+    }
+
+    /// Stores the provenance statement supplied by the publisher. The
+    /// document is kept as-is and is not verified.
+    pub fn record_provenance(
+        &self,
+        conn: &PgConnection,
+        document: &serde_json::Value,
+    ) -> QueryResult<usize> {
+        diesel::insert_into(provenance::table)
+            .values((
+                provenance::version_id.eq(self.id),
+                provenance::document.eq(document),
+            )).execute(conn)
+    }
+
+    /// Links each of the given versions that was published with a provenance
+    /// statement to it
+    pub fn add_provenance_links(
+        conn: &PgConnection,
+        versions: &mut [EncodableVersion],
+    ) -> QueryResult<()> {
+        let ids = versions.iter().map(|v| v.id).collect::<Vec<_>>();
+        let with_provenance = provenance::table
+            .filter(provenance::version_id.eq_any(ids))
+            .select(provenance::version_id)
+            .load::<i32>(conn)?;
+        for version in versions.iter_mut().filter(|v| with_provenance.contains(&v.id)) {
+            version.links.provenance = Some(format!(
+                "/api/v1/crates/{}/{}/provenance",
+                version.krate, version.num
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns the provenance statement recorded at publish
+    pub fn provenance(&self, conn: &PgConnection) -> QueryResult<serde_json::Value> {
+        provenance::table
+            .find(self.id)
+            .select(provenance::document)
+            .first(conn)
+    }
+
+    /// Stores the changelog supplied by the publisher, along with an excerpt
+    /// of it for the version metadata
+    pub fn record_changelog(&self, conn: &PgConnection, changelog: &str) -> QueryResult<usize> {
+        diesel::insert_into(version_changelogs::table)
+            .values((
+                version_changelogs::version_id.eq(self.id),
+                version_changelogs::changelog.eq(changelog),
+                version_changelogs::excerpt.eq(changelog_excerpt(changelog)),
+            )).execute(conn)
+    }
+
+    /// Returns the changelog recorded at publish
+    pub fn changelog(&self, conn: &PgConnection) -> QueryResult<String> {
+        version_changelogs::table
+            .find(self.id)
+            .select(version_changelogs::changelog)
+            .first(conn)
+    }
+
+    /// Returns the changelog excerpts of the given versions, keyed by version
+    /// id. Versions published without a changelog are left out.
+    pub fn changelog_excerpts(
+        conn: &PgConnection,
+        version_ids: &[i32],
+    ) -> QueryResult<HashMap<i32, String>> {
+        Ok(version_changelogs::table
+            .filter(version_changelogs::version_id.eq_any(version_ids))
+            .select((version_changelogs::version_id, version_changelogs::excerpt))
+            .load::<(i32, String)>(conn)?
+            .into_iter()
+            .collect())
+    }
+
+    /// Stores the list of files contained in the uploaded `.crate` file,
+    /// along with their sizes and SHA256 checksums
+    pub fn record_integrity_manifest(
+        &self,
+        conn: &PgConnection,
+        files: &serde_json::Value,
+    ) -> QueryResult<usize> {
+        diesel::insert_into(integrity_manifests::table)
+            .values((
+                integrity_manifests::version_id.eq(self.id),
+                integrity_manifests::files.eq(files),
+            )).execute(conn)
+    }
+
+    /// Returns the integrity manifest recorded at publish
+    pub fn integrity_manifest(&self, conn: &PgConnection) -> QueryResult<serde_json::Value> {
+        integrity_manifests::table
+            .find(self.id)
+            .select(integrity_manifests::files)
+            .first(conn)
+    }
+
+    /// Whether `git_ref` looks like a branch or tag name or a commit hash.
+    /// It ends up in links to the repository, so anything that could change
+    /// what such a link points to, like whitespace, `?` or `#`, is rejected.
+    pub fn valid_git_ref(git_ref: &str) -> bool {
+        !git_ref.is_empty()
+            && !git_ref.starts_with('/')
+            && !git_ref.contains("..")
+            && git_ref
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-_./+".contains(c))
+    }
+
+    /// Stores the VCS ref this version was published from
+    pub fn record_git_ref(&self, conn: &PgConnection, git_ref: &str) -> QueryResult<usize> {
+        diesel::update(self)
+            .set(versions::git_ref.eq(git_ref))
+            .execute(conn)
+    }
+
+    /// Stores the total size of the files in the version's tarball
+    pub fn record_uncompressed_size(&self, conn: &PgConnection, size: u64) -> QueryResult<usize> {
+        diesel::update(self)
+            .set(versions::uncompressed_size.eq(size as i64))
+            .execute(conn)
+    }
+
     pub fn record_readme_rendering(&self, conn: &PgConnection) -> QueryResult<usize> {
         use diesel::dsl::now;
         use schema::readme_renderings::dsl::*;
@@ -121,7 +284,14 @@ impl NewVersion {
         license: Option<String>,
         license_file: Option<&str>,
         crate_size: i32,
+        published_by: Option<i32>,
     ) -> CargoResult<Self> {
+        let feature_syntax_version = features
+            .values()
+            .flat_map(|enabled| enabled.iter())
+            .map(|feature| Crate::feature_syntax_version(feature))
+            .max()
+            .unwrap_or(1);
         let features = serde_json::to_value(features)?;
 
         let mut new_version = NewVersion {
@@ -130,6 +300,8 @@ impl NewVersion {
             features,
             license,
             crate_size: Some(crate_size),
+            feature_syntax_version,
+            published_by,
         };
 
         new_version.validate_license(license_file)?;
@@ -137,27 +309,49 @@ impl NewVersion {
         Ok(new_version)
     }
 
+    /// The feature syntax version this version's features require
+    pub fn feature_syntax_version(&self) -> i32 {
+        self.feature_syntax_version
+    }
+
     pub fn save(&self, conn: &PgConnection, authors: &[String]) -> CargoResult<Version> {
         use diesel::dsl::exists;
-        use diesel::{insert_into, select};
-        use schema::version_authors::{name, version_id};
+        use diesel::select;
         use schema::versions::dsl::*;
 
         conn.transaction(|| {
+            // Build metadata doesn't participate in version precedence, so
+            // `1.0.0+foo` counts as a duplicate of `1.0.0`.
             let already_uploaded = versions
                 .filter(crate_id.eq(self.crate_id))
-                .filter(num.eq(&self.num));
+                .filter(split_part(num, "+", 1).eq(self.normalized_num()));
             if select(exists(already_uploaded)).get_result(conn)? {
-                return Err(human(&format_args!(
-                    "crate version `{}` is already \
-                     uploaded",
-                    self.num
-                )));
+                return Err(self.already_uploaded_error());
             }
+            self.insert(conn, authors)
+        })
+    }
 
+    /// Inserts the version and its authors without checking whether it has
+    /// been uploaded already, as `save` does. The unique index on versions
+    /// still rejects a duplicate, which is how concurrent publishes of the
+    /// same version that both pass the check in `save` are caught.
+    pub fn insert(&self, conn: &PgConnection, authors: &[String]) -> CargoResult<Version> {
+        use diesel::insert_into;
+        use diesel::result::{DatabaseErrorKind, Error as DieselError};
+        use schema::version_authors::{name, version_id};
+        use schema::versions::dsl::*;
+
+        conn.transaction(|| {
             let version = insert_into(versions)
                 .values(self)
-                .get_result::<Version>(conn)?;
+                .get_result::<Version>(conn)
+                .map_err(|e| match e {
+                    DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+                        self.already_uploaded_error()
+                    }
+                    e => e.into(),
+                })?;
 
             let new_authors = authors
                 .iter()
@@ -171,6 +365,19 @@ impl NewVersion {
         })
     }
 
+    fn already_uploaded_error(&self) -> Box<dyn CargoError> {
+        human(&format_args!(
+            "crate version `{}` is already \
+             uploaded",
+            self.num
+        ))
+    }
+
+    /// The version number without any build metadata
+    fn normalized_num(&self) -> &str {
+        self.num.split('+').next().unwrap_or(&self.num)
+    }
+
     fn validate_license(&mut self, license_file: Option<&str>) -> CargoResult<()> {
         if let Some(ref license) = self.license {
             for part in license.split('/') {
@@ -192,3 +399,22 @@ impl NewVersion {
         Ok(())
     }
 }
+
+/// Returns the first paragraph of `changelog`, cut off after
+/// `CHANGELOG_EXCERPT_LENGTH` characters
+fn changelog_excerpt(changelog: &str) -> String {
+    let paragraph = changelog.trim().split("\n\n").next().unwrap_or("");
+    let mut chars = paragraph.chars();
+    let excerpt = chars
+        .by_ref()
+        .take(CHANGELOG_EXCERPT_LENGTH)
+        .collect::<String>();
+    if chars.next().is_some() {
+        format!("{}…", excerpt.trim_right())
+    } else {
+        excerpt
+    }
+}
+
+use diesel::sql_types::{Integer, Text};
+sql_function!(fn split_part(string: Text, delimiter: Text, n: Integer) -> Text);
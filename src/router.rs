@@ -6,7 +6,9 @@ use conduit_git_http_backend;
 use conduit_router::{RequestParams, RouteBuilder};
 
 use controllers::*;
-use util::errors::{std_error, CargoError, CargoResult, NotFound};
+use middleware::app::RequestApp;
+use middleware::current_user::RequestUser;
+use util::errors::{std_error, CargoError, CargoResult, LoginRequired, NotFound};
 use util::RequestProxy;
 use {App, Env};
 
@@ -30,13 +32,19 @@ pub fn build_router(app: &App) -> R404 {
         "/crates/:crate_id/:version/download",
         C(version::downloads::download),
     );
+    api_router.get(
+        "/crates/:crate_id/download",
+        C(version::downloads::download_matching),
+    );
 
     // Routes that appear to be unused
     api_router.get("/versions", C(version::deprecated::index));
     api_router.get("/versions/:version_id", C(version::deprecated::show));
 
     // Routes used by the frontend
+    api_router.post("/crates/lookup", Read(C(krate::search::lookup)));
     api_router.get("/crates/:crate_id", C(krate::metadata::show));
+    api_router.put("/crates/:crate_id", C(krate::metadata::update));
     api_router.get("/crates/:crate_id/:version", C(version::deprecated::show));
     api_router.get(
         "/crates/:crate_id/:version/readme",
@@ -54,11 +62,24 @@ pub fn build_router(app: &App) -> R404 {
         "/crates/:crate_id/:version/authors",
         C(version::metadata::authors),
     );
+    api_router.get(
+        "/crates/:crate_id/:version/provenance",
+        C(version::metadata::provenance),
+    );
+    api_router.get(
+        "/crates/:crate_id/:version/integrity",
+        C(version::metadata::integrity),
+    );
+    api_router.get(
+        "/crates/:crate_id/:version/changelog",
+        C(version::metadata::changelog),
+    );
     api_router.get(
         "/crates/:crate_id/downloads",
         C(krate::downloads::downloads),
     );
     api_router.get("/crates/:crate_id/versions", C(krate::metadata::versions));
+    api_router.get("/crates/:crate_id/resolve", C(krate::metadata::resolve));
     api_router.put("/crates/:crate_id/follow", C(krate::follow::follow));
     api_router.delete("/crates/:crate_id/follow", C(krate::follow::unfollow));
     api_router.get("/crates/:crate_id/following", C(krate::follow::following));
@@ -68,11 +89,17 @@ pub fn build_router(app: &App) -> R404 {
         "/crates/:crate_id/reverse_dependencies",
         C(krate::metadata::reverse_dependencies),
     );
+    api_router.get(
+        "/crates/:crate_id/feature_usage",
+        C(krate::metadata::feature_usage),
+    );
     api_router.get("/keywords", C(keyword::index));
     api_router.get("/keywords/:keyword_id", C(keyword::show));
     api_router.get("/categories", C(category::index));
     api_router.get("/categories/:category_id", C(category::show));
     api_router.get("/category_slugs", C(category::slugs));
+    api_router.get("/crate_names", C(krate::search::names));
+    api_router.get("/changed_crates", C(krate::downloads::changed_since));
     api_router.get("/users/:user_id", C(user::other::show));
     api_router.put("/users/:user_id", C(user::me::update_user));
     api_router.get("/users/:user_id/stats", C(user::other::stats));
@@ -97,16 +124,25 @@ pub fn build_router(app: &App) -> R404 {
         C(user::me::regenerate_token_and_send),
     );
     api_router.get("/site_metadata", C(site_metadata::show_deployed_sha));
+    api_router.post(
+        "/checksums/verify",
+        Read(C(version::metadata::verify_checksums)),
+    );
+
+    // Routes used by registry operators
+    api_router.get("/admin/index_audit", C(admin::index_audit));
+    api_router.post("/admin/gc_s3", C(admin::gc_s3));
     let api_router = Arc::new(R404(api_router));
 
     let mut router = RouteBuilder::new();
 
     // Mount the router under the /api/v1 path so we're at least somewhat at the
-    // liberty to change things in the future!
-    router.get("/api/v1/*path", R(Arc::clone(&api_router)));
+    // liberty to change things in the future! Everything it serves with GET is
+    // read only, so private registries only let their users see it.
+    router.get("/api/v1/*path", Read(R(Arc::clone(&api_router))));
     router.put("/api/v1/*path", R(Arc::clone(&api_router)));
     router.post("/api/v1/*path", R(Arc::clone(&api_router)));
-    router.head("/api/v1/*path", R(Arc::clone(&api_router)));
+    router.head("/api/v1/*path", Read(R(Arc::clone(&api_router))));
     router.delete("/api/v1/*path", R(api_router));
 
     router.get("/authorize_url", C(user::session::github_authorize));
@@ -141,6 +177,21 @@ impl Handler for C {
     }
 }
 
+/// Rejects anonymous requests when the registry has been configured to only
+/// be readable by its users. Any way of logging in, cookie or API token, is
+/// accepted.
+struct Read<H>(pub H);
+
+impl<H: Handler> Handler for Read<H> {
+    fn call(&self, req: &mut dyn Request) -> Result<Response, Box<dyn Error + Send>> {
+        if req.app().config.require_auth_for_reads && req.user().is_err() {
+            return Ok(LoginRequired.response().unwrap());
+        }
+        let Read(ref handler) = *self;
+        handler.call(req)
+    }
+}
+
 struct R<H>(pub Arc<H>);
 
 impl<H: Handler> Handler for R<H> {
@@ -198,6 +249,10 @@ mod tests {
             C(|_| err(Unauthorized)).call(&mut req).unwrap().status.0,
             403
         );
+        assert_eq!(
+            C(|_| err(LoginRequired)).call(&mut req).unwrap().status.0,
+            401
+        );
         assert_eq!(
             C(|_| Err(DieselError::NotFound.into()))
                 .call(&mut req)
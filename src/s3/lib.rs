@@ -6,12 +6,22 @@ extern crate openssl;
 extern crate reqwest;
 
 use base64::encode;
-use chrono::prelude::Utc;
+use chrono::prelude::{DateTime, Utc};
 use openssl::hash::MessageDigest;
 use openssl::pkey::PKey;
 use openssl::sign::Signer;
 use reqwest::header;
 
+/// One page of the objects in a bucket, as returned by `Bucket::list`
+#[derive(Clone, Debug)]
+pub struct ObjectList {
+    /// The key of each object on this page, along with when it was last
+    /// modified
+    pub objects: Vec<(String, DateTime<Utc>)>,
+    /// Given to `Bucket::list` to get the next page, if there is one
+    pub next_continuation_token: Option<String>,
+}
+
 #[derive(Clone, Debug)]
 pub struct Bucket {
     name: String,
@@ -38,6 +48,10 @@ impl Bucket {
         }
     }
 
+    /// Uploads `content` to `path`.
+    ///
+    /// The response is returned whatever its status, so that the caller can
+    /// inspect the body of an error response.
     pub fn put(
         &self,
         client: &reqwest::Client,
@@ -60,8 +74,7 @@ impl Bucket {
             .header(header::CONTENT_TYPE, content_type)
             .header(header::DATE, date)
             .body(content)
-            .send()?
-            .error_for_status()
+            .send()
     }
 
     pub fn delete(
@@ -86,6 +99,76 @@ impl Bucket {
             .error_for_status()
     }
 
+    /// Lists up to 1000 of the objects whose keys start with `prefix`,
+    /// continuing from an earlier page if `continuation_token` is given.
+    ///
+    /// Objects whose modification time can't be read are listed as having
+    /// been modified just now.
+    pub fn list(
+        &self,
+        client: &reqwest::Client,
+        prefix: &str,
+        continuation_token: Option<&str>,
+    ) -> reqwest::Result<ObjectList> {
+        let date = Utc::now().to_rfc2822().to_string();
+        let auth = self.auth("GET", &date, "", "", "");
+        let mut params = vec![("list-type", "2"), ("prefix", prefix)];
+        if let Some(token) = continuation_token {
+            params.push(("continuation-token", token));
+        }
+        let url = reqwest::Url::parse_with_params(&self.url(""), &params)
+            .expect("bucket URLs are always valid");
+
+        let body = client
+            .get(url)
+            .header(header::DATE, date)
+            .header(header::AUTHORIZATION, auth)
+            .send()?
+            .error_for_status()?
+            .text()?;
+
+        let objects = elements(&body, "Contents")
+            .into_iter()
+            .filter_map(|contents| {
+                let key = elements(contents, "Key").into_iter().next()?;
+                let last_modified = elements(contents, "LastModified")
+                    .into_iter()
+                    .next()
+                    .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+                    .map(|time| time.with_timezone(&Utc))
+                    .unwrap_or_else(Utc::now);
+                Some((unescape(key), last_modified))
+            }).collect();
+        let next_continuation_token = elements(&body, "NextContinuationToken")
+            .into_iter()
+            .next()
+            .map(unescape);
+        Ok(ObjectList {
+            objects,
+            next_continuation_token,
+        })
+    }
+
+    /// Returns a URL that anyone can use to GET `path` from the bucket until
+    /// `expires`, given as a Unix timestamp, even if the bucket is private
+    pub fn presigned_get_url(&self, path: &str, expires: i64) -> String {
+        let path = if path.starts_with('/') {
+            &path[1..]
+        } else {
+            path
+        };
+        let expires = expires.to_string();
+        let signature = self.sign(&format!("GET\n\n\n{}\n/{}/{}", expires, self.name, path));
+        let params = [
+            ("AWSAccessKeyId", &self.access_key[..]),
+            ("Expires", &expires[..]),
+            ("Signature", &signature[..]),
+        ];
+        reqwest::Url::parse_with_params(&self.url(path), &params)
+            .expect("bucket URLs are always valid")
+            .to_string()
+    }
+
     pub fn host(&self) -> String {
         format!(
             "{}.s3{}.amazonaws.com",
@@ -108,16 +191,45 @@ impl Bucket {
             headers = "",
             resource = format!("/{}/{}", self.name, path)
         );
-        let signature = {
-            let key = PKey::hmac(self.secret_key.as_bytes()).unwrap();
-            let mut signer = Signer::new(MessageDigest::sha1(), &key).unwrap();
-            signer.update(string.as_bytes()).unwrap();
-            encode(&signer.sign_to_vec().unwrap()[..])
-        };
-        format!("AWS {}:{}", self.access_key, signature)
+        format!("AWS {}:{}", self.access_key, self.sign(&string))
+    }
+
+    fn sign(&self, string: &str) -> String {
+        let key = PKey::hmac(self.secret_key.as_bytes()).unwrap();
+        let mut signer = Signer::new(MessageDigest::sha1(), &key).unwrap();
+        signer.update(string.as_bytes()).unwrap();
+        encode(&signer.sign_to_vec().unwrap()[..])
     }
 
     fn url(&self, path: &str) -> String {
         format!("{}://{}/{}", self.proto, self.host(), path)
     }
 }
+
+/// Returns the contents of every `<tag>` element in `xml`. Listing objects
+/// only needs a handful of elements with text in them, so this is all the
+/// XML parsing done here.
+fn elements<'a>(mut xml: &'a str, tag: &str) -> Vec<&'a str> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    let mut found = Vec::new();
+    while let Some(start) = xml.find(&open) {
+        let rest = &xml[start + open.len()..];
+        match rest.find(&close) {
+            Some(end) => {
+                found.push(&rest[..end]);
+                xml = &rest[end + close.len()..];
+            }
+            None => break,
+        }
+    }
+    found
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
@@ -345,6 +345,18 @@ table! {
         ///
         /// (Automatically generated by Diesel.)
         readme_file -> Nullable<Varchar>,
+        /// The `custom_metadata` column of the `crates` table.
+        ///
+        /// Its SQL type is `Jsonb`.
+        ///
+        /// (Automatically generated by Diesel.)
+        custom_metadata -> Jsonb,
+        /// The `stats_updated_at` column of the `crates` table.
+        ///
+        /// Its SQL type is `Timestamp`.
+        ///
+        /// (Automatically generated by Diesel.)
+        stats_updated_at -> Timestamp,
     }
 }
 
@@ -531,6 +543,84 @@ table! {
         ///
         /// (Automatically generated by Diesel.)
         crate_id -> Int4,
+        /// The `prereleases` column of the `follows` table.
+        ///
+        /// Its SQL type is `Bool`.
+        ///
+        /// (Automatically generated by Diesel.)
+        prereleases -> Bool,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use diesel_full_text_search::{TsVector as Tsvector};
+    use diesel_ltree::Ltree;
+
+    /// Representation of the `idempotency_keys` table.
+    ///
+    /// (Automatically generated by Diesel.)
+    idempotency_keys (user_id, key) {
+        /// The `user_id` column of the `idempotency_keys` table.
+        ///
+        /// Its SQL type is `Int4`.
+        ///
+        /// (Automatically generated by Diesel.)
+        user_id -> Int4,
+        /// The `key` column of the `idempotency_keys` table.
+        ///
+        /// Its SQL type is `Varchar`.
+        ///
+        /// (Automatically generated by Diesel.)
+        key -> Varchar,
+        /// The `response` column of the `idempotency_keys` table.
+        ///
+        /// Its SQL type is `Jsonb`.
+        ///
+        /// (Automatically generated by Diesel.)
+        response -> Jsonb,
+        /// The `created_at` column of the `idempotency_keys` table.
+        ///
+        /// Its SQL type is `Timestamp`.
+        ///
+        /// (Automatically generated by Diesel.)
+        created_at -> Timestamp,
+        /// The `fingerprint` column of the `idempotency_keys` table.
+        ///
+        /// Its SQL type is `Varchar`.
+        ///
+        /// (Automatically generated by Diesel.)
+        fingerprint -> Varchar,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use diesel_full_text_search::{TsVector as Tsvector};
+    use diesel_ltree::Ltree;
+
+    /// Representation of the `integrity_manifests` table.
+    ///
+    /// (Automatically generated by Diesel.)
+    integrity_manifests (version_id) {
+        /// The `version_id` column of the `integrity_manifests` table.
+        ///
+        /// Its SQL type is `Int4`.
+        ///
+        /// (Automatically generated by Diesel.)
+        version_id -> Int4,
+        /// The `files` column of the `integrity_manifests` table.
+        ///
+        /// Its SQL type is `Jsonb`.
+        ///
+        /// (Automatically generated by Diesel.)
+        files -> Jsonb,
+        /// The `created_at` column of the `integrity_manifests` table.
+        ///
+        /// Its SQL type is `Timestamp`.
+        ///
+        /// (Automatically generated by Diesel.)
+        created_at -> Timestamp,
     }
 }
 
@@ -588,6 +678,66 @@ table! {
     }
 }
 
+table! {
+    use diesel::sql_types::*;
+    use diesel_full_text_search::{TsVector as Tsvector};
+    use diesel_ltree::Ltree;
+
+    /// Representation of the `pending_index_writes` table.
+    ///
+    /// (Automatically generated by Diesel.)
+    pending_index_writes (version_id) {
+        /// The `version_id` column of the `pending_index_writes` table.
+        ///
+        /// Its SQL type is `Int4`.
+        ///
+        /// (Automatically generated by Diesel.)
+        version_id -> Int4,
+        /// The `entry` column of the `pending_index_writes` table.
+        ///
+        /// Its SQL type is `Jsonb`.
+        ///
+        /// (Automatically generated by Diesel.)
+        entry -> Jsonb,
+        /// The `created_at` column of the `pending_index_writes` table.
+        ///
+        /// Its SQL type is `Timestamp`.
+        ///
+        /// (Automatically generated by Diesel.)
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use diesel_full_text_search::{TsVector as Tsvector};
+    use diesel_ltree::Ltree;
+
+    /// Representation of the `provenance` table.
+    ///
+    /// (Automatically generated by Diesel.)
+    provenance (version_id) {
+        /// The `version_id` column of the `provenance` table.
+        ///
+        /// Its SQL type is `Int4`.
+        ///
+        /// (Automatically generated by Diesel.)
+        version_id -> Int4,
+        /// The `document` column of the `provenance` table.
+        ///
+        /// Its SQL type is `Jsonb`.
+        ///
+        /// (Automatically generated by Diesel.)
+        document -> Jsonb,
+        /// The `created_at` column of the `provenance` table.
+        ///
+        /// Its SQL type is `Timestamp`.
+        ///
+        /// (Automatically generated by Diesel.)
+        created_at -> Timestamp,
+    }
+}
+
 table! {
     use diesel::sql_types::*;
     use diesel_full_text_search::{TsVector as Tsvector};
@@ -612,6 +762,48 @@ table! {
     }
 }
 
+table! {
+    use diesel::sql_types::*;
+    use diesel_full_text_search::{TsVector as Tsvector};
+    use diesel_ltree::Ltree;
+
+    /// Representation of the `recent_crate_changes` table.
+    ///
+    /// (Automatically generated by Diesel.)
+    recent_crate_changes (id) {
+        /// The `id` column of the `recent_crate_changes` table.
+        ///
+        /// Its SQL type is `Int4`.
+        ///
+        /// (Automatically generated by Diesel.)
+        id -> Int4,
+        /// The `user_id` column of the `recent_crate_changes` table.
+        ///
+        /// Its SQL type is `Int4`.
+        ///
+        /// (Automatically generated by Diesel.)
+        user_id -> Int4,
+        /// The `crate_id` column of the `recent_crate_changes` table.
+        ///
+        /// Its SQL type is `Int4`.
+        ///
+        /// (Automatically generated by Diesel.)
+        crate_id -> Int4,
+        /// The `operation` column of the `recent_crate_changes` table.
+        ///
+        /// Its SQL type is `Varchar`.
+        ///
+        /// (Automatically generated by Diesel.)
+        operation -> Varchar,
+        /// The `created_at` column of the `recent_crate_changes` table.
+        ///
+        /// Its SQL type is `Timestamp`.
+        ///
+        /// (Automatically generated by Diesel.)
+        created_at -> Timestamp,
+    }
+}
+
 table! {
     /// Representation of the `recent_crate_downloads` view.
     ///
@@ -630,6 +822,36 @@ table! {
     }
 }
 
+table! {
+    use diesel::sql_types::*;
+    use diesel_full_text_search::{TsVector as Tsvector};
+    use diesel_ltree::Ltree;
+
+    /// Representation of the `recent_publishes` table.
+    ///
+    /// (Automatically generated by Diesel.)
+    recent_publishes (id) {
+        /// The `id` column of the `recent_publishes` table.
+        ///
+        /// Its SQL type is `Int4`.
+        ///
+        /// (Automatically generated by Diesel.)
+        id -> Int4,
+        /// The `user_id` column of the `recent_publishes` table.
+        ///
+        /// Its SQL type is `Int4`.
+        ///
+        /// (Automatically generated by Diesel.)
+        user_id -> Int4,
+        /// The `created_at` column of the `recent_publishes` table.
+        ///
+        /// Its SQL type is `Timestamp`.
+        ///
+        /// (Automatically generated by Diesel.)
+        created_at -> Timestamp,
+    }
+}
+
 table! {
     use diesel::sql_types::*;
     use diesel_full_text_search::{TsVector as Tsvector};
@@ -780,6 +1002,42 @@ table! {
     }
 }
 
+table! {
+    use diesel::sql_types::*;
+    use diesel_full_text_search::{TsVector as Tsvector};
+    use diesel_ltree::Ltree;
+
+    /// Representation of the `version_changelogs` table.
+    ///
+    /// (Automatically generated by Diesel.)
+    version_changelogs (version_id) {
+        /// The `version_id` column of the `version_changelogs` table.
+        ///
+        /// Its SQL type is `Int4`.
+        ///
+        /// (Automatically generated by Diesel.)
+        version_id -> Int4,
+        /// The `changelog` column of the `version_changelogs` table.
+        ///
+        /// Its SQL type is `Text`.
+        ///
+        /// (Automatically generated by Diesel.)
+        changelog -> Text,
+        /// The `excerpt` column of the `version_changelogs` table.
+        ///
+        /// Its SQL type is `Text`.
+        ///
+        /// (Automatically generated by Diesel.)
+        excerpt -> Text,
+        /// The `created_at` column of the `version_changelogs` table.
+        ///
+        /// Its SQL type is `Timestamp`.
+        ///
+        /// (Automatically generated by Diesel.)
+        created_at -> Timestamp,
+    }
+}
+
 table! {
     use diesel::sql_types::*;
     use diesel_full_text_search::{TsVector as Tsvector};
@@ -897,6 +1155,36 @@ table! {
         ///
         /// (Automatically generated by Diesel.)
         crate_size -> Nullable<Int4>,
+        /// The `feature_syntax_version` column of the `versions` table.
+        ///
+        /// Its SQL type is `Int4`.
+        ///
+        /// (Automatically generated by Diesel.)
+        feature_syntax_version -> Int4,
+        /// The `checksum` column of the `versions` table.
+        ///
+        /// Its SQL type is `Nullable<Varchar>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        checksum -> Nullable<Varchar>,
+        /// The `git_ref` column of the `versions` table.
+        ///
+        /// Its SQL type is `Nullable<Varchar>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        git_ref -> Nullable<Varchar>,
+        /// The `uncompressed_size` column of the `versions` table.
+        ///
+        /// Its SQL type is `Nullable<Int8>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        uncompressed_size -> Nullable<Int8>,
+        /// The `published_by` column of the `versions` table.
+        ///
+        /// Its SQL type is `Nullable<Int4>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        published_by -> Nullable<Int4>,
     }
 }
 
@@ -915,10 +1203,18 @@ joinable!(dependencies -> versions (version_id));
 joinable!(emails -> users (user_id));
 joinable!(follows -> crates (crate_id));
 joinable!(follows -> users (user_id));
+joinable!(idempotency_keys -> users (user_id));
+joinable!(integrity_manifests -> versions (version_id));
+joinable!(pending_index_writes -> versions (version_id));
+joinable!(provenance -> versions (version_id));
 joinable!(readme_renderings -> versions (version_id));
+joinable!(recent_crate_changes -> crates (crate_id));
+joinable!(recent_crate_changes -> users (user_id));
 joinable!(recent_crate_downloads -> crates (crate_id));
+joinable!(recent_publishes -> users (user_id));
 joinable!(version_authors -> users (user_id));
 joinable!(version_authors -> versions (version_id));
+joinable!(version_changelogs -> versions (version_id));
 joinable!(version_downloads -> versions (version_id));
 joinable!(versions -> crates (crate_id));
 
@@ -935,14 +1231,21 @@ allow_tables_to_appear_in_same_query!(
     dependencies,
     emails,
     follows,
+    idempotency_keys,
+    integrity_manifests,
     keywords,
     metadata,
+    pending_index_writes,
+    provenance,
     readme_renderings,
+    recent_crate_changes,
     recent_crate_downloads,
+    recent_publishes,
     reserved_crate_names,
     teams,
     users,
     version_authors,
+    version_changelogs,
     version_downloads,
     versions,
 );
@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+
+use diesel::prelude::*;
+use diesel::update;
+
+use builders::{CrateBuilder, VersionBuilder};
+use cargo_registry::git;
+use schema::versions;
+use {RequestHelper, TestApp};
+
+#[derive(Deserialize)]
+struct Discrepancy {
+    #[serde(rename = "crate")]
+    krate: String,
+    version: String,
+    problem: String,
+}
+#[derive(Deserialize)]
+struct IndexAudit {
+    discrepancies: Vec<Discrepancy>,
+}
+#[derive(Deserialize)]
+struct GcS3 {
+    orphans: Vec<String>,
+    confirm: String,
+    deleted: bool,
+}
+
+fn index_entry(name: &str, vers: &str, cksum: &str) -> git::Crate {
+    git::Crate {
+        name: name.to_string(),
+        vers: vers.to_string(),
+        deps: Vec::new(),
+        cksum: cksum.to_string(),
+        features: HashMap::new(),
+        features2: None,
+        yanked: Some(false),
+        links: None,
+        v: None,
+    }
+}
+
+#[test]
+fn index_audit_requires_admin() {
+    let (app, anon, user) = TestApp::init().with_user();
+
+    anon.get::<()>("/api/v1/admin/index_audit").assert_forbidden();
+    user.get::<()>("/api/v1/admin/index_audit").assert_forbidden();
+
+    let admin = app.db_new_admin_user("admin");
+    let json: IndexAudit = admin.get("/api/v1/admin/index_audit").good();
+    assert!(json.discrepancies.is_empty());
+}
+
+#[test]
+fn index_audit_reports_discrepancies() {
+    let (app, _) = TestApp::init().empty();
+    let admin = app.db_new_admin_user("admin");
+    let user = admin.as_model();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo_audit", user.id)
+            .version("1.0.0")
+            .version("1.1.0")
+            .version("1.2.0")
+            .expect_build(conn);
+        update(versions::table)
+            .set(versions::checksum.eq("abc"))
+            .execute(conn)
+            .unwrap();
+        update(versions::table.filter(versions::num.eq("1.1.0")))
+            .set(versions::yanked.eq(true))
+            .execute(conn)
+            .unwrap();
+    });
+
+    for entry in &[
+        index_entry("foo_audit", "1.0.0", "abc"),
+        index_entry("foo_audit", "1.1.0", "def"),
+        index_entry("foo_audit", "2.0.0", "abc"),
+    ] {
+        git::add_crate(app.as_inner(), entry).unwrap();
+    }
+
+    let json: IndexAudit = admin.get("/api/v1/admin/index_audit").good();
+    let mut found = json
+        .discrepancies
+        .iter()
+        .map(|d| (&*d.krate, &*d.version, &*d.problem))
+        .collect::<Vec<_>>();
+    found.sort();
+    assert_eq!(
+        found,
+        vec![
+            (
+                "foo_audit",
+                "1.1.0",
+                "checksum is `abc` in the database but `def` in the index",
+            ),
+            (
+                "foo_audit",
+                "1.1.0",
+                "yanked is true in the database but false in the index",
+            ),
+            ("foo_audit", "1.2.0", "missing from the index"),
+            ("foo_audit", "2.0.0", "missing from the database"),
+        ]
+    );
+}
+
+#[test]
+fn gc_s3_deletes_orphaned_crate_files() {
+    let (app, anon, user) = TestApp::with_proxy().with_user();
+
+    anon.post::<()>("/api/v1/admin/gc_s3", &[]).assert_forbidden();
+    user.post::<()>("/api/v1/admin/gc_s3", &[]).assert_forbidden();
+
+    let admin = app.db_new_admin_user("admin");
+    app.db(|conn| {
+        CrateBuilder::new("foo_gc", admin.as_model().id)
+            .version("1.0.0")
+            .version(VersionBuilder::new("1.1.0").yanked(true))
+            .expect_build(conn);
+    });
+
+    // S3 also lists 2.0.0, but it was uploaded too recently to be an orphan
+    let json: GcS3 = admin.post("/api/v1/admin/gc_s3", &[]).good();
+    assert_eq!(json.orphans, vec!["crates/foo_gc/foo_gc-0.9.0.crate"]);
+    assert!(!json.deleted);
+
+    admin
+        .post::<()>("/api/v1/admin/gc_s3", br#"{"confirm":"abc"}"#)
+        .bad_with_status(400);
+
+    let body = format!(r#"{{"confirm":"{}"}}"#, json.confirm);
+    let json: GcS3 = admin.post("/api/v1/admin/gc_s3", body.as_bytes()).good();
+    assert_eq!(json.orphans, vec!["crates/foo_gc/foo_gc-0.9.0.crate"]);
+    assert!(json.deleted);
+}
@@ -76,6 +76,7 @@ macro_rules! bad_resp {
     }};
 }
 
+mod admin;
 mod badge;
 mod builders;
 mod categories;
@@ -108,6 +109,8 @@ pub struct CrateList {
 struct Warnings {
     invalid_categories: Vec<String>,
     invalid_badges: Vec<String>,
+    dependency_cycles: Vec<String>,
+    other: Vec<String>,
 }
 #[derive(Deserialize)]
 struct CrateMeta {
@@ -138,6 +141,20 @@ fn app() -> (
     Arc<App>,
     conduit_middleware::MiddlewareBuilder,
 ) {
+    app_with_config(|_| {})
+}
+
+/// Like `app`, but lets the test adjust the configuration first
+fn app_with_config<F>(
+    configure: F,
+) -> (
+    record::Bomb,
+    Arc<App>,
+    conduit_middleware::MiddlewareBuilder,
+)
+where
+    F: FnOnce(&mut cargo_registry::Config),
+{
     dotenv::dotenv().ok();
 
     let (proxy, bomb) = record::proxy();
@@ -155,15 +172,25 @@ fn app() -> (
         cdn: None,
     };
 
-    let (app, handler) = simple_app(uploader);
+    let (app, handler) = simple_app_with_config(uploader, configure);
     (bomb, app, handler)
 }
 
 fn simple_app(
     uploader: cargo_registry::Uploader,
 ) -> (Arc<App>, conduit_middleware::MiddlewareBuilder) {
+    simple_app_with_config(uploader, |_| {})
+}
+
+fn simple_app_with_config<F>(
+    uploader: cargo_registry::Uploader,
+    configure: F,
+) -> (Arc<App>, conduit_middleware::MiddlewareBuilder)
+where
+    F: FnOnce(&mut cargo_registry::Config),
+{
     git::init();
-    let config = cargo_registry::Config {
+    let mut config = cargo_registry::Config {
         uploader,
         session_key: "test this has to be over 32 bytes long".to_string(),
         git_repo_checkout: git::checkout(),
@@ -177,7 +204,26 @@ fn simple_app(
         // When testing we route all API traffic over HTTP so we can
         // sniff/record it, but everywhere else we use https
         api_protocol: String::from("http"),
+        feature_syntax_version: 2,
+        new_crate_window_days: 14,
+        admin_gh_ids: vec![ADMIN_GH_ID],
+        count_downloads: true,
+        require_auth_for_reads: false,
+        publish_rate_limit: None,
+        publish_rate_window_minutes: 60,
+        queue_index_writes: false,
+        crate_change_rate_limit: None,
+        crate_change_rate_window_minutes: 60,
+        reject_dependency_cycles: false,
+        signed_download_expiry_secs: None,
+        s3_upload_attempts: 1,
+        s3_upload_backoff_ms: 0,
+        crate_name_pattern: None,
+        publish_size_warning_threshold: None,
+        buffer_downloads: false,
+        download_cache_size: 0,
     };
+    configure(&mut config);
     let app = App::new(&config);
     t!(t!(app.diesel_database.get()).begin_test_transaction());
     let app = Arc::new(app);
@@ -230,6 +276,10 @@ where
 
 static NEXT_GH_ID: AtomicUsize = ATOMIC_USIZE_INIT;
 
+/// The GitHub ID of the user that is configured as an admin, well clear of the
+/// IDs handed out by `new_user`
+const ADMIN_GH_ID: i32 = 1_000_000;
+
 fn new_user(login: &str) -> NewUser<'_> {
     NewUser {
         gh_id: NEXT_GH_ID.fetch_add(1, Ordering::SeqCst) as i32,
@@ -89,6 +89,7 @@ impl<'a> VersionBuilder<'a> {
             license,
             self.license_file,
             self.size,
+            None,
         )?.save(connection, &[])?;
 
         if self.yanked {
@@ -189,6 +190,12 @@ impl<'a> CrateBuilder<'a> {
         self
     }
 
+    /// Sets the crate's `repository` URL.
+    pub fn repository(mut self, repository: &'a str) -> Self {
+        self.krate.repository = Some(repository);
+        self
+    }
+
     /// Sets the crate's `max_upload_size` override value.
     pub fn max_upload_size(mut self, max_upload_size: i32) -> Self {
         self.krate.max_upload_size = Some(max_upload_size);
@@ -316,6 +323,7 @@ pub struct PublishBuilder {
     version: semver::Version,
     tarball: Vec<u8>,
     deps: Vec<u::CrateDependency>,
+    features: HashMap<u::FeatureName, Vec<u::Feature>>,
     desc: Option<String>,
     readme: Option<String>,
     doc_url: Option<String>,
@@ -325,6 +333,7 @@ pub struct PublishBuilder {
     license: Option<String>,
     license_file: Option<String>,
     authors: Vec<String>,
+    changelog: Option<String>,
 }
 
 impl PublishBuilder {
@@ -336,6 +345,7 @@ impl PublishBuilder {
             version: semver::Version::parse("1.0.0").unwrap(),
             tarball: EMPTY_TARBALL_BYTES.to_vec(),
             deps: vec![],
+            features: HashMap::new(),
             desc: Some("description".to_string()),
             readme: None,
             doc_url: None,
@@ -345,6 +355,7 @@ impl PublishBuilder {
             license: Some("MIT".to_string()),
             license_file: None,
             authors: vec!["foo".to_string()],
+            changelog: None,
         }
     }
 
@@ -400,6 +411,15 @@ impl PublishBuilder {
         self
     }
 
+    /// Add a feature to this crate that enables each of `enables`
+    pub fn feature(mut self, name: &str, enables: &[&str]) -> Self {
+        self.features.insert(
+            u::FeatureName(name.to_string()),
+            enables.iter().map(|f| u::Feature(f.to_string())).collect(),
+        );
+        self
+    }
+
     /// Set the description of this crate
     pub fn description(mut self, description: &str) -> Self {
         self.desc = Some(description.to_string());
@@ -467,12 +487,18 @@ impl PublishBuilder {
         self
     }
 
+    /// Set the changelog of the version being published
+    pub fn changelog(mut self, changelog: &str) -> Self {
+        self.changelog = Some(changelog.into());
+        self
+    }
+
     /// Consume this builder to make the Put request body
     pub fn body(self) -> Vec<u8> {
         let new_crate = u::NewCrate {
             name: u::CrateName(self.krate_name.clone()),
             vers: u::CrateVersion(self.version),
-            features: HashMap::new(),
+            features: self.features,
             deps: self.deps,
             authors: self.authors,
             description: self.desc,
@@ -491,6 +517,8 @@ impl PublishBuilder {
             repository: None,
             badges: Some(self.badges),
             links: None,
+            provenance: None,
+            changelog: self.changelog,
         };
 
         ::new_crate_to_body_with_tarball(&new_crate, &self.tarball)
@@ -8,28 +8,33 @@ use std::io::prelude::*;
 
 use self::diesel::prelude::*;
 use self::tempdir::TempDir;
-use chrono::Utc;
+use chrono::{Duration, Utc};
 use diesel::dsl::*;
 use diesel::update;
 use flate2::write::GzEncoder;
 use flate2::Compression;
 use git2;
+use semver;
 use serde_json;
 use tar;
 
 use cargo_registry::git;
-use cargo_registry::models::krate::MAX_NAME_LENGTH;
+use cargo_registry::models::krate::{canon_crate_name, MAX_NAME_LENGTH};
 
 use builders::{CrateBuilder, DependencyBuilder, PublishBuilder, VersionBuilder};
-use models::{Category, Crate};
-use schema::{api_tokens, crates, metadata, versions};
+use models::{
+    Category, Crate, IdempotencyKey, NewCrate, PendingIndexWrite, RecentPublish, Version,
+    VersionIdCache,
+};
+use schema::{api_tokens, crates, dependencies, metadata, version_downloads, versions};
+use views::krate_publish::CrateVersion;
 use views::{
     EncodableCategory, EncodableCrate, EncodableDependency, EncodableKeyword, EncodableVersion,
     EncodableVersionDownload,
 };
 use {
-    new_category, new_dependency, new_user, CrateMeta, CrateResponse, GoodCrate, OkBool,
-    RequestHelper, TestApp,
+    new_category, new_dependency, new_user, CrateList, CrateMeta, CrateResponse, GoodCrate,
+    OkBool, RequestHelper, TestApp, VersionResponse,
 };
 
 #[derive(Deserialize)]
@@ -101,6 +106,18 @@ fn index() {
     assert_eq!(json.meta.total, 1);
     assert_eq!(json.crates[0].name, krate.name);
     assert_eq!(json.crates[0].id, krate.name);
+    assert_eq!(json.crates[0].slug, krate.name);
+    assert_eq!(json.crates[0].is_new, Some(true));
+
+    app.db(|conn| {
+        update(crates::table)
+            .set(crates::created_at.eq(now - 30.days()))
+            .execute(conn)
+            .unwrap();
+    });
+
+    let json = anon.search("");
+    assert_eq!(json.crates[0].is_new, Some(false));
 }
 
 #[test]
@@ -141,6 +158,15 @@ fn index_queries() {
     assert_eq!(anon.search("letter=b").crates.len(), 1);
     assert_eq!(anon.search("letter=c").crates.len(), 0);
 
+    assert_eq!(anon.search("prefix=foo").crates.len(), 2);
+    assert_eq!(anon.search("prefix=foo_").crates.len(), 1);
+    assert_eq!(anon.search("prefix=foo-index").crates.len(), 1);
+    assert_eq!(anon.search("prefix=BAR_I").crates.len(), 1);
+    assert_eq!(anon.search("prefix=%25").crates.len(), 0);
+    assert_eq!(anon.search("prefix=f%25").crates.len(), 0);
+    assert_eq!(anon.search("prefix=foo&keyword=kw3").crates.len(), 1);
+    assert_eq!(anon.search("prefix=foo&keyword=kw3").meta.total, 1);
+
     assert_eq!(anon.search("keyword=kw1").crates.len(), 2);
     assert_eq!(anon.search("keyword=KW1").crates.len(), 2);
     assert_eq!(anon.search("keyword=kw2").crates.len(), 0);
@@ -184,6 +210,107 @@ fn index_queries() {
     assert_eq!(cl.meta.total, 0);
 }
 
+#[test]
+fn lookup() {
+    #[derive(Deserialize)]
+    struct Lookup {
+        crates: Vec<EncodableCrate>,
+        missing: Vec<String>,
+    }
+
+    let (app, anon, user) = TestApp::init().with_user();
+    let user = user.as_model();
+    app.db(|conn| {
+        CrateBuilder::new("foo_lookup", user.id)
+            .version("1.0.0")
+            .version("1.1.0")
+            .expect_build(conn);
+        CrateBuilder::new("bar_lookup", user.id).expect_build(conn);
+        CrateBuilder::new("baz_lookup", user.id).expect_build(conn);
+    });
+
+    let body = json!({ "ids": ["bar_lookup", "no_such_crate", "Foo-Lookup", "bar-lookup"] });
+    let json: Lookup = anon
+        .post("/api/v1/crates/lookup", body.to_string().as_bytes())
+        .good();
+    assert_eq!(json.crates.len(), 2);
+    assert_eq!(json.crates[0].name, "bar_lookup");
+    assert_eq!(json.crates[1].name, "foo_lookup");
+    assert_eq!(json.crates[1].max_version, "1.1.0");
+    assert_eq!(json.missing, vec!["no_such_crate"]);
+
+    let ids = (0..101).map(|i| format!("foo{}", i)).collect::<Vec<_>>();
+    let body = json!({ "ids": ids });
+    let mut response = anon.post::<()>("/api/v1/crates/lookup", body.to_string().as_bytes());
+    let json = response.bad_with_status(400);
+    assert!(json.errors[0].detail.contains("cannot look up more than 100"));
+}
+
+#[test]
+fn names() {
+    #[derive(Deserialize)]
+    struct Names {
+        names: Vec<String>,
+        meta: NamesMeta,
+    }
+    #[derive(Deserialize)]
+    struct NamesMeta {
+        next_page: Option<String>,
+    }
+
+    let (app, anon, user) = TestApp::init().with_user();
+    let user = user.as_model();
+    app.db(|conn| {
+        CrateBuilder::new("foo_names", user.id).expect_build(conn);
+        CrateBuilder::new("bar_names", user.id).expect_build(conn);
+        CrateBuilder::new("baz_names", user.id).expect_build(conn);
+    });
+
+    let json: Names = anon.get("/api/v1/crate_names").good();
+    assert_eq!(json.names, ["bar_names", "baz_names", "foo_names"]);
+    assert_eq!(json.meta.next_page, None);
+
+    let json: Names = anon
+        .get_with_query("/api/v1/crate_names", "per_page=2")
+        .good();
+    assert_eq!(json.names, ["bar_names", "baz_names"]);
+    let next_page = json.meta.next_page.unwrap();
+    assert_eq!(next_page, "?after=baz_names&per_page=2");
+
+    let json: Names = anon
+        .get_with_query("/api/v1/crate_names", &next_page[1..])
+        .good();
+    assert_eq!(json.names, ["foo_names"]);
+    assert_eq!(json.meta.next_page, None);
+}
+
+#[test]
+fn letter_and_prefix_filters_ignore_case() {
+    let (app, anon, user) = TestApp::init().with_user();
+    let user = user.as_model();
+    app.db(|conn| {
+        CrateBuilder::new("Apple", user.id).expect_build(conn);
+        CrateBuilder::new("apricot", user.id).expect_build(conn);
+        CrateBuilder::new("APPKIT", user.id).expect_build(conn);
+        CrateBuilder::new("banana", user.id).expect_build(conn);
+    });
+
+    let json = anon.search("letter=a&sort=alpha");
+    assert_eq!(json.meta.total, 3);
+    assert_eq!(json.crates[0].name, "APPKIT");
+    assert_eq!(json.crates[1].name, "Apple");
+    assert_eq!(json.crates[2].name, "apricot");
+
+    assert_eq!(anon.search("letter=A").meta.total, 3);
+    assert_eq!(anon.search("letter=B").meta.total, 1);
+    assert_eq!(anon.search("letter=_").meta.total, 0);
+    assert_eq!(anon.search("letter=").meta.total, 4);
+
+    assert_eq!(anon.search("prefix=app").meta.total, 2);
+    assert_eq!(anon.search("prefix=AppK").meta.total, 1);
+    assert_eq!(anon.search("prefix=Apr").meta.total, 1);
+}
+
 #[test]
 fn search_includes_crates_where_name_is_stopword() {
     let (app, anon, user) = TestApp::init().with_user();
@@ -199,6 +326,71 @@ fn search_includes_crates_where_name_is_stopword() {
     assert_eq!(json.meta.total, 1);
 }
 
+#[test]
+fn search_matches_name_substrings() {
+    let (app, anon, user) = TestApp::init().with_user();
+    let user = user.as_model();
+    app.db(|conn| {
+        CrateBuilder::new("serdeish", user.id).expect_build(conn);
+        CrateBuilder::new("my-serde-helpers", user.id).expect_build(conn);
+        CrateBuilder::new("serde", user.id).expect_build(conn);
+        CrateBuilder::new("other_100pct", user.id).expect_build(conn);
+    });
+
+    let json = anon.search("q=serde");
+    assert_eq!(json.meta.total, 3);
+    assert_eq!(json.crates.len(), 3);
+    assert_eq!(json.crates[0].name, "serde");
+    assert!(json.crates[0].exact_match);
+
+    let json = anon.search("q=serde&per_page=1");
+    assert_eq!(json.meta.total, 3);
+    assert_eq!(json.crates.len(), 1);
+
+    assert_eq!(anon.search("q=rdeis").meta.total, 1);
+    assert_eq!(anon.search("q=serde_helpers").meta.total, 1);
+    assert_eq!(anon.search("q=%25").meta.total, 0);
+    assert_eq!(anon.search("q=_").meta.total, 2);
+
+    // `letter` is ignored when searching
+    assert_eq!(anon.search("q=serde&letter=m").meta.total, 3);
+    // An empty `q` doesn't filter anything
+    assert_eq!(anon.search("q=").meta.total, 4);
+    assert_eq!(anon.search("q=&letter=m").meta.total, 1);
+}
+
+#[test]
+fn search_by_dependency() {
+    let (app, anon, user) = TestApp::init().with_user();
+    let user = user.as_model();
+    app.db(|conn| {
+        let openssl = CrateBuilder::new("openssl-sys", user.id).expect_build(conn);
+        // Depending on it from more than one version only lists the crate once
+        CrateBuilder::new("openssl", user.id)
+            .version(VersionBuilder::new("0.1.0").dependency(&openssl, None))
+            .version(VersionBuilder::new("0.2.0").dependency(&openssl, None))
+            .expect_build(conn);
+        CrateBuilder::new("curl", user.id)
+            .version(VersionBuilder::new("1.0.0").dependency(&openssl, Some("unix")))
+            .expect_build(conn);
+        CrateBuilder::new("rustls", user.id)
+            .version("1.0.0")
+            .expect_build(conn);
+    });
+
+    let json = anon.search("depends_on=openssl-sys");
+    assert_eq!(json.meta.total, 2);
+    assert_eq!(json.crates.len(), 2);
+    assert_eq!(json.crates[0].name, "curl");
+    assert_eq!(json.crates[1].name, "openssl");
+
+    assert_eq!(anon.search("depends_on=OpenSSL_sys").meta.total, 2);
+    assert_eq!(anon.search("depends_on=openssl-sys&q=curl").meta.total, 1);
+    assert_eq!(anon.search("depends_on=openssl-sys&per_page=1").crates.len(), 1);
+    assert_eq!(anon.search("depends_on=rustls").meta.total, 0);
+    assert_eq!(anon.search("depends_on=nonexistent").meta.total, 0);
+}
+
 #[test]
 fn exact_match_first_on_queries() {
     let (app, anon, user) = TestApp::init().with_user();
@@ -241,6 +433,61 @@ fn exact_match_first_on_queries() {
     assert_eq!(json.crates[2].name, "foo_exact");
 }
 
+#[test]
+fn index_pagination_meta() {
+    #[derive(Deserialize)]
+    struct Meta {
+        total: i64,
+        page: i64,
+        per_page: i64,
+        next_page: Option<String>,
+        prev_page: Option<String>,
+    }
+    #[derive(Deserialize)]
+    struct R {
+        crates: Vec<EncodableCrate>,
+        meta: Meta,
+    }
+
+    let (app, anon, user) = TestApp::init().with_user();
+    let user = user.as_model();
+    app.db(|conn| {
+        for name in &["a_page", "b_page", "c_page"] {
+            CrateBuilder::new(name, user.id).expect_build(conn);
+        }
+    });
+
+    let page = |query: &str| -> R { anon.get_with_query("/api/v1/crates", query).good() };
+
+    let json = page("sort=alpha&per_page=2");
+    assert_eq!(json.crates.len(), 2);
+    assert_eq!(json.meta.total, 3);
+    assert_eq!(json.meta.page, 1);
+    assert_eq!(json.meta.per_page, 2);
+    assert_eq!(json.meta.next_page, Some("?per_page=2&sort=alpha&page=2".into()));
+    assert_eq!(json.meta.prev_page, None);
+
+    let json = page("sort=alpha&per_page=2&page=2");
+    assert_eq!(json.crates.len(), 1);
+    assert_eq!(json.crates[0].name, "c_page");
+    assert_eq!(json.meta.next_page, None);
+    assert_eq!(json.meta.prev_page, Some("?per_page=2&sort=alpha&page=1".into()));
+
+    // Pages past the end are empty, but still report the total
+    let json = page("sort=alpha&per_page=2&page=5");
+    assert_eq!(json.crates.len(), 0);
+    assert_eq!(json.meta.total, 3);
+    assert_eq!(json.meta.next_page, None);
+
+    // Pages before the first are clamped to the first
+    for query in &["per_page=2&page=0", "per_page=2&page=-3"] {
+        let json = page(query);
+        assert_eq!(json.crates.len(), 2);
+        assert_eq!(json.meta.page, 1);
+        assert_eq!(json.meta.prev_page, None);
+    }
+}
+
 #[test]
 fn index_sorting() {
     let (app, anon, user) = TestApp::init().with_user();
@@ -323,6 +570,58 @@ fn index_sorting() {
     assert_eq!(json.crates[3].name, "other_sort");
 }
 
+#[test]
+fn index_sorting_by_name_and_timestamps() {
+    let (app, anon, user) = TestApp::init().with_user();
+    let user = user.as_model();
+
+    app.db(|conn| {
+        let now = Utc::now().naive_utc();
+        let krate1 = CrateBuilder::new("b_sort", user.id).expect_build(conn);
+        let krate2 = CrateBuilder::new("C_sort", user.id).expect_build(conn);
+        let krate3 = CrateBuilder::new("a_sort", user.id).expect_build(conn);
+        update(&krate1)
+            .set((
+                crates::created_at.eq(now - 3.days()),
+                crates::updated_at.eq(now - 1.days()),
+            )).execute(conn)
+            .unwrap();
+        update(&krate2)
+            .set((
+                crates::created_at.eq(now - 1.days()),
+                crates::updated_at.eq(now - 2.days()),
+            )).execute(conn)
+            .unwrap();
+        update(&krate3)
+            .set((
+                crates::created_at.eq(now - 2.days()),
+                crates::updated_at.eq(now - 3.days()),
+            )).execute(conn)
+            .unwrap();
+    });
+
+    let names = |query: &str| {
+        anon.search(query)
+            .crates
+            .into_iter()
+            .map(|c| c.name)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(names("sort=name"), ["a_sort", "b_sort", "C_sort"]);
+    // Crates are sorted by name when no sort, or an empty one, is given
+    assert_eq!(names(""), ["a_sort", "b_sort", "C_sort"]);
+    assert_eq!(names("sort="), ["a_sort", "b_sort", "C_sort"]);
+    assert_eq!(names("sort=created_at"), ["C_sort", "a_sort", "b_sort"]);
+    assert_eq!(names("sort=updated_at"), ["b_sort", "C_sort", "a_sort"]);
+    assert_eq!(names("sort=created_at&per_page=1&page=2"), ["a_sort"]);
+    assert_eq!(names("letter=c&sort=updated_at"), ["C_sort"]);
+
+    let json = anon
+        .get_with_query::<()>("/api/v1/crates", "sort=name;DROP%20TABLE%20crates")
+        .bad_with_status(200);
+    assert!(json.errors[0].detail.contains("invalid sort"));
+}
+
 #[test]
 fn exact_match_on_queries_with_sort() {
     let (app, anon, user) = TestApp::init().with_user();
@@ -468,6 +767,228 @@ fn show() {
     assert_eq!(json.versions[2].num, "0.5.0");
 }
 
+#[test]
+fn show_includes_download_urls() {
+    let (app, anon, user) = TestApp::with_proxy().with_user();
+    let user = user.as_model();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo_download_url", user.id)
+            .version("1.0.0")
+            .expect_build(&conn);
+    });
+
+    let json = anon.show_crate("foo_download_url");
+    assert_eq!(
+        json.versions[0].download_url,
+        Some(
+            "https://alexcrichton-test.s3.amazonaws.com/crates/\
+             foo_download_url/foo_download_url-1.0.0.crate"
+                .into()
+        )
+    );
+    assert_eq!(
+        json.versions[0].dl_path,
+        "/api/v1/crates/foo_download_url/1.0.0/download"
+    );
+}
+
+#[test]
+fn show_has_no_dependencies() {
+    let (app, anon, user) = TestApp::init().with_user();
+    let user = user.as_model();
+
+    app.db(|conn| {
+        let leaf = CrateBuilder::new("foo_leaf", user.id)
+            .version("1.0.0")
+            .expect_build(&conn);
+        CrateBuilder::new("foo_not_leaf", user.id)
+            .version(VersionBuilder::new("0.1.0"))
+            .version(VersionBuilder::new("1.0.0").dependency(&leaf, None))
+            .expect_build(&conn);
+        CrateBuilder::new("foo_former_leaf", user.id)
+            .version(VersionBuilder::new("0.1.0").dependency(&leaf, None))
+            .version(VersionBuilder::new("1.0.0"))
+            .expect_build(&conn);
+    });
+
+    let json = anon.show_crate("foo_leaf");
+    assert_eq!(json.krate.has_no_dependencies, Some(true));
+    let json = anon.show_crate("foo_not_leaf");
+    assert_eq!(json.krate.has_no_dependencies, Some(false));
+    let json = anon.show_crate("foo_former_leaf");
+    assert_eq!(json.krate.has_no_dependencies, Some(true));
+}
+
+#[test]
+fn crate_changes_are_rate_limited() {
+    let (app, _, user, token) =
+        TestApp::with_proxy_and_config(|config| config.crate_change_rate_limit = Some(1))
+            .with_token();
+    let user = user.as_model();
+    app.db_new_user("another");
+
+    app.db(|conn| {
+        CrateBuilder::new("foo_limited", user.id).expect_build(&conn);
+        CrateBuilder::new("foo_unlimited", user.id).expect_build(&conn);
+    });
+
+    let body = json!({ "crate": { "description": "new description" } }).to_string();
+    token
+        .put::<CrateResponse>("/api/v1/crates/foo_limited", body.as_bytes())
+        .good();
+    let json = token
+        .put::<()>("/api/v1/crates/foo_limited", body.as_bytes())
+        .bad_with_status(429);
+    assert!(json.errors[0].detail.contains("rate limit exceeded"));
+
+    // Other crates and other kinds of changes have limits of their own
+    token
+        .put::<CrateResponse>("/api/v1/crates/foo_unlimited", body.as_bytes())
+        .good();
+    token.add_named_owner("foo_limited", "another").good();
+    let json = token
+        .add_named_owner("foo_limited", "another")
+        .bad_with_status(429);
+    assert!(json.errors[0].detail.contains("rate limit exceeded"));
+}
+
+#[test]
+fn failed_and_no_op_changes_are_not_rate_limited() {
+    let (app, _, user, token) =
+        TestApp::with_proxy_and_config(|config| config.crate_change_rate_limit = Some(1))
+            .with_token();
+    let user = user.as_model();
+    app.db_new_user("another");
+
+    app.db(|conn| {
+        CrateBuilder::new("foo_not_limited", user.id)
+            .version("1.0.0")
+            .expect_build(&conn);
+    });
+
+    // Unyanking a version that isn't yanked doesn't change anything
+    token.unyank("foo_not_limited", "1.0.0").good();
+    token.unyank("foo_not_limited", "1.0.0").good();
+
+    // Neither does adding an owner that doesn't exist
+    let json = token
+        .add_named_owner("foo_not_limited", "nobody")
+        .bad_with_status(200);
+    assert!(!json.errors[0].detail.contains("rate limit exceeded"));
+    token.add_named_owner("foo_not_limited", "another").good();
+}
+
+#[test]
+fn update() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+    let another_user = app.db_new_user("another");
+    let user = cookie.as_model();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo_update", user.id)
+            .description("description")
+            .homepage("http://example.com")
+            .version("1.0.0")
+            .expect_build(&conn);
+    });
+
+    let url = "/api/v1/crates/foo_update";
+    let body = json!({
+        "crate": {
+            "description": "new description",
+            "repository": "https://github.com/foo/foo_update",
+        }
+    });
+
+    let json = another_user
+        .put::<()>(url, body.to_string().as_bytes())
+        .bad_with_status(200);
+    assert!(
+        json.errors[0]
+            .detail
+            .contains("only owners have permission to modify crate metadata"),
+        "{:?}",
+        json.errors
+    );
+    anon.put::<()>(url, body.to_string().as_bytes()).assert_forbidden();
+
+    let json: CrateResponse = cookie.put(url, body.to_string().as_bytes()).good();
+    assert_eq!(json.krate.description, Some("new description".into()));
+    assert_eq!(
+        json.krate.repository,
+        Some("https://github.com/foo/foo_update".into())
+    );
+    assert_eq!(json.krate.homepage, Some("http://example.com".into()));
+    assert_eq!(json.versions.len(), 1);
+
+    let json = anon.show_crate("foo_update");
+    assert_eq!(json.krate.description, Some("new description".into()));
+
+    let body = json!({ "crate": { "homepage": "ftp://example.com" } });
+    let json = cookie
+        .put::<()>(url, body.to_string().as_bytes())
+        .bad_with_status(200);
+    assert!(
+        json.errors[0]
+            .detail
+            .contains("`homepage` has an invalid url scheme"),
+        "{:?}",
+        json.errors
+    );
+}
+
+#[test]
+fn custom_metadata_is_only_shown_to_owners_and_admins() {
+    let (app, anon, cookie) = TestApp::init().with_user();
+    let another_user = app.db_new_user("another");
+    let admin = app.db_new_admin_user("admin");
+    let user = cookie.as_model();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo_custom_metadata", user.id).expect_build(&conn);
+    });
+
+    let url = "/api/v1/crates/foo_custom_metadata";
+    let body = json!({ "crate": { "custom_metadata": { "cost_center": "1234" } } });
+    let json: CrateResponse = cookie.put(url, body.to_string().as_bytes()).good();
+    let expected = vec![("cost_center".to_string(), "1234".to_string())]
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+    assert_eq!(json.krate.custom_metadata, Some(expected));
+
+    assert_eq!(anon.show_crate("foo_custom_metadata").krate.custom_metadata, None);
+    let json = another_user.show_crate("foo_custom_metadata");
+    assert_eq!(json.krate.custom_metadata, None);
+
+    let body = json!({ "crate": { "custom_metadata": { "team": "infra" } } });
+    let json: CrateResponse = admin.put(url, body.to_string().as_bytes()).good();
+    let expected = vec![("team".to_string(), "infra".to_string())]
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+    assert_eq!(json.krate.custom_metadata, Some(expected.clone()));
+    let json = cookie.show_crate("foo_custom_metadata");
+    assert_eq!(json.krate.custom_metadata, Some(expected));
+
+    let invalid = vec![
+        json!(["team"]),
+        json!({ "team": { "name": "infra" } }),
+        json!({ "size": 1 }),
+        json!({ "team": "x".repeat(257) }),
+    ];
+    for custom_metadata in invalid {
+        let body = json!({ "crate": { "custom_metadata": custom_metadata } });
+        let json = cookie
+            .put::<()>(url, body.to_string().as_bytes())
+            .bad_with_status(200);
+        assert!(
+            json.errors[0].detail.contains("`custom_metadata`"),
+            "{:?}",
+            json.errors
+        );
+    }
+}
+
 #[test]
 fn yanked_versions_are_not_considered_for_max_version() {
     let (app, anon, user) = TestApp::init().with_user();
@@ -506,6 +1027,89 @@ fn versions() {
     assert_eq!(json.versions[2].num, "0.5.0");
 }
 
+#[test]
+fn unix_timestamps_when_asked() {
+    use serde_json::Value;
+
+    let (app, anon, user) = TestApp::init().with_user();
+    let user = user.as_model();
+    let krate = app.db(|conn| {
+        CrateBuilder::new("foo_unix_time", user.id)
+            .version("1.0.0")
+            .expect_build(conn)
+    });
+    let created_at = krate.created_at.timestamp();
+
+    let json: Value = anon.get("/api/v1/crates/foo_unix_time").good();
+    assert!(json["crate"]["created_at"].is_string());
+
+    let json: Value = anon
+        .get_with_query("/api/v1/crates/foo_unix_time", "time_format=unix")
+        .good();
+    assert_eq!(json["crate"]["created_at"].as_i64(), Some(created_at));
+    assert!(json["crate"]["updated_at"].is_i64());
+    assert!(json["versions"][0]["created_at"].is_i64());
+
+    let json: Value = anon.get_with_query("/api/v1/crates", "time_format=unix").good();
+    assert_eq!(json["crates"][0]["created_at"].as_i64(), Some(created_at));
+
+    let json: Value = anon.get_with_query("/api/v1/summary", "time_format=unix").good();
+    assert_eq!(json["new_crates"][0]["created_at"].as_i64(), Some(created_at));
+}
+
+#[test]
+fn versions_are_sorted_by_semver_precedence() {
+    let (app, anon, user) = TestApp::init().with_user();
+    let user = user.as_model();
+    app.db(|conn| {
+        CrateBuilder::new("foo_precedence", user.id)
+            .version("0.10.0-rc.1")
+            .version("0.9.0")
+            .version("0.10.0")
+            .expect_build(conn);
+    });
+
+    let expected = ["0.10.0", "0.10.0-rc.1", "0.9.0"];
+    let json: VersionsList = anon.get("/api/v1/crates/foo_precedence/versions").good();
+    let nums = json.versions.iter().map(|v| &v.num[..]).collect::<Vec<_>>();
+    assert_eq!(nums, expected);
+
+    let json = anon.show_crate("foo_precedence");
+    let nums = json.versions.iter().map(|v| &v.num[..]).collect::<Vec<_>>();
+    assert_eq!(nums, expected);
+    assert_eq!(json.krate.max_version, "0.10.0");
+}
+
+#[test]
+fn resolve() {
+    let (app, anon, user) = TestApp::init().with_user();
+    let user = user.as_model();
+    app.db(|conn| {
+        CrateBuilder::new("foo_resolve", user.id)
+            .version("1.2.0")
+            .version("1.4.0")
+            .version(VersionBuilder::new("1.5.0").yanked(true))
+            .version("1.6.0-beta.1")
+            .version("2.0.0")
+            .expect_build(conn);
+    });
+
+    let resolve = |query: &str| {
+        anon.get_with_query::<VersionResponse>("/api/v1/crates/foo_resolve/resolve", query)
+    };
+
+    let json = resolve("req=%5E1.2").good();
+    assert_eq!(json.version.num, "1.4.0");
+    let json = resolve("req=%5E1.2&include_prerelease=true").good();
+    assert_eq!(json.version.num, "1.6.0-beta.1");
+    let json = resolve("req=*").good();
+    assert_eq!(json.version.num, "2.0.0");
+
+    resolve("req=%5E3").assert_not_found();
+    resolve("req=1.5.0").assert_not_found();
+    resolve("req=not-a-req").assert_status(400);
+}
+
 #[test]
 fn uploading_new_version_touches_crate() {
     use diesel::dsl::*;
@@ -607,19 +1211,456 @@ fn new_krate() {
 }
 
 #[test]
-fn new_krate_with_token() {
-    let (_, _, _, token) = TestApp::with_proxy().with_token();
-
-    let crate_to_publish = PublishBuilder::new("foo_new").version("1.0.0");
-    let json: GoodCrate = token.publish(crate_to_publish).good();
-
-    assert_eq!(json.krate.name, "foo_new");
+fn publish_retries_failed_upload() {
+    let (_, _, user) =
+        TestApp::with_proxy_and_config(|config| config.s3_upload_attempts = 2).with_user();
+
+    // The first upload gets a 500 from S3, the second one succeeds
+    let crate_to_publish = PublishBuilder::new("foo_retry").version("1.0.0");
+    let json = user.publish(crate_to_publish).good();
+    assert_eq!(json.krate.name, "foo_retry");
     assert_eq!(json.krate.max_version, "1.0.0");
 }
 
 #[test]
-fn new_krate_weird_version() {
-    let (_, _, _, token) = TestApp::with_proxy().with_token();
+fn publish_cleans_up_after_failed_upload() {
+    let (_, _, user) = TestApp::with_proxy().with_user();
+
+    // The upload gets a 500 from S3, after which the file is deleted in case
+    // it was stored anyway
+    let crate_to_publish = PublishBuilder::new("foo_retry").version("1.0.0");
+    let error = user.publish_internal_error(crate_to_publish);
+    assert!(error.contains("failed to upload to S3 after 1 attempt(s)"));
+}
+
+#[test]
+fn publish_with_idempotency_key() {
+    use schema::idempotency_keys;
+
+    let (app, _, _, token) = TestApp::with_proxy().with_token();
+    let body = PublishBuilder::new("foo_idempotent").version("1.0.0").body();
+    let headers = [("Idempotency-Key", "retry-1")];
+
+    let json: GoodCrate = token
+        .put_with_headers("/api/v1/crates/new", &body, &headers)
+        .good();
+    assert_eq!(json.krate.name, "foo_idempotent");
+
+    // The retry gets the same response without publishing (and uploading) again
+    let json: GoodCrate = token
+        .put_with_headers("/api/v1/crates/new", &body, &headers)
+        .good();
+    assert_eq!(json.krate.name, "foo_idempotent");
+    assert_eq!(json.krate.max_version, "1.0.0");
+
+    // A different key goes through the usual duplicate version check
+    let other_headers = [("Idempotency-Key", "retry-2")];
+    let json = token
+        .put_with_headers::<()>("/api/v1/crates/new", &body, &other_headers)
+        .bad_with_status(200);
+    assert!(json.errors[0].detail.contains("already uploaded"));
+
+    // So does the original key once it has expired
+    app.db(|conn| {
+        update(idempotency_keys::table)
+            .set(idempotency_keys::created_at.eq(now - 25.hours()))
+            .execute(conn)
+            .unwrap();
+    });
+    let json = token
+        .put_with_headers::<()>("/api/v1/crates/new", &body, &headers)
+        .bad_with_status(200);
+    assert!(json.errors[0].detail.contains("already uploaded"));
+}
+
+#[test]
+fn publish_rejects_reused_idempotency_key() {
+    let (_, _, _, token) = TestApp::with_proxy().with_token();
+    let headers = [("Idempotency-Key", "retry-1")];
+
+    let body = PublishBuilder::new("foo_idempotent").version("1.0.0").body();
+    token
+        .put_with_headers::<GoodCrate>("/api/v1/crates/new", &body, &headers)
+        .good();
+
+    // Reusing the key for another version isn't mistaken for a retry
+    let body = PublishBuilder::new("foo_idempotent").version("1.0.1").body();
+    let json = token
+        .put_with_headers::<()>("/api/v1/crates/new", &body, &headers)
+        .bad_with_status(422);
+    assert!(json.errors[0].detail.contains("Idempotency-Key"));
+
+    let json = token.show_crate("foo_idempotent");
+    assert_eq!(json.krate.max_version, "1.0.0");
+}
+
+#[test]
+fn idempotent_retry_of_queued_publish_is_accepted() {
+    let (_, _, _, token) =
+        TestApp::with_proxy_and_config(|config| config.queue_index_writes = true).with_token();
+
+    // Take the index's remote away so that the index entry gets queued
+    let moved = ::git::bare().with_extension("moved");
+    fs::rename(::git::bare(), &moved).unwrap();
+
+    let body = PublishBuilder::new("foo_queued_retry").version("1.0.0").body();
+    let headers = [("Idempotency-Key", "retry-1")];
+    token
+        .put_with_headers::<()>("/api/v1/crates/new", &body, &headers)
+        .assert_status(202);
+
+    // The retry is told the same thing as the original request
+    token
+        .put_with_headers::<()>("/api/v1/crates/new", &body, &headers)
+        .assert_status(202);
+
+    fs::rename(&moved, ::git::bare()).unwrap();
+}
+
+#[test]
+fn concurrently_recorded_idempotency_key_is_rejected() {
+    let (app, _, user) = TestApp::init().with_user();
+    let user = user.as_model();
+
+    app.db(|conn| {
+        let response = json!({});
+        IdempotencyKey::record(conn, user.id, "retry-1", "foo 1.0.0 abc", &response).unwrap();
+
+        // As if another request using the key had been recorded after this
+        // one checked for it
+        let err = IdempotencyKey::record(conn, user.id, "retry-1", "foo 1.0.1 def", &response)
+            .unwrap_err();
+        assert!(err.to_string().contains("Idempotency-Key"), "{}", err);
+    });
+}
+
+#[test]
+fn publish_records_integrity_manifest() {
+    use cargo_registry::uploaders::IntegrityFile;
+
+    #[derive(Deserialize)]
+    struct Integrity {
+        files: Vec<IntegrityFile>,
+    }
+
+    // `foo_integrity-1.0.0/src/lib.rs` containing `pub fn foo() {}\n`. The
+    // tarball is spelled out so that it matches the recorded upload exactly.
+    const TARBALL: &[u8] = b"\
+        \x1f\x8b\x08\x00\x00\x00\x00\x00\x02\x03\xed\xc8\xb1\x0a\xc2\x30\
+        \x14\x85\xe1\x3b\xfb\x14\x77\xd4\xc1\xf4\xc6\x16\x5f\x47\xac\x18\
+        \x09\x48\x23\x49\x3a\x48\xf1\xdd\xad\x4e\xe2\x2c\x82\xf8\x7f\xcb\
+        \x7f\x38\x21\xa5\x5d\x1c\xea\xf1\x94\x63\xbd\xae\xbd\x33\x67\x4d\
+        \xc9\x87\xe6\x1c\x7b\x97\x8b\x7c\x84\xcd\xb6\x5d\xf7\xec\xec\xbd\
+        \x66\x9b\x97\xfd\xf8\x7d\xeb\x7d\x2b\x6a\xf2\x05\x63\xa9\xfb\xac\
+        \x2a\x7f\xea\x32\xf6\x1a\x06\x0d\x29\x2d\x57\x3a\xdd\x16\x02\x00\
+        \x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\xf8\x09\x77\xa6\
+        \x7f\x6a\x38\x00\x28\x00\x00";
+
+    let (app, anon, user) = TestApp::with_proxy().with_user();
+    let crate_to_publish = PublishBuilder::new("foo_integrity")
+        .version("1.0.0")
+        .tarball(TARBALL.to_vec());
+    user.publish(crate_to_publish).good();
+
+    let json: Integrity = anon
+        .get("/api/v1/crates/foo_integrity/1.0.0/integrity")
+        .good();
+    assert_eq!(
+        json.files,
+        vec![IntegrityFile {
+            path: "src/lib.rs".into(),
+            size: 16,
+            sha256: "8f46e21779e9807d1c3d9828c172b1cac2f2b98924494a59d1512ca82ea42fb8".into(),
+        }]
+    );
+
+    // Versions published before manifests were recorded don't have one
+    app.db(|conn| {
+        CrateBuilder::new("foo_integrity_old", user.as_model().id)
+            .version("1.0.0")
+            .expect_build(conn);
+    });
+    anon.get::<()>("/api/v1/crates/foo_integrity_old/1.0.0/integrity")
+        .assert_not_found();
+}
+
+#[test]
+fn publish_records_changelog() {
+    #[derive(Deserialize)]
+    struct Changelog {
+        changelog: String,
+    }
+
+    let (app, anon, user) = TestApp::with_proxy().with_user();
+    let changelog = "Fixed the frobnicator.\n\nThe frobnicator no longer panics when empty.";
+    let crate_to_publish = PublishBuilder::new("foo_changelog")
+        .version("1.0.0")
+        .changelog(changelog);
+    user.publish(crate_to_publish).good();
+
+    let json: Changelog = anon
+        .get("/api/v1/crates/foo_changelog/1.0.0/changelog")
+        .good();
+    assert_eq!(json.changelog, changelog);
+
+    let json = anon.show_crate("foo_changelog");
+    assert_eq!(
+        json.versions[0].changelog_excerpt,
+        Some("Fixed the frobnicator.".into())
+    );
+
+    app.db(|conn| {
+        CrateBuilder::new("foo_changelog_old", user.as_model().id)
+            .version("1.0.0")
+            .expect_build(conn);
+    });
+    anon.get::<()>("/api/v1/crates/foo_changelog_old/1.0.0/changelog")
+        .assert_not_found();
+    let json = anon.show_crate("foo_changelog_old");
+    assert_eq!(json.versions[0].changelog_excerpt, None);
+}
+
+#[test]
+fn publish_enforces_name_pattern() {
+    let (_, _, user) = TestApp::with_proxy_and_config(|config| {
+        config.crate_name_pattern = Some("acme-[a-z0-9_-]+".to_string())
+    }).with_user();
+
+    let json = user
+        .publish(PublishBuilder::new("foo_unprefixed"))
+        .bad_with_status(200);
+    assert!(
+        json.errors[0]
+            .detail
+            .contains("crate names on this registry must match the pattern `acme-[a-z0-9_-]+`"),
+        "{:?}",
+        json.errors
+    );
+
+    // The pattern has to match the whole name
+    let json = user
+        .publish(PublishBuilder::new("foo-acme-bar"))
+        .bad_with_status(200);
+    assert!(json.errors[0].detail.contains("must match the pattern"));
+
+    let json = user.publish(PublishBuilder::new("acme-foo")).good();
+    assert_eq!(json.krate.name, "acme-foo");
+}
+
+#[test]
+fn name_pattern_does_not_apply_to_existing_crates() {
+    let (app, _, user) = TestApp::with_proxy_and_config(|config| {
+        config.crate_name_pattern = Some("acme-[a-z0-9_-]+".to_string())
+    }).with_user();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo_before_pattern", user.as_model().id)
+            .version("1.0.0")
+            .expect_build(conn);
+    });
+
+    let crate_to_publish = PublishBuilder::new("foo_before_pattern").version("1.1.0");
+    let json = user.publish(crate_to_publish).good();
+    assert_eq!(json.krate.name, "foo_before_pattern");
+}
+
+#[test]
+fn publish_rejects_too_many_features() {
+    // The metadata would otherwise be rejected for being over the max upload size
+    let (_, _, user) =
+        TestApp::with_proxy_and_config(|config| config.max_upload_size = 100_000).with_user();
+    let crate_to_publish = (0..301).fold(PublishBuilder::new("foo_many_features"), |p, i| {
+        p.feature(&format!("f{}", i), &[])
+    });
+    let json = user.publish(crate_to_publish).bad_with_status(200);
+    assert!(
+        json.errors[0]
+            .detail
+            .contains("a version can have at most 300 features"),
+        "{:?}",
+        json.errors
+    );
+
+    let crate_to_publish = PublishBuilder::new("foo_many_features")
+        .feature("a", &vec!["b"; 3001]);
+    let json = user.publish(crate_to_publish).bad_with_status(200);
+    assert!(
+        json.errors[0]
+            .detail
+            .contains("features can enable at most 3000 entries in total"),
+        "{:?}",
+        json.errors
+    );
+}
+
+#[test]
+fn publish_rejects_features_of_missing_dependencies() {
+    let (_, _, user) = TestApp::init().with_user();
+
+    for enables in &["serde/std", "serde?/std", "dep:serde"] {
+        let crate_to_publish =
+            PublishBuilder::new("foo_missing_dep_feature").feature("std", &[*enables]);
+        let json = user.publish(crate_to_publish).bad_with_status(200);
+        assert!(
+            json.errors[0]
+                .detail
+                .contains("but `serde` is not a dependency"),
+            "{:?}",
+            json.errors
+        );
+    }
+}
+
+#[test]
+fn publish_accepts_features_of_dependencies() {
+    let (app, _, user) = TestApp::with_proxy().with_user();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo_feature_dep", user.as_model().id).expect_build(conn);
+    });
+
+    // Features refer to dependencies by the name they were given in Cargo.toml
+    let dependency = DependencyBuilder::new("foo_feature_dep").rename("featdep");
+    let crate_to_publish = PublishBuilder::new("foo_features")
+        .dependency(dependency)
+        .feature("tls", &["featdep/tls"])
+        .feature("default", &["tls"]);
+    user.publish(crate_to_publish).good();
+}
+
+#[test]
+fn publish_accepts_compound_dependency_reqs() {
+    let (app, _, user) = TestApp::with_proxy().with_user();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo_compound_dep", user.as_model().id).expect_build(conn);
+    });
+
+    let dependency = DependencyBuilder::new("foo_compound_dep").version_req(">=0.2, <0.4");
+    let crate_to_publish = PublishBuilder::new("foo_compound_req").dependency(dependency);
+    user.publish(crate_to_publish).good();
+
+    // Requirements are stored in their normalized form
+    let stored = app.db(|conn| {
+        dependencies::table
+            .select(dependencies::req)
+            .first::<String>(conn)
+            .unwrap()
+    });
+    let expected = semver::VersionReq::parse(">=0.2, <0.4").unwrap();
+    assert_eq!(stored, expected.to_string());
+}
+
+#[test]
+fn publish_rejects_invalid_dependency_reqs() {
+    let (app, _, user) = TestApp::init().with_user();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo_garbage_dep", user.as_model().id).expect_build(conn);
+    });
+
+    // The builders only allow valid requirements, so swap one in the JSON
+    let dependency = DependencyBuilder::new("foo_garbage_dep");
+    let body = PublishBuilder::new("foo_garbage_req")
+        .dependency(dependency)
+        .body();
+    let json_len = body[..4]
+        .iter()
+        .rev()
+        .fold(0, |len, &byte| len << 8 | byte as usize);
+    let mut json = serde_json::from_slice::<serde_json::Value>(&body[4..4 + json_len]).unwrap();
+    json["deps"][0]["version_req"] = "not-a-version".into();
+    let json = json.to_string();
+    let mut new_body = vec![
+        json.len() as u8,
+        (json.len() >> 8) as u8,
+        (json.len() >> 16) as u8,
+        (json.len() >> 24) as u8,
+    ];
+    new_body.extend(json.as_bytes());
+    new_body.extend(&body[4 + json_len..]);
+
+    let json = user
+        .put::<()>("/api/v1/crates/new", &new_body)
+        .bad_with_status(200);
+    assert!(
+        json.errors[0].detail.contains(
+            "dependency `foo_garbage_dep` has an invalid version requirement `not-a-version`"
+        ),
+        "{:?}",
+        json.errors
+    );
+}
+
+#[test]
+fn publish_rejects_long_changelog() {
+    // The metadata would otherwise be rejected for being over the max upload size
+    let (_, _, user) =
+        TestApp::with_proxy_and_config(|config| config.max_upload_size = 100_000).with_user();
+    let changelog = "a".repeat(64 * 1024 + 1);
+    let crate_to_publish = PublishBuilder::new("foo_long_changelog").changelog(&changelog);
+    let json = user.publish(crate_to_publish).bad_with_status(200);
+    assert!(
+        json.errors[0]
+            .detail
+            .contains("the changelog must not be longer than 65536 bytes"),
+        "{:?}",
+        json.errors
+    );
+}
+
+#[test]
+fn publish_rate_limit() {
+    use schema::recent_publishes;
+
+    let (app, _, user) =
+        TestApp::with_proxy_and_config(|config| config.publish_rate_limit = Some(1)).with_user();
+    let user_id = user.as_model().id;
+
+    // Something was published just now, so the limit has been reached
+    app.db(|conn| {
+        RecentPublish::record(conn, user_id, Utc::now().naive_utc() - Duration::hours(1))
+            .unwrap();
+    });
+    let crate_to_publish = PublishBuilder::new("foo_rate_limit").version("1.0.0");
+    let json = user.publish(crate_to_publish).bad_with_status(429);
+    assert!(json.errors[0].detail.contains("rate limit exceeded"));
+
+    // Publishing is allowed again once that falls out of the window
+    app.db(|conn| {
+        update(recent_publishes::table)
+            .set(recent_publishes::created_at.eq(now - 61.minutes()))
+            .execute(conn)
+            .unwrap();
+    });
+    let crate_to_publish = PublishBuilder::new("foo_rate_limit").version("1.0.0");
+    let json: GoodCrate = user.publish(crate_to_publish).good();
+    assert_eq!(json.krate.name, "foo_rate_limit");
+
+    // The expired publish was cleaned up when the new one was recorded
+    let count = app.db(|conn| {
+        recent_publishes::table
+            .count()
+            .get_result::<i64>(conn)
+            .unwrap()
+    });
+    assert_eq!(count, 1);
+}
+
+#[test]
+fn new_krate_with_token() {
+    let (_, _, _, token) = TestApp::with_proxy().with_token();
+
+    let crate_to_publish = PublishBuilder::new("foo_new").version("1.0.0");
+    let json: GoodCrate = token.publish(crate_to_publish).good();
+
+    assert_eq!(json.krate.name, "foo_new");
+    assert_eq!(json.krate.max_version, "1.0.0");
+}
+
+#[test]
+fn new_krate_weird_version() {
+    let (_, _, _, token) = TestApp::with_proxy().with_token();
 
     let crate_to_publish = PublishBuilder::new("foo_weird").version("0.0.0-pre");
     let json: GoodCrate = token.publish(crate_to_publish).good();
@@ -697,6 +1738,85 @@ fn reject_new_krate_with_non_exact_dependency() {
     token.publish(crate_to_publish).bad_with_status(200);
 }
 
+#[test]
+fn publish_warns_about_dependency_cycles() {
+    let (app, _, user, token) = TestApp::with_proxy().with_token();
+
+    app.db(|conn| {
+        let foo = CrateBuilder::new("foo_cycle", user.as_model().id)
+            .version("0.1.0")
+            .expect_build(&conn);
+        CrateBuilder::new("bar_cycle", user.as_model().id)
+            .version(VersionBuilder::new("1.0.0").dependency(&foo, None))
+            .expect_build(&conn);
+        CrateBuilder::new("baz_cycle", user.as_model().id)
+            .version("1.0.0")
+            .expect_build(&conn);
+    });
+
+    let crate_to_publish = PublishBuilder::new("foo_cycle")
+        .version("1.0.0")
+        .dependency(DependencyBuilder::new("bar_cycle"))
+        .dependency(DependencyBuilder::new("baz_cycle"));
+    let json = token.publish(crate_to_publish).good();
+    assert_eq!(
+        json.warnings.dependency_cycles,
+        vec!["foo_cycle -> bar_cycle -> foo_cycle"]
+    );
+    assert_eq!(
+        json.warnings.other,
+        vec!["this version creates a dependency cycle: foo_cycle -> bar_cycle -> foo_cycle"]
+    );
+}
+
+#[test]
+fn publish_warns_about_large_crates() {
+    let (_, _, user) = TestApp::with_proxy_and_config(|config| {
+        config.publish_size_warning_threshold = Some(20)
+    }).with_user();
+
+    // Only the size of the tarball counts, not the metadata sent along with it
+    let json = user.publish(PublishBuilder::new("foo_large")).good();
+    assert_eq!(json.warnings.other.len(), 1);
+    assert!(
+        json.warnings.other[0]
+            .starts_with("the uploaded crate is 35 bytes, more than the 20 bytes"),
+        "{:?}",
+        json.warnings.other
+    );
+}
+
+#[test]
+fn publish_rejects_dependency_cycles_when_configured() {
+    let (app, _, user, token) =
+        TestApp::with_proxy_and_config(|config| config.reject_dependency_cycles = true)
+            .with_token();
+
+    app.db(|conn| {
+        let foo = CrateBuilder::new("foo_cycle", user.as_model().id)
+            .version("0.1.0")
+            .expect_build(&conn);
+        let bar = CrateBuilder::new("bar_cycle", user.as_model().id)
+            .version(VersionBuilder::new("1.0.0").dependency(&foo, None))
+            .expect_build(&conn);
+        CrateBuilder::new("baz_cycle", user.as_model().id)
+            .version(VersionBuilder::new("1.0.0").dependency(&bar, None))
+            .expect_build(&conn);
+    });
+
+    let crate_to_publish = PublishBuilder::new("foo_cycle")
+        .version("1.0.0")
+        .dependency(DependencyBuilder::new("baz_cycle"));
+    let json = token.publish(crate_to_publish).bad_with_status(200);
+    assert!(
+        json.errors[0]
+            .detail
+            .contains("foo_cycle -> baz_cycle -> bar_cycle -> foo_cycle"),
+        "{:?}",
+        json.errors
+    );
+}
+
 #[test]
 fn new_krate_with_wildcard_dependency() {
     let (app, _, user, token) = TestApp::init().with_token();
@@ -770,6 +1890,15 @@ fn valid_feature_names() {
     assert!(!Crate::valid_feature("%/%"));
     assert!(Crate::valid_feature("a/a"));
     assert!(Crate::valid_feature("32-column-tables"));
+    assert!(Crate::valid_feature("dep:foo"));
+    assert!(Crate::valid_feature("foo?/bar"));
+    assert!(!Crate::valid_feature("dep:"));
+    assert!(!Crate::valid_feature("dep:foo/bar"));
+    assert!(!Crate::valid_feature("foo?"));
+    assert!(!Crate::valid_feature("foo/bar?"));
+    assert_eq!(Crate::feature_syntax_version("foo/bar"), 1);
+    assert_eq!(Crate::feature_syntax_version("dep:foo"), 2);
+    assert_eq!(Crate::feature_syntax_version("foo?/bar"), 2);
 }
 
 #[test]
@@ -822,17 +1951,56 @@ fn new_krate_wrong_files() {
 }
 
 #[test]
-fn new_krate_gzip_bomb() {
-    let (_, _, _, token) = TestApp::init().with_token();
-
-    let len = 512 * 1024;
-    let mut body = io::repeat(0).take(len);
-
-    let crate_to_publish = PublishBuilder::new("foo")
-        .version("1.1.0")
-        .files_with_io(&mut [("foo-1.1.0/a", &mut body, len)]);
-
-    let json = token.publish(crate_to_publish).bad_with_status(200);
+fn new_krate_with_mismatched_manifest() {
+    let (_, _, user) = TestApp::init().with_user();
+
+    let manifest = b"[package]\nname = \"foo_manifest\"\nversion = \"2.0.0\"\n";
+    let files = [("foo_manifest-1.0.0/Cargo.toml", &manifest[..])];
+    let builder = PublishBuilder::new("foo_manifest").files(&files);
+    let json = user.publish(builder).bad_with_status(200);
+    assert!(
+        json.errors[0].detail.contains(
+            "the Cargo.toml in the uploaded tarball is for `foo_manifest 2.0.0`, \
+             but `foo_manifest 1.0.0` is being published"
+        ),
+        "{:?}",
+        json.errors
+    );
+
+    let manifest = b"[project]\nname = \"bar\"\nversion = \"1.0.0\"\n";
+    let files = [("foo_manifest-1.0.0/Cargo.toml", &manifest[..])];
+    let builder = PublishBuilder::new("foo_manifest").files(&files);
+    let json = user.publish(builder).bad_with_status(200);
+    assert!(
+        json.errors[0]
+            .detail
+            .contains("the Cargo.toml in the uploaded tarball is for `bar 1.0.0`"),
+        "{:?}",
+        json.errors
+    );
+
+    let files = [("foo_manifest-1.0.0/Cargo.toml", &b"[package"[..])];
+    let builder = PublishBuilder::new("foo_manifest").files(&files);
+    let json = user.publish(builder).bad_with_status(200);
+    assert!(
+        json.errors[0].detail.contains("is not valid TOML"),
+        "{:?}",
+        json.errors
+    );
+}
+
+#[test]
+fn new_krate_gzip_bomb() {
+    let (_, _, _, token) = TestApp::init().with_token();
+
+    let len = 512 * 1024;
+    let mut body = io::repeat(0).take(len);
+
+    let crate_to_publish = PublishBuilder::new("foo")
+        .version("1.1.0")
+        .files_with_io(&mut [("foo-1.1.0/a", &mut body, len)]);
+
+    let json = token.publish(crate_to_publish).bad_with_status(200);
 
     assert!(
         json.errors[0]
@@ -924,6 +2092,28 @@ fn new_crate_similar_name_underscore() {
     );
 }
 
+#[test]
+fn new_crate_similar_name_case_and_separator() {
+    let (app, _, user, token) = TestApp::init().with_token();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo-bar-mixed", user.as_model().id)
+            .version("1.0.0")
+            .expect_build(&conn);
+    });
+
+    let crate_to_publish = PublishBuilder::new("Foo_Bar_Mixed").version("1.1.0");
+    let json = token.publish(crate_to_publish).bad_with_status(200);
+
+    assert!(
+        json.errors[0]
+            .detail
+            .contains("crate was previously named `foo-bar-mixed`"),
+        "{:?}",
+        json.errors
+    );
+}
+
 #[test]
 fn new_krate_git_upload() {
     let (_, _, _, token) = TestApp::with_proxy().with_token();
@@ -983,166 +2173,937 @@ fn new_krate_git_upload_with_conflicts() {
         let tree = repo.find_tree(parent.tree_id()).unwrap();
         repo.commit(Some("HEAD"), &sig, &sig, "empty commit", &tree, &[&parent])
             .unwrap();
-    }
+    }
+
+    let (_, _, _, token) = TestApp::with_proxy().with_token();
+    let crate_to_publish = PublishBuilder::new("foo_conflicts");
+    token.publish(crate_to_publish).good();
+}
+
+#[test]
+fn publish_with_unavailable_index() {
+    use schema::pending_index_writes;
+
+    let (app, _, _, token) =
+        TestApp::with_proxy_and_config(|config| config.queue_index_writes = true).with_token();
+
+    // Take the index's remote away so that pushing to it fails
+    let moved = ::git::bare().with_extension("moved");
+    fs::rename(::git::bare(), &moved).unwrap();
+
+    let crate_to_publish = PublishBuilder::new("foo_queued").version("1.0.0");
+    token.publish(crate_to_publish).assert_status(202);
+    let pending = app.db(|conn| {
+        pending_index_writes::table
+            .count()
+            .get_result::<i64>(conn)
+            .unwrap()
+    });
+    assert_eq!(pending, 1);
+
+    // Yanking the version before its entry is written doesn't need the index
+    token.yank("foo_queued", "1.0.0").good();
+
+    // Once the remote is back the queued entry gets written, exactly once
+    fs::rename(&moved, ::git::bare()).unwrap();
+    assert_eq!(git::write_pending_entries(app.as_inner()).unwrap(), 1);
+
+    // An entry that's queued again, as if its row couldn't be deleted after
+    // it was written, isn't added a second time
+    let written = git::crate_entries(app.as_inner(), "foo_queued").unwrap();
+    let entry = serde_json::to_value(&written[0]).unwrap();
+    app.db(|conn| {
+        let version_id = versions::table
+            .select(versions::id)
+            .first::<i32>(conn)
+            .unwrap();
+        PendingIndexWrite::record(conn, version_id, &entry).unwrap();
+    });
+    assert_eq!(git::write_pending_entries(app.as_inner()).unwrap(), 1);
+
+    let remote_contents = clone_remote_repo();
+    let mut contents = String::new();
+    File::open(remote_contents.path().join("fo/o_/foo_queued"))
+        .unwrap()
+        .read_to_string(&mut contents)
+        .unwrap();
+    let entries = contents.lines().collect::<Vec<_>>();
+    assert_eq!(entries.len(), 1);
+    let p: git::Crate = serde_json::from_str(entries[0]).unwrap();
+    assert_eq!(p.vers, "1.0.0");
+    assert_eq!(p.yanked, Some(true));
+
+    let pending = app.db(|conn| {
+        pending_index_writes::table
+            .count()
+            .get_result::<i64>(conn)
+            .unwrap()
+    });
+    assert_eq!(pending, 0);
+}
+
+#[test]
+fn publish_rolls_back_when_index_fails() {
+    let (app, _, user, token) = TestApp::with_proxy().with_token();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo_rollback_dep", user.as_model().id).expect_build(conn);
+    });
+
+    // Take the index's remote away so that pushing to it fails
+    let moved = ::git::bare().with_extension("moved");
+    fs::rename(::git::bare(), &moved).unwrap();
+
+    let crate_to_publish = PublishBuilder::new("foo_rollback")
+        .version("1.0.0")
+        .dependency(DependencyBuilder::new("foo_rollback_dep"));
+    let error = token.publish_internal_error(crate_to_publish);
+    assert!(
+        error.contains("could not add crate `foo_rollback` to the git repo"),
+        "{}",
+        error
+    );
+    fs::rename(&moved, ::git::bare()).unwrap();
+
+    // The uploaded file was deleted again, and nothing was kept in the database
+    let (krates, versions, dependencies) = app.db(|conn| {
+        (
+            crates::table
+                .filter(crates::name.eq("foo_rollback"))
+                .count()
+                .get_result::<i64>(conn)
+                .unwrap(),
+            versions::table.count().get_result::<i64>(conn).unwrap(),
+            dependencies::table.count().get_result::<i64>(conn).unwrap(),
+        )
+    });
+    assert_eq!((krates, versions, dependencies), (0, 0, 0));
+}
+
+#[test]
+fn new_krate_with_invalid_keywords() {
+    let (_, _, _, token) = TestApp::init().with_token();
+
+    let too_many = ["one", "two", "three", "four", "five", "six"]
+        .iter()
+        .fold(PublishBuilder::new("foo_keywords"), |builder, kw| {
+            builder.keyword(kw)
+        });
+    let json = token.publish(too_many).bad_with_status(200);
+    assert!(json.errors[0].detail.contains("at most 5 keywords per crate"));
+
+    let too_long = PublishBuilder::new("foo_keywords").keyword("aaaaaaaaaaaaaaaaaaaaa");
+    let json = token.publish(too_long).bad_with_status(200);
+    assert!(
+        json.errors[0]
+            .detail
+            .contains("a keyword with less than 20 characters")
+    );
+
+    let invalid = PublishBuilder::new("foo_keywords").keyword("-http");
+    let json = token.publish(invalid).bad_with_status(200);
+    assert!(json.errors[0].detail.contains("a valid keyword specifier"));
+}
+
+#[test]
+fn new_krate_dependency_missing() {
+    let (_, _, _, token) = TestApp::init().with_token();
+
+    // Deliberately not inserting this crate in the database to test behavior when a dependency
+    // doesn't exist!
+    let dependency = DependencyBuilder::new("bar_missing");
+    let crate_to_publish = PublishBuilder::new("foo_missing").dependency(dependency);
+
+    let json = token.publish(crate_to_publish).bad_with_status(200);
+    assert!(
+        json.errors[0]
+            .detail
+            .contains("no known crate named `bar_missing`"),
+        "{:?}",
+        json.errors
+    );
+}
+
+#[test]
+fn new_krate_with_readme() {
+    let (_, _, _, token) = TestApp::with_proxy().with_token();
+
+    let crate_to_publish = PublishBuilder::new("foo_readme").readme("");
+    let json = token.publish(crate_to_publish).good();
+
+    assert_eq!(json.krate.name, "foo_readme");
+    assert_eq!(json.krate.max_version, "1.0.0");
+}
+
+#[test]
+fn summary_doesnt_die() {
+    let (_, anon) = TestApp::init().empty();
+    anon.get::<SummaryResponse>("/api/v1/summary").good();
+}
+
+#[test]
+fn summary_new_crates() {
+    let (app, anon, user) = TestApp::init().with_user();
+    let user = user.as_model();
+    app.db(|conn| {
+        let krate = CrateBuilder::new("some_downloads", user.id)
+            .version(VersionBuilder::new("0.1.0"))
+            .description("description")
+            .keyword("popular")
+            .downloads(20)
+            .recent_downloads(10)
+            .expect_build(conn);
+
+        let krate2 = CrateBuilder::new("most_recent_downloads", user.id)
+            .version(VersionBuilder::new("0.2.0"))
+            .keyword("popular")
+            .downloads(5000)
+            .recent_downloads(50)
+            .expect_build(conn);
+
+        let krate3 = CrateBuilder::new("just_updated", user.id)
+            .version(VersionBuilder::new("0.1.0"))
+            .expect_build(conn);
+
+        CrateBuilder::new("with_downloads", user.id)
+            .version(VersionBuilder::new("0.3.0"))
+            .keyword("popular")
+            .downloads(1000)
+            .expect_build(conn);
+
+        new_category("Category 1", "cat1", "Category 1 crates")
+            .create_or_update(conn)
+            .unwrap();
+        Category::update_crate(conn, &krate, &["cat1"]).unwrap();
+        Category::update_crate(conn, &krate2, &["cat1"]).unwrap();
+
+        // set total_downloads global value for `num_downloads` prop
+        update(metadata::table)
+            .set(metadata::total_downloads.eq(6000))
+            .execute(&*conn)
+            .unwrap();
+
+        // update 'just_updated' krate. Others won't appear because updated_at == created_at.
+        let updated = Utc::now().naive_utc();
+        update(&krate3)
+            .set(crates::updated_at.eq(updated))
+            .execute(&*conn)
+            .unwrap();
+    });
+
+    let json: SummaryResponse = anon.get("/api/v1/summary").good();
+
+    assert_eq!(json.num_crates, 4);
+    assert_eq!(json.num_downloads, 6000);
+    assert_eq!(json.most_downloaded[0].name, "most_recent_downloads");
+    assert_eq!(
+        json.most_recently_downloaded[0].name,
+        "most_recent_downloads"
+    );
+    assert_eq!(json.popular_keywords[0].keyword, "popular");
+    assert_eq!(json.popular_categories[0].category, "Category 1");
+    assert_eq!(json.just_updated.len(), 1);
+    assert_eq!(json.just_updated[0].name, "just_updated");
+    assert_eq!(json.new_crates.len(), 4);
+}
+
+#[test]
+fn download() {
+    use chrono::{Duration, Utc};
+    let (app, anon, user) = TestApp::with_proxy().with_user();
+    let user = user.as_model();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo_download", user.id)
+            .version(VersionBuilder::new("1.0.0"))
+            .expect_build(&conn);
+    });
+
+    let assert_dl_count = |name_and_version: &str, query: Option<&str>, count: i32| {
+        let url = format!("/api/v1/crates/{}/downloads", name_and_version);
+        let downloads: Downloads = if let Some(query) = query {
+            anon.get_with_query(&url, query).good()
+        } else {
+            anon.get(&url).good()
+        };
+        let total_downloads = downloads
+            .version_downloads
+            .iter()
+            .map(|vd| vd.downloads)
+            .sum::<i32>();
+        assert_eq!(total_downloads, count);
+    };
+
+    let download = |name_and_version: &str| {
+        let url = format!("/api/v1/crates/{}/download", name_and_version);
+        anon.get::<()>(&url).assert_status(302);
+        // TODO: test the with_json code path
+    };
+
+    download("foo_download/1.0.0");
+    assert_dl_count("foo_download/1.0.0", None, 1);
+    assert_dl_count("foo_download", None, 1);
+
+    download("FOO_DOWNLOAD/1.0.0");
+    assert_dl_count("FOO_DOWNLOAD/1.0.0", None, 2);
+    assert_dl_count("FOO_DOWNLOAD", None, 2);
+
+    let yesterday = (Utc::today() + Duration::days(-1)).format("%F");
+    let query = format!("before_date={}", yesterday);
+    assert_dl_count("FOO_DOWNLOAD/1.0.0", Some(&query), 0);
+    // crate/downloads always returns the last 90 days and ignores date params
+    assert_dl_count("FOO_DOWNLOAD", Some(&query), 2);
+
+    let tomorrow = (Utc::today() + Duration::days(1)).format("%F");
+    let query = format!("before_date={}", tomorrow);
+    assert_dl_count("FOO_DOWNLOAD/1.0.0", Some(&query), 2);
+    assert_dl_count("FOO_DOWNLOAD", Some(&query), 2);
+}
+
+#[test]
+fn crate_downloads_for_the_last_days() {
+    use schema::version_downloads;
+
+    let (app, anon, user) = TestApp::init().with_user();
+    let user = user.as_model();
+
+    app.db(|conn| {
+        let krate = CrateBuilder::new("foo_days", user.id)
+            .version("1.0.0")
+            .expect_build(&conn);
+        let version = versions::table
+            .filter(versions::crate_id.eq(krate.id))
+            .first::<Version>(conn)
+            .unwrap();
+        let today = Utc::today().naive_utc();
+        diesel::insert_into(version_downloads::table)
+            .values(&vec![
+                (
+                    version_downloads::version_id.eq(version.id),
+                    version_downloads::downloads.eq(3),
+                    version_downloads::date.eq(today),
+                ),
+                (
+                    version_downloads::version_id.eq(version.id),
+                    version_downloads::downloads.eq(5),
+                    version_downloads::date.eq(today - Duration::days(10)),
+                ),
+            ]).execute(conn)
+            .unwrap();
+    });
+
+    let url = "/api/v1/crates/foo_days/downloads";
+    let total = |query: &str| {
+        let downloads: Downloads = anon.get_with_query(url, query).good();
+        downloads
+            .version_downloads
+            .iter()
+            .map(|vd| vd.downloads)
+            .sum::<i32>()
+    };
+    assert_eq!(total(""), 8);
+    assert_eq!(total("days=30"), 8);
+    assert_eq!(total("days=7"), 3);
+
+    anon.get_with_query::<()>(url, "days=0").assert_status(400);
+    anon.get_with_query::<()>(url, "days=91").assert_status(400);
+}
+
+#[test]
+fn changed_crates() {
+    #[derive(Deserialize)]
+    struct ChangedCrates {
+        crates: Vec<ChangedCrate>,
+        meta: ChangedMeta,
+    }
+    #[derive(Deserialize)]
+    struct ChangedCrate {
+        name: String,
+        downloads: i32,
+    }
+    #[derive(Deserialize)]
+    struct ChangedMeta {
+        next_page: Option<String>,
+    }
+
+    let (app, anon, user) = TestApp::init().with_user();
+    let user = user.as_model();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo_changed", user.id)
+            .downloads(10)
+            .expect_build(&conn);
+        CrateBuilder::new("foo_unchanged", user.id)
+            .downloads(20)
+            .expect_build(&conn);
+        update(crates::table.filter(crates::name.eq("foo_unchanged")))
+            .set(crates::stats_updated_at.eq(now - 2.days()))
+            .execute(conn)
+            .unwrap();
+    });
+
+    let url = "/api/v1/changed_crates";
+    let since = (Utc::now() - Duration::days(1)).format("%Y-%m-%dT%H:%M:%SZ");
+    let query = format!("ts={}", since);
+    let json: ChangedCrates = anon.get_with_query(url, &query).good();
+    assert_eq!(json.crates.len(), 1);
+    assert_eq!(json.crates[0].name, "foo_changed");
+    assert_eq!(json.crates[0].downloads, 10);
+
+    // Only a change to the download count brings a crate back
+    app.db(|conn| {
+        update(crates::table.filter(crates::name.eq("foo_unchanged")))
+            .set(crates::description.eq("changed"))
+            .execute(conn)
+            .unwrap();
+    });
+    let json: ChangedCrates = anon.get_with_query(url, &query).good();
+    assert_eq!(json.crates.len(), 1);
+    assert_eq!(json.meta.next_page, None);
+
+    app.db(|conn| {
+        update(crates::table.filter(crates::name.eq("foo_unchanged")))
+            .set(crates::downloads.eq(crates::downloads + 1))
+            .execute(conn)
+            .unwrap();
+    });
+    let json: ChangedCrates = anon.get_with_query(url, &query).good();
+    assert_eq!(json.crates.len(), 2);
+    assert!(
+        json.crates
+            .iter()
+            .any(|krate| krate.name == "foo_unchanged" && krate.downloads == 21)
+    );
+
+    // Following the links one crate at a time gets to every crate once, even
+    // though both changed in the same transaction
+    let mut names = Vec::new();
+    let mut page = format!("{}&per_page=1", query);
+    loop {
+        let json: ChangedCrates = anon.get_with_query(url, &page).good();
+        names.extend(json.crates.into_iter().map(|krate| krate.name));
+        match json.meta.next_page {
+            Some(next) => page = next[1..].to_string(),
+            None => break,
+        }
+    }
+    names.sort();
+    assert_eq!(names, ["foo_changed", "foo_unchanged"]);
+
+    anon.get::<()>(url).assert_status(400);
+    anon.get_with_query::<()>(url, "ts=yesterday").assert_status(400);
+    let query = format!("{}&after_id=first", query);
+    anon.get_with_query::<()>(url, &query).assert_status(400);
+}
+
+#[test]
+fn create_or_update_reuses_the_existing_row() {
+    use schema::crate_owners;
+
+    let (app, _, user) = TestApp::init().with_user();
+    let user = user.as_model();
+
+    app.db(|conn| {
+        let new_crate = || NewCrate {
+            name: "foo_upsert",
+            ..NewCrate::default()
+        };
+        let first = new_crate().create_or_update(conn, None, user.id).unwrap();
+        let second = NewCrate {
+            name: "FOO-UPSERT",
+            description: Some("updated"),
+            ..NewCrate::default()
+        }.create_or_update(conn, None, user.id)
+        .unwrap();
+        let third = new_crate().create_or_update(conn, None, user.id).unwrap();
+
+        assert_eq!(first.id, second.id);
+        assert_eq!(first.id, third.id);
+        assert_eq!(second.description, Some("updated".to_string()));
+
+        // Counted by name, as a second row would have an id of its own
+        let crate_count = crates::table
+            .filter(canon_crate_name(crates::name).eq("foo_upsert"))
+            .count()
+            .get_result::<i64>(conn)
+            .unwrap();
+        assert_eq!(crate_count, 1);
+
+        // Only the insert that created the crate adds its owner
+        let owner_count = crate_owners::table
+            .filter(crate_owners::crate_id.eq(first.id))
+            .count()
+            .get_result::<i64>(conn)
+            .unwrap();
+        assert_eq!(owner_count, 1);
+    });
+}
+
+#[test]
+fn download_without_counting() {
+    let (app, anon, user) =
+        TestApp::with_proxy_and_config(|config| config.count_downloads = false).with_user();
+    let user = user.as_model();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo_uncounted", user.id)
+            .version(VersionBuilder::new("1.0.0"))
+            .expect_build(&conn);
+    });
+
+    anon.get::<()>("/api/v1/crates/foo_uncounted/1.0.0/download")
+        .assert_status(302);
+
+    let downloads: Downloads = anon.get("/api/v1/crates/foo_uncounted/downloads").good();
+    assert!(downloads.version_downloads.is_empty());
+    let json: CrateResponse = anon.get("/api/v1/crates/foo_uncounted").good();
+    assert_eq!(json.krate.downloads, 0);
+}
+
+#[test]
+fn download_with_buffering() {
+    let (app, anon, user) =
+        TestApp::with_proxy_and_config(|config| config.buffer_downloads = true).with_user();
+    let user = user.as_model();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo_buffered", user.id)
+            .version(VersionBuilder::new("1.0.0"))
+            .version(VersionBuilder::new("2.0.0"))
+            .expect_build(&conn);
+    });
+
+    let download = |version: &str, times: usize| {
+        let url = format!("/api/v1/crates/foo_buffered/{}/download", version);
+        for _ in 0..times {
+            anon.get::<()>(&url).assert_status(302);
+        }
+    };
+    let recorded_downloads = || {
+        let mut downloads = app.db(|conn| {
+            version_downloads::table
+                .inner_join(versions::table)
+                .select((versions::num, version_downloads::downloads))
+                .load::<(String, i32)>(conn)
+                .unwrap()
+        });
+        downloads.sort();
+        downloads
+    };
+
+    download("1.0.0", 5);
+    download("2.0.0", 2);
+    assert!(recorded_downloads().is_empty());
+
+    app.db(|conn| flush_downloads(&app, conn));
+    assert_eq!(
+        recorded_downloads(),
+        vec![("1.0.0".to_string(), 5), ("2.0.0".to_string(), 2)]
+    );
+
+    // Later flushes add to the same rows, and only write what's new
+    download("1.0.0", 3);
+    app.db(|conn| flush_downloads(&app, conn));
+    app.db(|conn| flush_downloads(&app, conn));
+    assert_eq!(
+        recorded_downloads(),
+        vec![("1.0.0".to_string(), 8), ("2.0.0".to_string(), 2)]
+    );
+}
+
+fn flush_downloads(app: &TestApp, conn: &PgConnection) {
+    let app = app.as_inner();
+    app.pending_downloads.flush(conn, &app.version_ids).unwrap();
+}
+
+#[test]
+fn buffered_downloads_notice_deleted_versions() {
+    let (app, anon, user) = TestApp::with_proxy_and_config(|config| {
+        config.buffer_downloads = true;
+        config.download_cache_size = 10;
+    }).with_user();
+    let user = user.as_model();
+
+    let version = app.db(|conn| {
+        let krate = CrateBuilder::new("foo_buffered_cached", user.id)
+            .version("1.0.0")
+            .expect_build(&conn);
+        VersionBuilder::new("2.0.0").expect_build(krate.id, &conn)
+    });
+    let url = "/api/v1/crates/foo_buffered_cached/2.0.0/download";
+    anon.get::<()>(url).assert_status(302);
+    app.db(|conn| flush_downloads(&app, conn));
+
+    // Until the next flush, nothing checks that a cached version still exists
+    app.db(|conn| {
+        diesel::delete(versions::table.find(version.id))
+            .execute(conn)
+            .unwrap()
+    });
+    anon.get::<()>(url).assert_status(302);
+
+    app.db(|conn| flush_downloads(&app, conn));
+    let version_ids = &app.as_inner().version_ids;
+    assert_eq!(version_ids.get("foo_buffered_cached", "2.0.0"), None);
+    anon.get::<()>(url).assert_not_found();
+}
+
+#[test]
+fn download_caches_version_ids() {
+    let (app, anon, _, token) =
+        TestApp::with_proxy_and_config(|config| config.download_cache_size = 10).with_token();
+    let user = token.as_model();
+
+    let version = app.db(|conn| {
+        let krate = CrateBuilder::new("foo_cached", user.user_id)
+            .version("1.0.0")
+            .expect_build(&conn);
+        VersionBuilder::new("2.0.0").expect_build(krate.id, &conn)
+    });
+    // Yanking updates the index entry
+    let entry = git::Crate {
+        name: "foo_cached".to_string(),
+        vers: "2.0.0".to_string(),
+        deps: Vec::new(),
+        cksum: String::new(),
+        features: HashMap::new(),
+        features2: None,
+        yanked: Some(false),
+        links: None,
+        v: None,
+    };
+    git::add_crate(app.as_inner(), &entry).unwrap();
+    let version_ids = &app.as_inner().version_ids;
+
+    anon.get::<()>("/api/v1/crates/foo_cached/2.0.0/download")
+        .assert_status(302);
+    assert_eq!(version_ids.get("foo_cached", "2.0.0"), Some(version.id));
+    assert_eq!(version_ids.get("FOO-CACHED", "2.0.0"), Some(version.id));
+
+    // Downloads of cached versions are still counted
+    anon.get::<()>("/api/v1/crates/FOO_CACHED/2.0.0/download")
+        .assert_status(302);
+    let downloads: Downloads = anon.get("/api/v1/crates/foo_cached/2.0.0/downloads").good();
+    assert_eq!(downloads.version_downloads[0].downloads, 2);
+
+    token.yank("foo_cached", "2.0.0").good();
+    assert_eq!(version_ids.get("foo_cached", "2.0.0"), None);
+
+    // Versions deleted behind the cache's back are noticed when counting
+    anon.get::<()>("/api/v1/crates/foo_cached/2.0.0/download")
+        .assert_status(302);
+    app.db(|conn| {
+        diesel::delete(versions::table.find(version.id))
+            .execute(conn)
+            .unwrap()
+    });
+    anon.get::<()>("/api/v1/crates/foo_cached/2.0.0/download")
+        .assert_not_found();
+    assert_eq!(version_ids.get("foo_cached", "2.0.0"), None);
+}
+
+#[test]
+fn version_id_cache_forgets_least_recently_used() {
+    let cache = VersionIdCache::new(2);
+    cache.insert("foo", "1.0.0", 1);
+    cache.insert("foo", "2.0.0", 2);
+    assert_eq!(cache.get("foo", "1.0.0"), Some(1));
+    cache.insert("bar", "1.0.0", 3);
+    assert_eq!(cache.get("foo", "1.0.0"), Some(1));
+    assert_eq!(cache.get("foo", "2.0.0"), None);
+    assert_eq!(cache.get("bar", "1.0.0"), Some(3));
+
+    let disabled = VersionIdCache::new(0);
+    disabled.insert("foo", "1.0.0", 1);
+    assert_eq!(disabled.get("foo", "1.0.0"), None);
+}
+
+#[test]
+fn download_rejects_malformed_paths() {
+    // Without counting, nothing checks the version exists before redirecting
+    let (app, anon, user) =
+        TestApp::with_proxy_and_config(|config| config.count_downloads = false).with_user();
+    let user = user.as_model();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo", user.id)
+            .version(VersionBuilder::new("1.0.0"))
+            .expect_build(&conn);
+    });
+
+    let urls = [
+        "/api/v1/crates/foo/bar-1.0.0.tar.gz/download",
+        "/api/v1/crates/foo/1.0.0.tar.gz/download",
+        "/api/v1/crates/foo/../download",
+        "/api/v1/crates/../1.0.0/download",
+        "/api/v1/crates/foo%2F..%2Fbar/1.0.0/download",
+    ];
+    for url in &urls {
+        anon.get::<()>(url).assert_not_found();
+    }
+    anon.get::<()>("/api/v1/crates/foo/1.0.0/download")
+        .assert_status(302);
+}
+
+#[test]
+fn reads_require_a_user_when_configured() {
+    let (app, anon, user, token) =
+        TestApp::with_proxy_and_config(|config| config.require_auth_for_reads = true)
+            .with_token();
+    let user = user.as_model();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo_private", user.id)
+            .version("1.0.0")
+            .expect_build(&conn);
+    });
+
+    let urls = [
+        "/api/v1/summary",
+        "/api/v1/crates",
+        "/api/v1/crates/foo_private",
+        "/api/v1/crates/foo_private/versions",
+        "/api/v1/crates/foo_private/1.0.0",
+        "/api/v1/crates/foo_private/1.0.0/download",
+        "/api/v1/crates/foo_private/1.0.0/dependencies",
+        "/api/v1/crates/foo_private/reverse_dependencies",
+        "/api/v1/crate_names",
+        "/api/v1/changed_crates",
+        "/api/v1/keywords",
+        "/api/v1/categories",
+        "/api/v1/crates/missing",
+    ];
+    for url in &urls {
+        anon.get::<()>(url).assert_status(401);
+    }
+    let body = json!({ "crates": [{ "name": "foo_private", "version": "1.0.0" }] });
+    anon.post::<()>("/api/v1/checksums/verify", body.to_string().as_bytes())
+        .assert_status(401);
+    let body = json!({ "ids": ["foo_private"] });
+    anon.post::<()>("/api/v1/crates/lookup", body.to_string().as_bytes())
+        .assert_status(401);
+
+    token.get::<CrateList>("/api/v1/crates").good();
+    token.get::<CrateResponse>("/api/v1/crates/foo_private").good();
+    token
+        .get::<()>("/api/v1/crates/foo_private/versions")
+        .assert_status(200);
+    token
+        .get::<()>("/api/v1/crates/foo_private/1.0.0/download")
+        .assert_status(302);
+}
+
+#[test]
+fn download_json_includes_source_url_and_yanked() {
+    #[derive(Deserialize)]
+    struct Download {
+        url: String,
+        name: String,
+        version: String,
+        git_ref: Option<String>,
+        source_url: Option<String>,
+        yanked: bool,
+    }
+
+    let (app, anon, user) = TestApp::with_proxy().with_user();
+    let user = user.as_model();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo_source", user.id)
+            .repository("https://github.com/foo/foo_source.git")
+            .version("1.0.0")
+            .version(VersionBuilder::new("1.1.0").yanked(true))
+            .expect_build(conn);
+        CrateBuilder::new("foo_elsewhere", user.id)
+            .repository("https://git.example.com/foo_elsewhere")
+            .version("1.0.0")
+            .expect_build(conn);
+        update(versions::table.filter(versions::num.eq("1.0.0")))
+            .set(versions::git_ref.eq("v1.0.0"))
+            .execute(conn)
+            .unwrap();
+    });
 
-    let (_, _, _, token) = TestApp::with_proxy().with_token();
-    let crate_to_publish = PublishBuilder::new("foo_conflicts");
-    token.publish(crate_to_publish).good();
+    let json: Download = anon
+        .get_json("/api/v1/crates/foo_source/1.0.0/download")
+        .good();
+    assert!(json.url.ends_with("/crates/foo_source/foo_source-1.0.0.crate"));
+    assert_eq!(json.name, "foo_source");
+    assert_eq!(json.version, "1.0.0");
+    assert_eq!(json.git_ref, Some("v1.0.0".into()));
+    assert_eq!(
+        json.source_url,
+        Some("https://github.com/foo/foo_source/tree/v1.0.0".into())
+    );
+    assert!(!json.yanked);
+
+    // Other hosts may not link to refs the same way, so only the ref is given
+    let json: Download = anon
+        .get_json("/api/v1/crates/foo_elsewhere/1.0.0/download")
+        .good();
+    assert_eq!(json.git_ref, Some("v1.0.0".into()));
+    assert_eq!(json.source_url, None);
+
+    // Yanked versions are still served, but flagged as such
+    let json: Download = anon
+        .get_json("/api/v1/crates/foo_source/1.1.0/download")
+        .good();
+    assert_eq!(json.git_ref, None);
+    assert_eq!(json.source_url, None);
+    assert!(json.yanked);
 }
 
 #[test]
-fn new_krate_dependency_missing() {
+fn publish_rejects_invalid_git_ref() {
     let (_, _, _, token) = TestApp::init().with_token();
 
-    // Deliberately not inserting this crate in the database to test behavior when a dependency
-    // doesn't exist!
-    let dependency = DependencyBuilder::new("bar_missing");
-    let crate_to_publish = PublishBuilder::new("foo_missing").dependency(dependency);
+    for git_ref in &["main?tab=readme", "v1.0 beta", "main#readme", "../../foo"] {
+        let body = PublishBuilder::new("foo_bad_ref").body();
+        let headers = [("X-Cargo-Pkg-Git-Ref", *git_ref)];
+        let json = token
+            .put_with_headers::<()>("/api/v1/crates/new", &body, &headers)
+            .bad_with_status(200);
+        assert!(
+            json.errors[0]
+                .detail
+                .contains("invalid `X-Cargo-Pkg-Git-Ref` header")
+        );
+    }
+}
 
-    let json = token.publish(crate_to_publish).bad_with_status(200);
+fn version_error(version: &str) -> String {
+    let json = serde_json::to_string(version).unwrap();
+    serde_json::from_str::<CrateVersion>(&json)
+        .unwrap_err()
+        .to_string()
+}
+
+#[test]
+fn invalid_version_suggests_adding_missing_components() {
+    assert!(version_error("1.2").contains("missing patch component: did you mean `1.2.0`?"));
     assert!(
-        json.errors[0]
-            .detail
-            .contains("no known crate named `bar_missing`"),
-        "{:?}",
-        json.errors
+        version_error("1")
+            .contains("missing minor and patch components: did you mean `1.0.0`?")
+    );
+    assert!(
+        version_error("1.2-beta.1")
+            .contains("missing patch component: did you mean `1.2.0-beta.1`?")
     );
 }
 
 #[test]
-fn new_krate_with_readme() {
-    let (_, _, _, token) = TestApp::with_proxy().with_token();
-
-    let crate_to_publish = PublishBuilder::new("foo_readme").readme("");
-    let json = token.publish(crate_to_publish).good();
-
-    assert_eq!(json.krate.name, "foo_readme");
-    assert_eq!(json.krate.max_version, "1.0.0");
+fn invalid_version_suggests_removing_prefixes() {
+    assert!(version_error("v1.2.3").contains("leading `v`: did you mean `1.2.3`?"));
+    assert!(version_error(" =1.2 ").contains(
+        "surrounding whitespace, leading `=`, missing patch component: did you mean `1.2.0`?"
+    ));
 }
 
 #[test]
-fn summary_doesnt_die() {
-    let (_, anon) = TestApp::init().empty();
-    anon.get::<SummaryResponse>("/api/v1/summary").good();
+fn no_version_suggestion_when_far_from_semver() {
+    for version in &["latest", "1.2.3.4", "vx.y"] {
+        assert!(!version_error(version).contains("did you mean"));
+    }
 }
 
 #[test]
-fn summary_new_crates() {
-    let (app, anon, user) = TestApp::init().with_user();
+fn download_redirect_can_be_cached() {
+    let (app, anon, user) = TestApp::with_proxy().with_user();
     let user = user.as_model();
-    app.db(|conn| {
-        let krate = CrateBuilder::new("some_downloads", user.id)
-            .version(VersionBuilder::new("0.1.0"))
-            .description("description")
-            .keyword("popular")
-            .downloads(20)
-            .recent_downloads(10)
-            .expect_build(conn);
-
-        let krate2 = CrateBuilder::new("most_recent_downloads", user.id)
-            .version(VersionBuilder::new("0.2.0"))
-            .keyword("popular")
-            .downloads(5000)
-            .recent_downloads(50)
-            .expect_build(conn);
 
-        let krate3 = CrateBuilder::new("just_updated", user.id)
-            .version(VersionBuilder::new("0.1.0"))
+    app.db(|conn| {
+        CrateBuilder::new("foo_cached_redirect", user.id)
+            .version("1.0.0")
             .expect_build(conn);
+    });
 
-        CrateBuilder::new("with_downloads", user.id)
-            .version(VersionBuilder::new("0.3.0"))
-            .keyword("popular")
-            .downloads(1000)
-            .expect_build(conn);
+    let response = anon.get::<()>("/api/v1/crates/foo_cached_redirect/1.0.0/download");
+    response.assert_status(302);
+    assert_eq!(response.header("Cache-Control"), Some("public, max-age=3600"));
+}
 
-        new_category("Category 1", "cat1", "Category 1 crates")
-            .create_or_update(conn)
-            .unwrap();
-        Category::update_crate(conn, &krate, &["cat1"]).unwrap();
-        Category::update_crate(conn, &krate2, &["cat1"]).unwrap();
+#[test]
+fn download_redirects_to_signed_url_when_configured() {
+    #[derive(Deserialize)]
+    struct Download {
+        url: String,
+    }
 
-        // set total_downloads global value for `num_downloads` prop
-        update(metadata::table)
-            .set(metadata::total_downloads.eq(6000))
-            .execute(&*conn)
-            .unwrap();
+    let (app, anon, user) =
+        TestApp::with_proxy_and_config(|config| config.signed_download_expiry_secs = Some(300))
+            .with_user();
+    let user = user.as_model();
 
-        // update 'just_updated' krate. Others won't appear because updated_at == created_at.
-        let updated = Utc::now().naive_utc();
-        update(&krate3)
-            .set(crates::updated_at.eq(updated))
-            .execute(&*conn)
-            .unwrap();
+    app.db(|conn| {
+        CrateBuilder::new("foo_signed", user.id)
+            .version("1.0.0")
+            .expect_build(conn);
     });
 
-    let json: SummaryResponse = anon.get("/api/v1/summary").good();
-
-    assert_eq!(json.num_crates, 4);
-    assert_eq!(json.num_downloads, 6000);
-    assert_eq!(json.most_downloaded[0].name, "most_recent_downloads");
-    assert_eq!(
-        json.most_recently_downloaded[0].name,
-        "most_recent_downloads"
-    );
-    assert_eq!(json.popular_keywords[0].keyword, "popular");
-    assert_eq!(json.popular_categories[0].category, "Category 1");
-    assert_eq!(json.just_updated.len(), 1);
-    assert_eq!(json.just_updated[0].name, "just_updated");
-    assert_eq!(json.new_crates.len(), 4);
+    let json: Download = anon
+        .get_json("/api/v1/crates/foo_signed/1.0.0/download")
+        .good();
+    let prefix = "http://alexcrichton-test.s3.amazonaws.com/crates/foo_signed/";
+    assert!(json.url.starts_with(prefix), "{}", json.url);
+    assert!(json.url.contains("/foo_signed-1.0.0.crate?"), "{}", json.url);
+    assert!(json.url.contains("&Expires="), "{}", json.url);
+    assert!(json.url.contains("&Signature="), "{}", json.url);
+
+    // The redirect can only be cached by the client, and not for as long as
+    // the URL is valid
+    let response = anon.get::<()>("/api/v1/crates/foo_signed/1.0.0/download");
+    response.assert_status(302);
+    assert_eq!(response.header("Cache-Control"), Some("private, max-age=150"));
+
+    // Showing the crate links to the same kind of URL
+    let json = anon.show_crate("foo_signed");
+    let url = json.versions[0].download_url.clone().unwrap();
+    assert!(url.starts_with(prefix), "{}", url);
+    assert!(url.contains("&Signature="), "{}", url);
 }
 
 #[test]
-fn download() {
-    use chrono::{Duration, Utc};
+fn download_matching_requirement() {
     let (app, anon, user) = TestApp::with_proxy().with_user();
     let user = user.as_model();
 
     app.db(|conn| {
-        CrateBuilder::new("foo_download", user.id)
-            .version(VersionBuilder::new("1.0.0"))
-            .expect_build(&conn);
+        CrateBuilder::new("foo_matching", user.id)
+            .version("1.2.0")
+            .version("1.4.0")
+            .version(VersionBuilder::new("1.5.0").yanked(true))
+            .version("2.0.0")
+            .expect_build(conn);
     });
 
-    let assert_dl_count = |name_and_version: &str, query: Option<&str>, count: i32| {
-        let url = format!("/api/v1/crates/{}/downloads", name_and_version);
-        let downloads: Downloads = if let Some(query) = query {
-            anon.get_with_query(&url, query).good()
-        } else {
-            anon.get(&url).good()
-        };
-        let total_downloads = downloads
+    let url = "/api/v1/crates/foo_matching/download";
+    anon.get_with_query::<()>(url, "req=%5E1.2").assert_status(302);
+
+    let downloads = |version: &str| {
+        let url = format!("/api/v1/crates/foo_matching/{}/downloads", version);
+        let downloads: Downloads = anon.get(&url).good();
+        downloads
             .version_downloads
             .iter()
             .map(|vd| vd.downloads)
-            .sum::<i32>();
-        assert_eq!(total_downloads, count);
+            .sum::<i32>()
     };
+    assert_eq!(downloads("1.4.0"), 1);
+    assert_eq!(downloads("1.5.0"), 0);
+    assert_eq!(downloads("2.0.0"), 0);
+
+    // JSON responses say which version was picked
+    #[derive(Deserialize)]
+    struct Download {
+        name: String,
+        version: String,
+    }
+    let json: Download = anon.get_json_with_query(url, "req=%5E1.2").good();
+    assert_eq!(json.name, "foo_matching");
+    assert_eq!(json.version, "1.4.0");
 
-    let download = |name_and_version: &str| {
-        let url = format!("/api/v1/crates/{}/download", name_and_version);
-        anon.get::<()>(&url).assert_status(302);
-        // TODO: test the with_json code path
-    };
-
-    download("foo_download/1.0.0");
-    assert_dl_count("foo_download/1.0.0", None, 1);
-    assert_dl_count("foo_download", None, 1);
-
-    download("FOO_DOWNLOAD/1.0.0");
-    assert_dl_count("FOO_DOWNLOAD/1.0.0", None, 2);
-    assert_dl_count("FOO_DOWNLOAD", None, 2);
-
-    let yesterday = (Utc::today() + Duration::days(-1)).format("%F");
-    let query = format!("before_date={}", yesterday);
-    assert_dl_count("FOO_DOWNLOAD/1.0.0", Some(&query), 0);
-    // crate/downloads always returns the last 90 days and ignores date params
-    assert_dl_count("FOO_DOWNLOAD", Some(&query), 2);
-
-    let tomorrow = (Utc::today() + Duration::days(1)).format("%F");
-    let query = format!("before_date={}", tomorrow);
-    assert_dl_count("FOO_DOWNLOAD/1.0.0", Some(&query), 2);
-    assert_dl_count("FOO_DOWNLOAD", Some(&query), 2);
+    anon.get_with_query::<()>(url, "req=%5E3").assert_not_found();
+    anon.get_with_query::<()>(url, "req=not-a-req").assert_status(400);
 }
 
 #[test]
@@ -1166,7 +3127,9 @@ fn dependencies() {
     app.db(|conn| {
         let c1 = CrateBuilder::new("foo_deps", user.id).expect_build(&conn);
         let v = VersionBuilder::new("1.0.0").expect_build(c1.id, &conn);
-        let c2 = CrateBuilder::new("bar_deps", user.id).expect_build(&conn);
+        let c2 = CrateBuilder::new("bar_deps", user.id)
+            .version("1.0.0")
+            .expect_build(&conn);
         new_dependency(&conn, &v, &c2);
     });
 
@@ -1175,8 +3138,90 @@ fn dependencies() {
         .good();
     assert_eq!(deps.dependencies[0].crate_id, "bar_deps");
 
+    let deps: Deps = anon
+        .get("/api/v1/crates/bar_deps/1.0.0/dependencies")
+        .good();
+    assert!(deps.dependencies.is_empty());
+
     anon.get::<()>("/api/v1/crates/foo_deps/1.0.2/dependencies")
-        .bad_with_status(200);
+        .assert_not_found();
+    anon.get::<()>("/api/v1/crates/foo_deps/not-a-version/dependencies")
+        .assert_not_found();
+}
+
+#[test]
+fn dependencies_include_kind() {
+    use models::DependencyKind;
+    use schema::dependencies;
+
+    let (app, anon, user) = TestApp::init().with_user();
+    let user = user.as_model();
+
+    app.db(|conn| {
+        let c1 = CrateBuilder::new("foo_dep_kinds", user.id).expect_build(&conn);
+        let v = VersionBuilder::new("1.0.0").expect_build(c1.id, &conn);
+        let normal = CrateBuilder::new("normal_dep", user.id).expect_build(&conn);
+        let build = CrateBuilder::new("build_dep", user.id).expect_build(&conn);
+        let dev = CrateBuilder::new("dev_dep", user.id).expect_build(&conn);
+        new_dependency(&conn, &v, &normal);
+        for &(krate, kind) in &[(&build, DependencyKind::Build), (&dev, DependencyKind::Dev)] {
+            let dep = new_dependency(&conn, &v, krate);
+            update(dependencies::table.find(dep.id))
+                .set(dependencies::kind.eq(kind as i32))
+                .execute(conn)
+                .unwrap();
+        }
+    });
+
+    let json: serde_json::Value = anon
+        .get("/api/v1/crates/foo_dep_kinds/1.0.0/dependencies")
+        .good();
+    let mut kinds = json["dependencies"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|dep| {
+            (
+                dep["crate_id"].as_str().unwrap(),
+                dep["kind"].as_str().unwrap(),
+            )
+        }).collect::<Vec<_>>();
+    kinds.sort();
+    assert_eq!(
+        kinds,
+        [
+            ("build_dep", "build"),
+            ("dev_dep", "dev"),
+            ("normal_dep", "normal"),
+        ]
+    );
+}
+
+#[test]
+fn dependencies_include_optional_and_target() {
+    use schema::dependencies;
+
+    let (app, anon, user) = TestApp::init().with_user();
+    let user = user.as_model();
+
+    app.db(|conn| {
+        let c1 = CrateBuilder::new("foo_dep_target", user.id).expect_build(&conn);
+        let v = VersionBuilder::new("1.0.0").expect_build(c1.id, &conn);
+        let c2 = CrateBuilder::new("bar_dep_target", user.id).expect_build(&conn);
+        let dep = new_dependency(&conn, &v, &c2);
+        update(dependencies::table.find(dep.id))
+            .set((
+                dependencies::optional.eq(true),
+                dependencies::target.eq("cfg(unix)"),
+            )).execute(conn)
+            .unwrap();
+    });
+
+    let deps: Deps = anon
+        .get("/api/v1/crates/foo_dep_target/1.0.0/dependencies")
+        .good();
+    assert!(deps.dependencies[0].optional);
+    assert_eq!(deps.dependencies[0].target, Some("cfg(unix)".into()));
 }
 
 #[test]
@@ -1287,6 +3332,29 @@ fn yank() {
     assert!(!json.version.yanked);
 }
 
+#[test]
+fn yanked_versions_are_still_listed_and_downloadable() {
+    let (app, anon, user) = TestApp::with_proxy().with_user();
+    let user = user.as_model();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo_yanked_download", user.id)
+            .version("1.0.0")
+            .version(VersionBuilder::new("1.1.0").yanked(true))
+            .expect_build(conn);
+    });
+
+    anon.get::<()>("/api/v1/crates/foo_yanked_download/1.1.0/download")
+        .assert_status(302);
+
+    let json = anon.show_crate("foo_yanked_download");
+    assert_eq!(json.versions.len(), 2);
+    assert_eq!(json.versions[0].num, "1.1.0");
+    assert!(json.versions[0].yanked);
+    assert_eq!(json.versions[1].num, "1.0.0");
+    assert!(!json.versions[1].yanked);
+}
+
 #[test]
 fn yank_not_owner() {
     let (app, _, _, token) = TestApp::init().with_token();
@@ -1295,11 +3363,9 @@ fn yank_not_owner() {
         CrateBuilder::new("foo_not", another_user.id).expect_build(conn);
     });
 
-    let json = token.yank("foo_not", "1.0.0").bad_with_status(200);
-    assert_eq!(
-        json.errors[0].detail,
-        "crate `foo_not` does not have a version `1.0.0`"
-    );
+    token
+        .delete::<()>("/api/v1/crates/foo_not/1.0.0/yank")
+        .assert_not_found();
 }
 
 #[test]
@@ -1564,6 +3630,111 @@ fn reverse_dependencies() {
     assert_eq!(deps.meta.total, 0);
 }
 
+#[test]
+fn reverse_dependencies_are_paginated() {
+    let (app, anon, user) = TestApp::init().with_user();
+    let user = user.as_model();
+
+    app.db(|conn| {
+        let c1 = CrateBuilder::new("c1", user.id).expect_build(conn);
+        for &(name, downloads) in &[("c2", 30), ("c3", 20), ("c4", 10)] {
+            CrateBuilder::new(name, user.id)
+                .downloads(downloads)
+                .version(VersionBuilder::new("1.0.0").dependency(&c1, None))
+                .version(VersionBuilder::new("1.1.0").dependency(&c1, None))
+                .expect_build(conn);
+        }
+    });
+
+    let url = "/api/v1/crates/c1/reverse_dependencies";
+    let deps: RevDeps = anon.get_with_query(url, "per_page=2").good();
+    assert_eq!(deps.meta.total, 3);
+    let mut names = deps.versions.iter().map(|v| &v.krate[..]).collect::<Vec<_>>();
+    names.sort();
+    assert_eq!(names, ["c2", "c3"]);
+
+    let deps: RevDeps = anon.get_with_query(url, "per_page=2&page=2").good();
+    assert_eq!(deps.meta.total, 3);
+    assert_eq!(deps.dependencies.len(), 1);
+    assert_eq!(deps.versions[0].krate, "c4");
+    assert_eq!(deps.versions[0].num, "1.1.0");
+}
+
+#[test]
+fn feature_usage() {
+    use schema::dependencies;
+
+    #[derive(Deserialize)]
+    struct FeatureUsage {
+        name: String,
+        dependents: i64,
+    }
+    #[derive(Deserialize)]
+    struct R {
+        features: Vec<FeatureUsage>,
+    }
+
+    let (app, anon, user) = TestApp::init().with_user();
+    let user = user.as_model();
+
+    app.db(|conn| {
+        let c1 = CrateBuilder::new("c1", user.id)
+            .version("1.0.0")
+            .expect_build(conn);
+        update(versions::table.filter(versions::crate_id.eq(c1.id)))
+            .set(versions::features.eq(json!({
+                "default": ["a"],
+                "a": [],
+                "b": [],
+                "unused": [],
+            }))).execute(conn)
+            .unwrap();
+        let c2 = CrateBuilder::new("c2", user.id)
+            .version(VersionBuilder::new("1.0.0").dependency(&c1, None))
+            .version(VersionBuilder::new("1.1.0").dependency(&c1, None))
+            .expect_build(conn);
+        let c3 = CrateBuilder::new("c3", user.id)
+            .version(VersionBuilder::new("1.0.0").dependency(&c1, None))
+            .expect_build(conn);
+        let c4 = CrateBuilder::new("c4", user.id)
+            .version(VersionBuilder::new("1.0.0").dependency(&c1, None))
+            .version("2.0.0")
+            .expect_build(conn);
+
+        let enable = |krate: &Crate, num: &str, default_features: bool, features: &[&str]| {
+            let version_id = versions::table
+                .filter(versions::crate_id.eq(krate.id))
+                .filter(versions::num.eq(num))
+                .select(versions::id)
+                .first::<i32>(conn)
+                .unwrap();
+            update(dependencies::table.filter(dependencies::version_id.eq(version_id)))
+                .set((
+                    dependencies::default_features.eq(default_features),
+                    dependencies::features.eq(features),
+                )).execute(conn)
+                .unwrap();
+        };
+        // Only the newest version of each dependent is counted
+        enable(&c2, "1.0.0", true, &["b"]);
+        enable(&c2, "1.1.0", false, &["a"]);
+        enable(&c3, "1.0.0", true, &["a", "b"]);
+        // Even if the newest version no longer depends on the crate
+        enable(&c4, "1.0.0", false, &["b"]);
+    });
+
+    let json: R = anon.get("/api/v1/crates/c1/feature_usage").good();
+    let usage = json
+        .features
+        .iter()
+        .map(|f| (&f.name[..], f.dependents))
+        .collect::<Vec<_>>();
+    assert_eq!(usage, [("a", 2), ("b", 1), ("default", 1), ("unused", 0)]);
+
+    let json: R = anon.get("/api/v1/crates/c2/feature_usage").good();
+    assert!(json.features.is_empty());
+}
+
 #[test]
 fn reverse_dependencies_when_old_version_doesnt_depend_but_new_does() {
     let (app, anon, user) = TestApp::init().with_user();
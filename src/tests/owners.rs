@@ -123,6 +123,33 @@ fn owners_can_remove_self() {
     );
 }
 
+#[test]
+fn show_includes_owner_logins_when_asked() {
+    let (app, anon, user, token) = TestApp::init().with_token();
+
+    let krate = app
+        .db(|conn| CrateBuilder::new("owners_show", user.as_model().id).expect_build(conn));
+
+    let user2 = app.db_new_user("showowner");
+    token.add_user_owner("owners_show", user2.as_model());
+    user2.accept_ownership_invitation("owners_show", krate.id);
+
+    #[derive(Deserialize)]
+    struct R {
+        owners: Option<Vec<String>>,
+    }
+
+    let json: R = anon.get("/api/v1/crates/owners_show").good();
+    assert!(json.owners.is_none());
+
+    let json: R = anon
+        .get_with_query("/api/v1/crates/owners_show", "include_owners=true")
+        .good();
+    let mut owners = json.owners.unwrap();
+    owners.sort();
+    assert_eq!(owners, vec!["foo", "showowner"]);
+}
+
 /*  Testing the crate ownership between two crates and one team.
     Given two crates, one crate owned by both a team and a user,
     one only owned by a user, check that the CrateList returned
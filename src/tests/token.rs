@@ -2,6 +2,7 @@ use std::collections::HashSet;
 
 use diesel::prelude::*;
 
+use builders::PublishBuilder;
 use models::ApiToken;
 use views::{EncodableApiTokenWithToken, EncodableMe};
 use {user::UserShowPrivateResponse, RequestHelper, TestApp};
@@ -224,6 +225,23 @@ fn revoke_token_success() {
     });
 }
 
+#[test]
+fn revoked_token_cannot_publish() {
+    let (_, _, user, token) = TestApp::init().with_token();
+
+    let _json: RevokedResponse = user
+        .delete(&format!("/api/v1/me/tokens/{}", token.as_model().id))
+        .good();
+
+    let json = token
+        .publish(PublishBuilder::new("foo_revoked"))
+        .bad_with_status(403);
+    assert_contains!(
+        json.errors[0].detail,
+        "must be logged in to perform that action"
+    );
+}
+
 #[test]
 fn token_gives_access_to_me() {
     let url = "/api/v1/me";
@@ -187,6 +187,61 @@ fn following() {
         .bad_with_status(200); // TODO: Should be 500
 }
 
+#[test]
+fn following_prereleases_is_opt_in() {
+    #[derive(Deserialize)]
+    struct R {
+        versions: Vec<EncodableVersion>,
+    }
+    #[derive(Deserialize)]
+    struct F {
+        following: bool,
+        prereleases: bool,
+    }
+
+    let (app, _, user) = TestApp::init().with_user();
+    let user_id = user.as_model().id;
+    app.db(|conn| {
+        CrateBuilder::new("foo_channels", user_id)
+            .version(VersionBuilder::new("1.0.0"))
+            .version(VersionBuilder::new("1.1.0-beta.1"))
+            .version(VersionBuilder::new("1.0.1+build-2"))
+            .expect_build(conn);
+    });
+    let nums = || {
+        let r: R = user.get("/api/v1/me/updates").good();
+        let mut nums = r.versions.into_iter().map(|v| v.num).collect::<Vec<_>>();
+        nums.sort();
+        nums
+    };
+    let following = || user.get::<F>("/api/v1/crates/foo_channels/following").good();
+
+    // Following defaults to stable versions only
+    user.put::<OkBool>("/api/v1/crates/foo_channels/follow", b"")
+        .good();
+    assert_eq!(nums(), vec!["1.0.0", "1.0.1+build-2"]);
+    assert!(following().following);
+    assert!(!following().prereleases);
+
+    user.put::<OkBool>(
+        "/api/v1/crates/foo_channels/follow",
+        br#"{"prereleases":true}"#,
+    ).good();
+    assert_eq!(nums(), vec!["1.0.0", "1.0.1+build-2", "1.1.0-beta.1"]);
+    assert!(following().prereleases);
+
+    user.put::<OkBool>(
+        "/api/v1/crates/foo_channels/follow",
+        br#"{"prereleases":false}"#,
+    ).good();
+    assert_eq!(nums(), vec!["1.0.0", "1.0.1+build-2"]);
+
+    let json = user
+        .put::<()>("/api/v1/crates/foo_channels/follow", b"not json")
+        .bad_with_status(200);
+    assert!(json.errors[0].detail.contains("invalid json request"));
+}
+
 #[test]
 fn user_total_downloads() {
     use diesel::update;
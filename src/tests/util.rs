@@ -28,10 +28,11 @@ use conduit_test::MockRequest;
 
 use builders::PublishBuilder;
 use cargo_registry::app::App;
+use cargo_registry::Config;
 use cargo_registry::middleware::current_user::AuthenticationSource;
-use models::{ApiToken, User};
+use models::{ApiToken, NewUser, User};
 
-use super::{app, record, CrateList, CrateResponse, GoodCrate, OkBool, VersionResponse};
+use super::{record, CrateList, CrateResponse, GoodCrate, OkBool, VersionResponse};
 
 struct TestAppInner {
     app: Arc<App>,
@@ -58,7 +59,15 @@ impl TestApp {
 
     /// Initialize a full application that can record and playback outgoing HTTP requests
     pub fn with_proxy() -> TestAppBuilder {
-        let (bomb, app, middle) = app();
+        Self::with_proxy_and_config(|_| {})
+    }
+
+    /// Like `with_proxy`, but the application's configuration is adjusted by `configure` first
+    pub fn with_proxy_and_config<F>(configure: F) -> TestAppBuilder
+    where
+        F: FnOnce(&mut Config),
+    {
+        let (bomb, app, middle) = ::app_with_config(configure);
         let inner = Rc::new(TestAppInner {
             app,
             _bomb: Some(bomb),
@@ -88,6 +97,23 @@ impl TestApp {
         }
     }
 
+    /// Create a new user that is configured as an admin and return a mock user session
+    ///
+    /// This method updates the database directly
+    pub fn db_new_admin_user(&self, user: &str) -> MockCookieUser {
+        let user = self.db(|conn| {
+            NewUser {
+                gh_id: ::ADMIN_GH_ID,
+                ..::new_user(user)
+            }.create_or_update(conn)
+            .unwrap()
+        });
+        MockCookieUser {
+            app: TestApp(Rc::clone(&self.0)),
+            user,
+        }
+    }
+
     /// Obtain a reference to the inner `App` value
     pub fn as_inner(&self) -> &App {
         &*self.0.app
@@ -147,6 +173,27 @@ pub trait RequestHelper {
         Response::new(self.app().0.middle.call(&mut request))
     }
 
+    /// Issue a GET request that asks for a JSON response
+    fn get_json<T>(&self, path: &str) -> Response<T>
+    where
+        for<'de> T: serde::Deserialize<'de>,
+    {
+        let mut request = self.request_builder(Method::Get, path);
+        request.header("Accept", "application/json");
+        Response::new(self.app().0.middle.call(&mut request))
+    }
+
+    /// Issue a GET request that includes query parameters and asks for a JSON response
+    fn get_json_with_query<T>(&self, path: &str, query: &str) -> Response<T>
+    where
+        for<'de> T: serde::Deserialize<'de>,
+    {
+        let mut request = self.request_builder(Method::Get, path);
+        request.with_query(query);
+        request.header("Accept", "application/json");
+        Response::new(self.app().0.middle.call(&mut request))
+    }
+
     /// Issue a PUT request
     fn put<T>(&self, path: &str, body: &[u8]) -> Response<T>
     where
@@ -157,6 +204,29 @@ pub trait RequestHelper {
         Response::new(self.app().0.middle.call(request))
     }
 
+    /// Issue a PUT request that includes extra headers
+    fn put_with_headers<T>(&self, path: &str, body: &[u8], headers: &[(&str, &str)]) -> Response<T>
+    where
+        for<'de> T: serde::Deserialize<'de>,
+    {
+        let mut builder = self.request_builder(Method::Put, path);
+        for &(name, value) in headers {
+            builder.header(name, value);
+        }
+        let request = builder.with_body(body);
+        Response::new(self.app().0.middle.call(request))
+    }
+
+    /// Issue a POST request
+    fn post<T>(&self, path: &str, body: &[u8]) -> Response<T>
+    where
+        for<'de> T: serde::Deserialize<'de>,
+    {
+        let mut builder = self.request_builder(Method::Post, path);
+        let request = builder.with_body(body);
+        Response::new(self.app().0.middle.call(request))
+    }
+
     /// Issue a DELETE request
     fn delete<T>(&self, path: &str) -> Response<T>
     where
@@ -194,6 +264,18 @@ pub trait RequestHelper {
         response.with_callback(Box::new(callback_on_good))
     }
 
+    /// Publish a crate that is expected to fail with an error that isn't
+    /// shown to the user, returning that error
+    fn publish_internal_error(&self, publish_builder: PublishBuilder) -> String {
+        let body = publish_builder.body();
+        let mut builder = self.request_builder(Method::Put, "/api/v1/crates/new");
+        let request = builder.with_body(&body);
+        match self.app().0.middle.call(request) {
+            Ok(response) => panic!("unexpected response: {:?}", response.status),
+            Err(e) => e.to_string(),
+        }
+    }
+
     /// Request the JSON used for a crate's page
     fn show_crate(&self, krate_name: &str) -> CrateResponse {
         let url = format!("/api/v1/crates/{}", krate_name);
@@ -376,6 +458,15 @@ where
         assert_eq!(status, self.response.status.0);
         self
     }
+
+    /// Returns the first value of the response header `name`
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.response
+            .headers
+            .get(name)
+            .and_then(|values| values.first())
+            .map(|value| &value[..])
+    }
 }
 
 impl Response<()> {
@@ -1,8 +1,12 @@
+use std::collections::HashMap;
+
+use semver;
 use serde_json::Value;
 
 use diesel::prelude::*;
 
 use builders::{CrateBuilder, PublishBuilder, VersionBuilder};
+use models::{NewVersion, Version};
 use schema::versions;
 use views::EncodableVersion;
 use {RequestHelper, TestApp, VersionResponse};
@@ -62,6 +66,28 @@ fn show() {
     let json: VersionResponse = anon.get(&url).good();
     assert_eq!(json.version.id, v.id);
     assert_eq!(json.version.crate_size, Some(1234));
+    // Versions that weren't published through the API have no uncompressed size
+    assert_eq!(json.version.uncompressed_size, None);
+}
+
+#[test]
+fn show_includes_download_path_and_checksum() {
+    let (app, anon, user) = TestApp::init().with_user();
+    let user = user.as_model();
+    let v = app.db(|conn| {
+        let krate = CrateBuilder::new("foo_show_cksum", user.id).expect_build(conn);
+        let v = VersionBuilder::new("1.0.0").expect_build(krate.id, conn);
+        v.record_checksum(conn, "abc123").unwrap();
+        v
+    });
+
+    let url = format!("/api/v1/versions/{}", v.id);
+    let json: VersionResponse = anon.get(&url).good();
+    assert_eq!(
+        json.version.dl_path,
+        "/api/v1/crates/foo_show_cksum/1.0.0/download"
+    );
+    assert_eq!(json.version.cksum, Some("abc123".into()));
 }
 
 #[test]
@@ -115,6 +141,7 @@ fn version_size() {
         .find(|v| v.num == "1.0.0")
         .expect("Could not find v1.0.0");
     assert_eq!(version1.crate_size, Some(35));
+    assert_eq!(version1.uncompressed_size, Some(0));
 
     let version2 = crate_json
         .versions
@@ -122,4 +149,210 @@ fn version_size() {
         .find(|v| v.num == "2.0.0")
         .expect("Could not find v2.0.0");
     assert_eq!(version2.crate_size, Some(91));
+    assert_eq!(version2.uncompressed_size, Some(1));
+}
+
+#[test]
+fn published_by() {
+    let (app, _, user) = TestApp::with_proxy().with_user();
+    let user_model = user.as_model();
+
+    // Versions that weren't published through the API have no publisher
+    app.db(|conn| {
+        CrateBuilder::new("foo_published_by", user_model.id)
+            .version("1.0.0")
+            .expect_build(conn);
+    });
+    let crate_to_publish = PublishBuilder::new("foo_published_by").version("2.0.0");
+    user.publish(crate_to_publish).good();
+
+    let crate_json = user.show_crate("foo_published_by");
+    let publisher = |num: &str| {
+        crate_json
+            .versions
+            .iter()
+            .find(|v| v.num == num)
+            .and_then(|v| v.published_by.as_ref())
+            .map(|u| (u.id, u.login.clone()))
+    };
+    assert_eq!(
+        publisher("2.0.0"),
+        Some((user_model.id, user_model.gh_login.clone()))
+    );
+    assert_eq!(publisher("1.0.0"), None);
+}
+
+#[test]
+fn duplicate_version_differing_in_build_metadata_is_rejected() {
+    let (app, _, user) = TestApp::init().with_user();
+    let user = user.as_model();
+
+    app.db(|conn| {
+        let krate = CrateBuilder::new("foo_dupe_build", user.id)
+            .version("1.0.0")
+            .expect_build(conn);
+
+        let num = semver::Version::parse("1.0.0+foo").unwrap();
+        let err = NewVersion::new(krate.id, &num, &HashMap::new(), None, None, 0, None)
+            .unwrap()
+            .save(conn, &[])
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "crate version `1.0.0+foo` is already uploaded"
+        );
+    });
+}
+
+#[test]
+fn duplicate_version_insert_violates_unique_index() {
+    use diesel::result::{DatabaseErrorKind, Error};
+
+    let (app, _, user) = TestApp::init().with_user();
+    let user = user.as_model();
+
+    app.db(|conn| {
+        let krate = CrateBuilder::new("foo_dupe_insert", user.id)
+            .version("1.0.0")
+            .expect_build(conn);
+
+        // Inserting directly skips the check done by `NewVersion::save`, the
+        // way a concurrent publish of the same version would.
+        let result = ::diesel::insert_into(versions::table)
+            .values((
+                versions::crate_id.eq(krate.id),
+                versions::num.eq("1.0.0+bar"),
+                versions::features.eq(json!({})),
+            )).execute(conn);
+        match result {
+            Err(Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {}
+            other => panic!("expected a unique violation, got {:?}", other),
+        }
+    });
+}
+
+#[test]
+fn duplicate_version_rejected_by_unique_index_is_already_uploaded() {
+    let (app, _, user) = TestApp::init().with_user();
+    let user = user.as_model();
+
+    app.db(|conn| {
+        let krate = CrateBuilder::new("foo_dupe_race", user.id)
+            .version("1.0.0")
+            .expect_build(conn);
+
+        // `insert` skips the check `save` makes first, like a publish that
+        // passed it at the same time as another one of this version
+        let num = semver::Version::parse("1.0.0+foo").unwrap();
+        let err = NewVersion::new(krate.id, &num, &HashMap::new(), None, None, 0, None)
+            .unwrap()
+            .insert(conn, &[])
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "crate version `1.0.0+foo` is already uploaded"
+        );
+    });
+}
+
+#[test]
+fn verify_checksums() {
+    #[derive(Deserialize)]
+    struct Checksum {
+        name: String,
+        version: String,
+        cksum: Option<String>,
+    }
+    #[derive(Deserialize)]
+    struct Checksums {
+        checksums: Vec<Checksum>,
+    }
+
+    let (app, anon, user) = TestApp::init().with_user();
+    let user = user.as_model();
+
+    app.db(|conn| {
+        let krate = CrateBuilder::new("foo_cksum", user.id)
+            .version("1.0.0")
+            .expect_build(conn);
+        ::diesel::update(versions::table.filter(versions::crate_id.eq(krate.id)))
+            .set(versions::checksum.eq("abc123"))
+            .execute(conn)
+            .unwrap();
+    });
+
+    let body = json!({
+        "crates": [
+            { "name": "foo-cksum", "version": "1.0.0" },
+            { "name": "foo_cksum", "version": "2.0.0" },
+            { "name": "no_such_crate", "version": "1.0.0" },
+        ]
+    });
+    let json: Checksums = anon
+        .post("/api/v1/checksums/verify", body.to_string().as_bytes())
+        .good();
+    assert_eq!(json.checksums.len(), 3);
+    assert_eq!(json.checksums[0].name, "foo-cksum");
+    assert_eq!(json.checksums[0].version, "1.0.0");
+    assert_eq!(json.checksums[0].cksum, Some("abc123".into()));
+    assert_eq!(json.checksums[1].cksum, None);
+    assert_eq!(json.checksums[2].cksum, None);
+}
+
+#[test]
+fn verify_checksums_caps_batch_size() {
+    let (_, anon) = TestApp::init().empty();
+
+    let crates = (0..101)
+        .map(|i| json!({ "name": format!("foo{}", i), "version": "1.0.0" }))
+        .collect::<Vec<_>>();
+    let body = json!({ "crates": crates });
+    let mut response = anon.post::<()>("/api/v1/checksums/verify", body.to_string().as_bytes());
+    let json = response.bad_with_status(400);
+    assert!(json.errors[0].detail.contains("cannot verify more than 100"));
+}
+
+#[test]
+fn provenance() {
+    #[derive(Deserialize)]
+    struct Provenance {
+        provenance: Value,
+    }
+
+    let (app, anon, user) = TestApp::init().with_user();
+    let user = user.as_model();
+
+    let statement = json!({
+        "_type": "https://in-toto.io/Statement/v0.1",
+        "predicateType": "https://slsa.dev/provenance/v0.2",
+    });
+    app.db(|conn| {
+        let krate = CrateBuilder::new("foo_provenance", user.id)
+            .version("1.0.0")
+            .version("2.0.0")
+            .expect_build(conn);
+        let version = versions::table
+            .filter(versions::crate_id.eq(krate.id))
+            .filter(versions::num.eq("1.0.0"))
+            .first::<Version>(conn)
+            .unwrap();
+        version.record_provenance(conn, &statement).unwrap();
+    });
+
+    let json: Provenance = anon
+        .get("/api/v1/crates/foo_provenance/1.0.0/provenance")
+        .good();
+    assert_eq!(json.provenance, statement);
+
+    let json: VersionResponse = anon.get("/api/v1/crates/foo_provenance/1.0.0").good();
+    assert_eq!(
+        json.version.links.provenance,
+        Some("/api/v1/crates/foo_provenance/1.0.0/provenance".to_string())
+    );
+
+    let json: VersionResponse = anon.get("/api/v1/crates/foo_provenance/2.0.0").good();
+    assert_eq!(json.version.links.provenance, None);
+
+    anon.get::<()>("/api/v1/crates/foo_provenance/2.0.0/provenance")
+        .assert_not_found();
 }
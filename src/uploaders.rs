@@ -1,23 +1,37 @@
+use chrono::{DateTime, Utc};
 use conduit::Request;
 use flate2::read::GzDecoder;
+use hex;
 use openssl::hash::{Hasher, MessageDigest};
 use reqwest;
 use s3;
 use semver;
 use tar;
+use toml;
 
 use util::LimitErrorReader;
 use util::{human, internal, CargoResult, ChainError, Maximums};
 
 use std::env;
 use std::fs::{self, File};
-use std::io::{Read, Write};
+use std::io::{self, Read, Write};
+use std::path::Path;
 use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
 
 use app::App;
 use middleware::app::RequestApp;
 use models::Crate;
 
+/// The longest `Uploader::upload` waits between retries in total, in
+/// milliseconds
+const MAX_UPLOAD_BACKOFF_MS: u64 = 10_000;
+
+/// The most files listed in the integrity manifest of a version. Files past
+/// this many are still checked but aren't recorded.
+const MAX_INTEGRITY_MANIFEST_FILES: usize = 1000;
+
 fn require_test_app_with_proxy() -> ! {
     panic!("No uploader is configured.  In tests, use `TestApp::with_proxy()`.");
 }
@@ -73,6 +87,26 @@ impl Uploader {
         }
     }
 
+    /// Returns a URL that can be used to download an uploaded crate's version
+    /// archive for the next `expires_in` seconds, even from a private bucket.
+    ///
+    /// Only S3 URLs are signed.
+    pub fn signed_crate_location(
+        &self,
+        crate_name: &str,
+        version: &str,
+        expires_in: i64,
+    ) -> Option<String> {
+        match *self {
+            Uploader::S3 { ref bucket, .. } => {
+                let path = Uploader::crate_path(crate_name, version);
+                let expires = Utc::now().timestamp() + expires_in;
+                Some(bucket.presigned_get_url(&path, expires))
+            }
+            Uploader::Local | Uploader::Panic => self.crate_location(crate_name, version),
+        }
+    }
+
     /// Returns the URL of an uploaded crate's version readme.
     ///
     /// The function doesn't check for the existence of the file.
@@ -109,6 +143,12 @@ impl Uploader {
 
     /// Uploads a file using the configured uploader (either `S3`, `Local` or `Panic`).
     ///
+    /// Uploads to S3 that fail with a server error or without a response are
+    /// made up to `attempts` times, waiting `backoff_ms` milliseconds before the
+    /// first retry and twice as long before each one after that, but no more
+    /// than `MAX_UPLOAD_BACKOFF_MS` in total. After the last failed attempt the
+    /// file is deleted, in case S3 stored it anyway.
+    ///
     /// It returns a a tuple containing the path of the uploaded file
     /// and its checksum.
     pub fn upload(
@@ -117,13 +157,40 @@ impl Uploader {
         path: &str,
         body: Vec<u8>,
         content_type: &str,
+        attempts: u32,
+        backoff_ms: u64,
     ) -> CargoResult<(Option<String>, Vec<u8>)> {
         let hash = hash(&body);
         match *self {
             Uploader::S3 { ref bucket, .. } => {
-                bucket
-                    .put(&client, path, body, content_type)
-                    .map_err(|e| internal(&format_args!("failed to upload to S3: {}", e)))?;
+                let mut attempt = 1;
+                let mut slept_ms = 0u64;
+                loop {
+                    let (error, retryable) =
+                        match bucket.put(&client, path, body.clone(), content_type) {
+                            Ok(ref response) if response.status().is_success() => break,
+                            Ok(mut response) => {
+                                let status = response.status();
+                                let text = response.text().unwrap_or_default();
+                                (format!("{}: {}", status, text), status.is_server_error())
+                            }
+                            Err(e) => (e.to_string(), true),
+                        };
+                    let delay_ms = backoff_ms.saturating_mul(1 << (attempt - 1).min(16));
+                    let out_of_time = slept_ms.saturating_add(delay_ms) > MAX_UPLOAD_BACKOFF_MS;
+                    if !retryable || attempt >= attempts || out_of_time {
+                        // A request that failed without a response may still
+                        // have been stored, so this is only best-effort
+                        let _ = bucket.delete(client, path);
+                        return Err(internal(&format_args!(
+                            "failed to upload to S3 after {} attempt(s): {}",
+                            attempt, error
+                        )));
+                    }
+                    thread::sleep(Duration::from_millis(delay_ms));
+                    slept_ms += delay_ms;
+                    attempt += 1;
+                }
                 Ok((Some(String::from(path)), hash))
             }
             Uploader::Local => {
@@ -139,7 +206,8 @@ impl Uploader {
     }
 
     /// Uploads a crate and its readme. Returns the checksum of the uploaded crate
-    /// file, and bombs for the uploaded crate and the uploaded readme.
+    /// file, the files it contains, their total size, and bombs for the uploaded
+    /// crate and the uploaded readme.
     pub fn upload_crate(
         &self,
         req: &mut dyn Request,
@@ -147,14 +215,33 @@ impl Uploader {
         readme: Option<String>,
         maximums: Maximums,
         vers: &semver::Version,
-    ) -> CargoResult<(Vec<u8>, Bomb, Bomb)> {
+    ) -> CargoResult<(Vec<u8>, Vec<IntegrityFile>, u64, Bomb, Bomb)> {
         let app = Arc::clone(req.app());
-        let (crate_path, checksum) = {
+        let (crate_path, checksum, files, uncompressed_size) = {
             let path = Uploader::crate_path(&krate.name, &vers.to_string());
             let mut body = Vec::new();
-            LimitErrorReader::new(req.body(), maximums.max_upload_size).read_to_end(&mut body)?;
-            verify_tarball(krate, vers, &body, maximums.max_unpack_size)?;
-            self.upload(&app.http_client()?, &path, body, "application/x-tar")?
+            let read =
+                LimitErrorReader::new(req.body(), maximums.max_upload_size).read_to_end(&mut body);
+            if let Err(e) = read {
+                if body.len() as u64 >= maximums.max_upload_size {
+                    return Err(human(&format_args!(
+                        "max upload size is: {}",
+                        maximums.max_upload_size
+                    )));
+                }
+                return Err(e.into());
+            }
+            let (files, uncompressed_size) =
+                verify_tarball(krate, vers, &body, maximums.max_unpack_size)?;
+            let (crate_path, checksum) = self.upload(
+                &app.http_client()?,
+                &path,
+                body,
+                "application/x-tar",
+                app.config.s3_upload_attempts,
+                app.config.s3_upload_backoff_ms,
+            )?;
+            (crate_path, checksum, files, uncompressed_size)
         };
         // We create the bomb for the crate file before uploading the readme so that if the
         // readme upload fails, the uploaded crate file is automatically deleted.
@@ -169,12 +256,16 @@ impl Uploader {
                 &path,
                 rendered.into_bytes(),
                 "text/html",
+                app.config.s3_upload_attempts,
+                app.config.s3_upload_backoff_ms,
             )?
         } else {
             (None, vec![])
         };
         Ok((
             checksum,
+            files,
+            uncompressed_size,
             crate_bomb,
             Bomb {
                 app: Arc::clone(&app),
@@ -183,8 +274,50 @@ impl Uploader {
         ))
     }
 
+    /// Lists every crate file uploaded to S3, along with when each was last
+    /// modified. Files uploaded locally can't be listed.
+    pub fn crate_files(&self, app: &Arc<App>) -> CargoResult<Vec<(String, DateTime<Utc>)>> {
+        match *self {
+            Uploader::S3 { ref bucket, .. } => {
+                let client = app.http_client()?;
+                let mut files = Vec::new();
+                let mut continuation_token = None;
+                loop {
+                    // Matches the prefix of `crate_path`
+                    let page = bucket.list(
+                        &client,
+                        "crates/",
+                        continuation_token.as_ref().map(String::as_str),
+                    )?;
+                    files.extend(page.objects);
+                    match page.next_continuation_token {
+                        Some(token) => continuation_token = Some(token),
+                        None => return Ok(files),
+                    }
+                }
+            }
+            Uploader::Local => Err(human("only crate files uploaded to S3 can be listed")),
+            Uploader::Panic => require_test_app_with_proxy(),
+        }
+    }
+
+    /// Splits a path returned by `crate_path` back into the crate's name and
+    /// the version. Returns `None` for any other path.
+    pub fn parse_crate_path(path: &str) -> Option<(&str, &str)> {
+        if !path.starts_with("crates/") || !path.ends_with(".crate") {
+            return None;
+        }
+        let path = &path["crates/".len()..path.len() - ".crate".len()];
+        let slash = path.find('/')?;
+        let (name, file) = (&path[..slash], &path[slash + 1..]);
+        if !file.starts_with(name) || !file[name.len()..].starts_with('-') {
+            return None;
+        }
+        Some((name, &file[name.len() + 1..]))
+    }
+
     /// Deletes an uploaded file.
-    fn delete(&self, app: &Arc<App>, path: &str) -> CargoResult<()> {
+    pub fn delete(&self, app: &Arc<App>, path: &str) -> CargoResult<()> {
         match *self {
             Uploader::S3 { ref bucket, .. } => {
                 bucket.delete(&app.http_client()?, path)?;
@@ -216,12 +349,25 @@ impl Drop for Bomb {
     }
 }
 
+/// A file contained in an uploaded `.crate` file
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct IntegrityFile {
+    /// The path of the file, relative to the `$name-$vers/` directory
+    pub path: String,
+    pub size: u64,
+    /// The hex encoded SHA256 checksum of the file
+    pub sha256: String,
+}
+
+/// Checks the contents of an uploaded tarball, returning the regular files it
+/// contains (up to `MAX_INTEGRITY_MANIFEST_FILES` of them) and the total size
+/// of all of them.
 fn verify_tarball(
     krate: &Crate,
     vers: &semver::Version,
     tarball: &[u8],
     max_unpack: u64,
-) -> CargoResult<()> {
+) -> CargoResult<(Vec<IntegrityFile>, u64)> {
     // All our data is currently encoded with gzip
     let decoder = GzDecoder::new(tarball);
 
@@ -232,8 +378,11 @@ fn verify_tarball(
     // Use this I/O object now to take a peek inside
     let mut archive = tar::Archive::new(decoder);
     let prefix = format!("{}-{}", krate.name, vers);
+    let manifest_path = Path::new(&prefix).join("Cargo.toml");
+    let mut files = Vec::new();
+    let mut total_size = 0;
     for entry in archive.entries()? {
-        let entry = entry.chain_error(|| {
+        let mut entry = entry.chain_error(|| {
             human("uploaded tarball is malformed or too large when decompressed")
         })?;
 
@@ -255,11 +404,83 @@ fn verify_tarball(
         if entry_type.is_hard_link() || entry_type.is_symlink() {
             return Err(human("invalid tarball uploaded"));
         }
+
+        if !entry_type.is_file() {
+            continue;
+        }
+        let path = entry.path()?.into_owned();
+        let mut hasher = Hasher::new(MessageDigest::sha256()).unwrap();
+        let size = if path == manifest_path {
+            // The manifest is the only file that's read into memory
+            let mut contents = Vec::new();
+            entry.read_to_end(&mut contents).chain_error(|| {
+                human("uploaded tarball is malformed or too large when decompressed")
+            })?;
+            verify_manifest(&contents[..], krate, vers)?;
+            hasher.update(&contents).unwrap();
+            contents.len() as u64
+        } else {
+            io::copy(&mut entry, &mut hasher).chain_error(|| {
+                human("uploaded tarball is malformed or too large when decompressed")
+            })?
+        };
+        total_size += size;
+
+        if files.len() < MAX_INTEGRITY_MANIFEST_FILES {
+            let relative_path = path
+                .strip_prefix(&prefix)
+                .map_err(|_| human("invalid tarball uploaded"))?;
+            files.push(IntegrityFile {
+                path: relative_path.to_string_lossy().into_owned(),
+                size,
+                sha256: hex::encode(hasher.finish2().unwrap().to_vec()),
+            });
+        }
+    }
+    Ok((files, total_size))
+}
+
+/// Checks that the `Cargo.toml` packaged in a tarball describes the crate and
+/// version it's being published as.
+fn verify_manifest<R: Read>(
+    mut manifest: R,
+    krate: &Crate,
+    vers: &semver::Version,
+) -> CargoResult<()> {
+    let mut contents = String::new();
+    manifest.read_to_string(&mut contents).chain_error(|| {
+        human("uploaded tarball is malformed or too large when decompressed")
+    })?;
+    let manifest = contents
+        .parse::<toml::Value>()
+        .map_err(|_| human("the Cargo.toml in the uploaded tarball is not valid TOML"))?;
+
+    // Manifests written by older versions of Cargo call this section `project`
+    let package = manifest.get("package").or_else(|| manifest.get("project"));
+    let name = package
+        .and_then(|p| p.get("name"))
+        .and_then(toml::Value::as_str);
+    let version = package
+        .and_then(|p| p.get("version"))
+        .and_then(toml::Value::as_str);
+    let version_matches = version
+        .and_then(|v| semver::Version::parse(v).ok())
+        .map_or(false, |v| v == *vers);
+
+    if name != Some(&krate.name[..]) || !version_matches {
+        return Err(human(&format_args!(
+            "the Cargo.toml in the uploaded tarball is for `{} {}`, \
+             but `{} {}` is being published",
+            name.unwrap_or("<unknown>"),
+            version.unwrap_or("<unknown>"),
+            krate.name,
+            vers
+        )));
     }
     Ok(())
 }
 
-fn hash(data: &[u8]) -> Vec<u8> {
+pub fn hash(data: &[u8]) -> Vec<u8> {
     let mut hasher = Hasher::new(MessageDigest::sha256()).unwrap();
     hasher.update(data).unwrap();
     hasher.finish2().unwrap().to_vec()
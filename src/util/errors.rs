@@ -247,6 +247,33 @@ impl fmt::Display for Unauthorized {
     }
 }
 
+/// Used when the registry can only be read by its users and nobody is
+/// logged in.
+#[derive(Debug, Clone, Copy)]
+pub struct LoginRequired;
+
+impl CargoError for LoginRequired {
+    fn description(&self) -> &str {
+        "login required"
+    }
+
+    fn response(&self) -> Option<Response> {
+        let mut response = json_response(&Bad {
+            errors: vec![StringError {
+                detail: "must be logged in to read this registry".to_string(),
+            }],
+        });
+        response.status = (401, "Unauthorized");
+        Some(response)
+    }
+}
+
+impl fmt::Display for LoginRequired {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        "must be logged in to read this registry".fmt(f)
+    }
+}
+
 #[derive(Debug)]
 struct BadRequest(String);
 
@@ -272,6 +299,81 @@ impl fmt::Display for BadRequest {
     }
 }
 
+#[derive(Debug)]
+struct Forbidden(String);
+
+impl CargoError for Forbidden {
+    fn description(&self) -> &str {
+        self.0.as_ref()
+    }
+
+    fn response(&self) -> Option<Response> {
+        let mut response = json_response(&Bad {
+            errors: vec![StringError {
+                detail: self.0.clone(),
+            }],
+        });
+        response.status = (403, "Forbidden");
+        Some(response)
+    }
+}
+
+impl fmt::Display for Forbidden {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[derive(Debug)]
+struct TooManyRequests(String);
+
+impl CargoError for TooManyRequests {
+    fn description(&self) -> &str {
+        self.0.as_ref()
+    }
+
+    fn response(&self) -> Option<Response> {
+        let mut response = json_response(&Bad {
+            errors: vec![StringError {
+                detail: self.0.clone(),
+            }],
+        });
+        response.status = (429, "Too Many Requests");
+        Some(response)
+    }
+}
+
+impl fmt::Display for TooManyRequests {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[derive(Debug)]
+struct UnprocessableEntity(String);
+
+impl CargoError for UnprocessableEntity {
+    fn description(&self) -> &str {
+        self.0.as_ref()
+    }
+
+    fn response(&self) -> Option<Response> {
+        let mut response = json_response(&Bad {
+            errors: vec![StringError {
+                detail: self.0.clone(),
+            }],
+        });
+        response.status = (422, "Unprocessable Entity");
+        Some(response)
+    }
+}
+
+impl fmt::Display for UnprocessableEntity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
 pub fn internal_error(error: &str, detail: &str) -> Box<dyn CargoError> {
     Box::new(ConcreteCargoError {
         description: error.to_string(),
@@ -310,6 +412,21 @@ pub fn bad_request<S: ToString + ?Sized>(error: &S) -> Box<dyn CargoError> {
     Box::new(BadRequest(error.to_string()))
 }
 
+/// Used when the user is logged in but isn't allowed to perform the action.
+pub fn forbidden<S: ToString + ?Sized>(error: &S) -> Box<dyn CargoError> {
+    Box::new(Forbidden(error.to_string()))
+}
+
+/// Used when the user has made too many requests of this kind recently.
+pub fn too_many_requests<S: ToString + ?Sized>(error: &S) -> Box<dyn CargoError> {
+    Box::new(TooManyRequests(error.to_string()))
+}
+
+/// Used when the request is well formed but conflicts with an earlier one.
+pub fn unprocessable_entity<S: ToString + ?Sized>(error: &S) -> Box<dyn CargoError> {
+    Box::new(UnprocessableEntity(error.to_string()))
+}
+
 pub fn std_error(e: Box<dyn CargoError>) -> Box<dyn Error + Send> {
     #[derive(Debug)]
     struct E(Box<dyn CargoError>);
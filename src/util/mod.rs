@@ -7,7 +7,10 @@ use serde_json;
 
 use conduit::Response;
 
-pub use self::errors::{bad_request, human, internal, internal_error, CargoError, CargoResult};
+pub use self::errors::{
+    bad_request, forbidden, human, internal, internal_error, too_many_requests,
+    unprocessable_entity, CargoError, CargoResult,
+};
 pub use self::errors::{std_error, ChainError};
 pub use self::io_util::{read_fill, read_le_u32, LimitErrorReader};
 pub use self::request_helpers::*;
@@ -5,6 +5,7 @@
 use std::collections::HashMap;
 
 use semver;
+use serde_json;
 use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 
 use models::krate::MAX_NAME_LENGTH;
@@ -33,6 +34,13 @@ pub struct NewCrate {
     pub badges: Option<HashMap<String, HashMap<String, String>>>,
     #[serde(default)]
     pub links: Option<String>,
+    /// A provenance statement about how this version was built. It is stored
+    /// as-is and isn't verified.
+    #[serde(default)]
+    pub provenance: Option<serde_json::Value>,
+    /// What changed in this version, as plain text
+    #[serde(default)]
+    pub changelog: Option<String>,
 }
 
 #[derive(PartialEq, Eq, Hash, Serialize, Debug, Deref)]
@@ -139,13 +147,66 @@ impl<'de> Deserialize<'de> for CrateVersion {
             Ok(v) => Ok(CrateVersion(v)),
             Err(..) => {
                 let value = de::Unexpected::Str(&s);
-                let expected = "a valid semver";
-                Err(de::Error::invalid_value(value, &expected))
+                let expected = match suggest_version(&s) {
+                    Some((problem, suggestion)) => format!(
+                        "a valid semver ({}: did you mean `{}`?)",
+                        problem, suggestion
+                    ),
+                    None => "a valid semver".to_string(),
+                };
+                Err(de::Error::invalid_value(value, &expected.as_ref()))
             }
         }
     }
 }
 
+/// Looks for common mistakes in a version that isn't valid semver, returning
+/// a description of the problem along with the corrected version if fixing
+/// those mistakes makes it valid.
+fn suggest_version(version: &str) -> Option<(String, String)> {
+    let mut problems = Vec::new();
+
+    let mut candidate = version.trim();
+    if candidate.len() != version.len() {
+        problems.push("surrounding whitespace");
+    }
+    if candidate.starts_with('v') || candidate.starts_with('V') {
+        problems.push("leading `v`");
+        candidate = &candidate[1..];
+    } else if candidate.starts_with('=') {
+        problems.push("leading `=`");
+        candidate = &candidate[1..];
+    }
+
+    // Only the `major.minor.patch` part can be padded, any pre-release or
+    // build metadata is kept as is
+    let split = candidate
+        .find(|c: char| c == '-' || c == '+')
+        .unwrap_or(candidate.len());
+    let (core, rest) = candidate.split_at(split);
+    let components = core.split('.').collect::<Vec<_>>();
+    let all_numeric = components
+        .iter()
+        .all(|c| !c.is_empty() && c.chars().all(|c| c.is_ascii_digit()));
+    let mut core = core.to_string();
+    if all_numeric && components.len() < 3 {
+        problems.push(if components.len() == 1 {
+            "missing minor and patch components"
+        } else {
+            "missing patch component"
+        });
+        for _ in components.len()..3 {
+            core.push_str(".0");
+        }
+    }
+
+    let suggestion = core + rest;
+    if problems.is_empty() || semver::Version::parse(&suggestion).is_err() {
+        return None;
+    }
+    Some((problems.join(", "), suggestion))
+}
+
 impl<'de> Deserialize<'de> for CrateVersionReq {
     fn deserialize<D: Deserializer<'de>>(d: D) -> Result<CrateVersionReq, D::Error> {
         let s = String::deserialize(d)?;
@@ -86,6 +86,8 @@ pub struct EncodableKeyword {
 pub struct EncodableCrate {
     pub id: String,
     pub name: String,
+    /// The canonical form of `name`, suitable for building stable URLs
+    pub slug: String,
     #[serde(with = "rfc3339")]
     pub updated_at: NaiveDateTime,
     pub versions: Option<Vec<i32>>,
@@ -105,6 +107,14 @@ pub struct EncodableCrate {
     pub repository: Option<String>,
     pub links: EncodableCrateLinks,
     pub exact_match: bool,
+    /// Whether the crate was created recently, only computed for crate listings
+    pub is_new: Option<bool>,
+    /// Whether the newest version has no dependencies other than
+    /// dev-dependencies, only computed when a single crate is shown
+    pub has_no_dependencies: Option<bool>,
+    /// Registry specific key-value pairs, only shown to owners and admins
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub custom_metadata: Option<HashMap<String, String>>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -201,6 +211,23 @@ pub struct EncodableVersion {
     pub license: Option<String>,
     pub links: EncodableVersionLinks,
     pub crate_size: Option<i32>,
+    /// The total size of the files in the `.crate` file once unpacked. Not
+    /// known for versions published before it was recorded.
+    pub uncompressed_size: Option<i64>,
+    /// The hex encoded SHA256 checksum of the `.crate` file at `dl_path`
+    pub cksum: Option<String>,
+    /// Where the `.crate` file is stored. Unlike `dl_path`, fetching it
+    /// doesn't count as a download.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub download_url: Option<String>,
+    /// The start of the changelog published with this version, if there was
+    /// one. Only included where a single crate's versions are shown.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub changelog_excerpt: Option<String>,
+    /// The user who published this version. Only included where a single
+    /// crate's versions are shown.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub published_by: Option<EncodablePublicUser>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -208,6 +235,11 @@ pub struct EncodableVersionLinks {
     pub dependencies: String,
     pub version_downloads: String,
     pub authors: String,
+    /// Only included for versions published with a provenance statement
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub provenance: Option<String>,
+    pub integrity: String,
+    pub changelog: String,
 }
 
 // TODO: Prefix many of these with `Encodable` then clean up the reexports
@@ -293,8 +325,16 @@ mod tests {
                 dependencies: "".to_string(),
                 version_downloads: "".to_string(),
                 authors: "".to_string(),
+                provenance: None,
+                integrity: "".to_string(),
+                changelog: "".to_string(),
             },
             crate_size: Some(1234),
+            uncompressed_size: Some(5678),
+            cksum: None,
+            download_url: None,
+            changelog_excerpt: None,
+            published_by: None,
         };
         let json = serde_json::to_string(&ver).unwrap();
         assert!(
@@ -314,6 +354,7 @@ mod tests {
         let crt = EncodableCrate {
             id: "".to_string(),
             name: "".to_string(),
+            slug: "".to_string(),
             updated_at: NaiveDate::from_ymd(2017, 1, 6).and_hms(14, 23, 11),
             versions: None,
             keywords: None,
@@ -336,6 +377,9 @@ mod tests {
                 reverse_dependencies: "".to_string(),
             },
             exact_match: false,
+            is_new: None,
+            has_no_dependencies: None,
+            custom_metadata: None,
         };
         let json = serde_json::to_string(&crt).unwrap();
         assert!(